@@ -0,0 +1,464 @@
+use crate::auth::Principal;
+use crate::error::NotionResult;
+use crate::safety;
+use crate::workspaces::{RequestParams, RequestedWorkspace};
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage};
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cmp::Reverse;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+pub const DEFAULT_QUERY_LIMIT: usize = 100;
+// Longer string arguments (file contents, long texts) are cut in the log
+const MAX_LOGGED_STRING: usize = 1000;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+// Request IDs Notion returned for the writes of one tool call
+pub type RequestIds = Arc<Mutex<Vec<String>>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    // Unix seconds
+    pub timestamp: u64,
+    // API key name or token subject; none when authentication is off
+    pub caller: Option<String>,
+    pub tool: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    pub targets: Vec<String>,
+    pub params: Value,
+    pub notion_request_ids: Vec<String>,
+    // ok, error, dry_run or confirmation_required
+    pub outcome: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// Filters of `query_audit_log`; every field is optional
+#[derive(Debug, Default)]
+pub struct AuditQuery {
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub caller: Option<String>,
+    pub tool: Option<String>,
+    pub target: Option<String>,
+    pub outcome: Option<String>,
+    pub limit: usize,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.until.is_none_or(|until| entry.timestamp <= until)
+            && self.caller.as_ref().is_none_or(|caller| entry.caller.as_ref() == Some(caller))
+            && self.tool.as_ref().is_none_or(|tool| &entry.tool == tool)
+            && self.target.as_ref().is_none_or(|target| {
+                let target = target.replace('-', "");
+                entry.targets.iter().any(|t| t.replace('-', "") == target)
+            })
+            && self.outcome.as_ref().is_none_or(|outcome| &entry.outcome == outcome)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// `YYYY-MM-DD` of a unix day number (UTC)
+fn format_day(days: u64) -> String {
+    // Civil-from-days, valid for any date after 1970
+    let z = days as i64 + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// Append-only JSON Lines log of every mutating tool call. The file is
+// rotated when it reaches the size limit or on the first write of a new day.
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    max_bytes: u64,
+    // Serializes rotation and appends
+    lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf, max_bytes: u64) -> Self {
+        Self { path, max_bytes, lock: Mutex::new(()) }
+    }
+
+    fn stem(&self) -> String {
+        self.path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "audit".to_string())
+    }
+
+    fn dir(&self) -> &Path {
+        self.path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."))
+    }
+
+    // Move the current file aside as `<stem>-<day>[-n].jsonl`
+    fn rotate_if_needed(&self, timestamp: u64) -> NotionResult<()> {
+        let Ok(metadata) = fs::metadata(&self.path) else {
+            return Ok(());
+        };
+        let written = metadata.modified().ok()
+            .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or(timestamp);
+        let new_day = written / SECONDS_PER_DAY != timestamp / SECONDS_PER_DAY;
+        if !new_day && metadata.len() < self.max_bytes {
+            return Ok(());
+        }
+
+        let day = format_day(written / SECONDS_PER_DAY);
+        let mut rotated = self.dir().join(format!("{}-{}.jsonl", self.stem(), day));
+        let mut n = 1;
+        while rotated.exists() {
+            rotated = self.dir().join(format!("{}-{}-{}.jsonl", self.stem(), day, n));
+            n += 1;
+        }
+        fs::rename(&self.path, rotated)?;
+        Ok(())
+    }
+
+    pub fn append(&self, entry: &AuditEntry) -> NotionResult<()> {
+        let _guard = self.lock.lock().unwrap();
+        self.rotate_if_needed(entry.timestamp)?;
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    // The current file and its rotated predecessors, newest first
+    fn files(&self) -> NotionResult<Vec<PathBuf>> {
+        let prefix = format!("{}-", self.stem());
+        let mut rotated: Vec<(SystemTime, PathBuf)> = fs::read_dir(self.dir())?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                name.starts_with(&prefix) && name.ends_with(".jsonl")
+            })
+            .filter_map(|path| Some((fs::metadata(&path).ok()?.modified().ok()?, path)))
+            .collect();
+        rotated.sort_by_key(|(modified, _)| Reverse(*modified));
+
+        let mut files = vec![self.path.clone()];
+        files.extend(rotated.into_iter().map(|(_, path)| path));
+        Ok(files)
+    }
+
+    // Matching entries, newest first
+    pub fn query(&self, query: &AuditQuery) -> NotionResult<Vec<AuditEntry>> {
+        let _guard = self.lock.lock().unwrap();
+        let mut found = Vec::new();
+        for path in self.files()? {
+            let Ok(file) = File::open(&path) else {
+                continue;
+            };
+            let mut entries: Vec<AuditEntry> = BufReader::new(file).lines()
+                .map_while(Result::ok)
+                .filter_map(|line| serde_json::from_str(&line).ok())
+                .filter(|entry| query.matches(entry))
+                .collect();
+            entries.reverse();
+            found.extend(entries);
+            if found.len() >= query.limit {
+                break;
+            }
+        }
+        found.sort_by_key(|entry| Reverse(entry.timestamp));
+        found.truncate(query.limit);
+        Ok(found)
+    }
+}
+
+fn is_secret(key: &str) -> bool {
+    let key = key.to_lowercase();
+    ["token", "secret", "password", "api_key", "apikey", "authorization", "credential"]
+        .iter()
+        .any(|word| key.contains(word))
+}
+
+// Arguments as logged: secrets replaced and long strings shortened
+fn redact(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(map.iter()
+            .map(|(key, value)| {
+                let value = if is_secret(key) { Value::String("[redacted]".to_string()) } else { redact(value) };
+                (key.clone(), value)
+            })
+            .collect::<Map<String, Value>>()),
+        Value::Array(items) => Value::Array(items.iter().map(redact).collect()),
+        Value::String(s) if s.len() > MAX_LOGGED_STRING => {
+            let cut = (0..=MAX_LOGGED_STRING).rev().find(|i| s.is_char_boundary(*i)).unwrap_or(0);
+            Value::String(format!("{}… ({} bytes)", &s[..cut], s.len()))
+        }
+        other => other.clone(),
+    }
+}
+
+// IDs of the objects a call touches: `*_id` arguments, the parent, and
+// the object returned by the tool
+fn targets(params: &Value, response: &Value) -> Vec<String> {
+    let mut ids: Vec<String> = params.as_object()
+        .map(|args| args.iter()
+            .filter(|(key, _)| key.ends_with("_id"))
+            .filter_map(|(_, value)| value.as_str().map(|s| s.to_string()))
+            .collect())
+        .unwrap_or_default();
+    ids.extend(params["parent"]["id"].as_str().map(|s| s.to_string()));
+    for key in ["id", "page_id"] {
+        if let Some(id) = response[key].as_str() {
+            if !ids.iter().any(|known| known.replace('-', "") == id.replace('-', "")) {
+                ids.push(id.to_string());
+            }
+        }
+    }
+    ids
+}
+
+fn outcome(response: &Value) -> (String, Option<String>) {
+    if let Some(error) = response["error"].as_str() {
+        return ("error".to_string(), Some(error.to_string()));
+    }
+    if response["confirmation_required"] == true {
+        return ("confirmation_required".to_string(), None);
+    }
+    if response["dry_run"] == true {
+        return ("dry_run".to_string(), None);
+    }
+    ("ok".to_string(), None)
+}
+
+// Record every call to a tool that changes Notion. Runs after the workspace
+// is selected, so the arguments are already buffered.
+pub async fn record(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let log = req.app_data::<web::Data<Option<Arc<AuditLog>>>>().and_then(|log| log.as_ref().clone());
+    let Some(log) = log.filter(|_| safety::is_mutating(req.path())) else {
+        return next.call(req).await.map(|res| res.map_into_boxed_body());
+    };
+
+    let request_ids: RequestIds = Arc::default();
+    req.extensions_mut().insert(request_ids.clone());
+    let caller = req.extensions().get::<Principal>().map(|p| p.name.clone());
+    let workspace = req.extensions().get::<RequestedWorkspace>().map(|w| w.0.clone());
+    let params = req.extensions().get::<RequestParams>().map(|p| p.0.clone()).unwrap_or_default();
+    let tool = req.path().trim_start_matches("/api/").to_string();
+
+    let (request, response) = next.call(req).await?.into_parts();
+    let status = response.status();
+    let (response, body) = response.into_parts();
+    let bytes = body::to_bytes(body).await
+        .map_err(|e| format!("Could not read the response: {}", e.into()));
+    let result: Value = bytes.as_ref().ok()
+        .and_then(|bytes| serde_json::from_slice(bytes).ok())
+        .unwrap_or_default();
+
+    let (mut outcome, mut error_message) = outcome(&result);
+    if let Err(e) = &bytes {
+        outcome = "error".to_string();
+        error_message = Some(e.clone());
+    } else if !status.is_success() && error_message.is_none() {
+        outcome = "error".to_string();
+        error_message = Some(format!("HTTP {}", status));
+    }
+    let entry = AuditEntry {
+        timestamp: now(),
+        caller,
+        tool,
+        workspace,
+        targets: targets(&params, &result),
+        params: redact(&params),
+        notion_request_ids: std::mem::take(&mut *request_ids.lock().unwrap()),
+        outcome,
+        error: error_message,
+    };
+    if let Err(e) = log.append(&entry) {
+        error!("Could not write the audit log: {}", e);
+    }
+
+    // The call already ran, so it is logged even when its response is lost
+    let bytes = bytes.map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(ServiceResponse::new(request, response.set_body(BoxBody::new(bytes))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Scope;
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{App, HttpRequest, HttpResponse};
+    use serde_json::json;
+
+    const PAGE: &str = "1a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d";
+
+    fn log_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("notion-mcp-audit-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn entry(timestamp: u64, caller: &str, tool: &str, target: &str, outcome: &str) -> AuditEntry {
+        AuditEntry {
+            timestamp,
+            caller: Some(caller.to_string()),
+            tool: tool.to_string(),
+            workspace: None,
+            targets: vec![target.to_string()],
+            params: Value::Null,
+            notion_request_ids: Vec::new(),
+            outcome: outcome.to_string(),
+            error: None,
+        }
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn formats_days() {
+        assert_eq!(format_day(0), "1970-01-01");
+        assert_eq!(format_day(19_782), "2024-02-29");
+        assert_eq!(format_day(20_000), "2024-10-04");
+    }
+
+    #[test]
+    fn rotates_when_the_file_is_full() {
+        let dir = log_dir("size");
+        let log = AuditLog::new(dir.join("audit.jsonl"), 100);
+        let at = now();
+        for _ in 0..3 {
+            log.append(&entry(at, "key:ops", "update_page", PAGE, "ok")).unwrap();
+        }
+        let day = format_day(at / SECONDS_PER_DAY);
+        assert_eq!(file_names(&dir), vec![
+            format!("audit-{}-1.jsonl", day),
+            format!("audit-{}.jsonl", day),
+            "audit.jsonl".to_string(),
+        ]);
+        assert_eq!(log.query(&AuditQuery { limit: 10, ..Default::default() }).unwrap().len(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotates_on_the_first_write_of_a_day() {
+        let dir = log_dir("day");
+        let log = AuditLog::new(dir.join("audit.jsonl"), DEFAULT_MAX_BYTES);
+        let today = now();
+        log.append(&entry(today, "key:ops", "update_page", PAGE, "ok")).unwrap();
+        log.append(&entry(today, "key:ops", "archive_page", PAGE, "ok")).unwrap();
+        assert_eq!(file_names(&dir), vec!["audit.jsonl"]);
+
+        log.append(&entry(today + SECONDS_PER_DAY, "key:ops", "move_page", PAGE, "ok")).unwrap();
+        let rotated = format!("audit-{}.jsonl", format_day(today / SECONDS_PER_DAY));
+        assert_eq!(file_names(&dir), vec![rotated.clone(), "audit.jsonl".to_string()]);
+        assert_eq!(fs::read_to_string(dir.join(rotated)).unwrap().lines().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn filters_entries_newest_first() {
+        let dir = log_dir("query");
+        let log = AuditLog::new(dir.join("audit.jsonl"), 400);
+        let other = "2a2b3c4d5e6f4a1b8c2d3e4f5a6b7c8d";
+        let at = now();
+        log.append(&entry(at, "key:site", "create_page", other, "ok")).unwrap();
+        log.append(&entry(at + 1, "key:ops", "update_page", PAGE, "error")).unwrap();
+        log.append(&entry(at + 2, "key:ops", "update_page", other, "ok")).unwrap();
+        log.append(&entry(at + 3, "key:site", "archive_page", PAGE, "dry_run")).unwrap();
+        assert!(file_names(&dir).len() > 1, "the entries should span rotated files");
+
+        let tools = |query: AuditQuery| -> Vec<String> {
+            log.query(&query).unwrap().into_iter().map(|e| e.tool).collect()
+        };
+        assert_eq!(tools(AuditQuery { limit: 10, ..Default::default() }), vec!["archive_page", "update_page", "update_page", "create_page"]);
+        assert_eq!(tools(AuditQuery { limit: 2, ..Default::default() }), vec!["archive_page", "update_page"]);
+        assert_eq!(tools(AuditQuery { caller: Some("key:site".to_string()), limit: 10, ..Default::default() }), vec!["archive_page", "create_page"]);
+        assert_eq!(tools(AuditQuery { tool: Some("update_page".to_string()), outcome: Some("ok".to_string()), limit: 10, ..Default::default() }), vec!["update_page"]);
+        // Targets match with or without dashes
+        assert_eq!(tools(AuditQuery { target: Some(PAGE.replace('-', "")), limit: 10, ..Default::default() }), vec!["archive_page", "update_page"]);
+        assert_eq!(tools(AuditQuery { since: Some(at + 1), until: Some(at + 2), limit: 10, ..Default::default() }), vec!["update_page", "update_page"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Stands in for the earlier middleware and the handler's Notion calls
+    async fn authenticated(req: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+        req.extensions_mut().insert(Principal { name: "key:ops".to_string(), scope: Scope::Write });
+        req.extensions_mut().insert(RequestParams(json!({ "page_id": PAGE, "parent": { "id": "p1" }, "api_token": "s3cr3t", "title": "x".repeat(2000) })));
+        next.call(req).await
+    }
+
+    async fn tool(req: HttpRequest) -> HttpResponse {
+        if let Some(ids) = req.extensions().get::<RequestIds>() {
+            ids.lock().unwrap().push("req-1".to_string());
+        }
+        match req.path() {
+            "/api/archive_page" => HttpResponse::Ok().json(json!({ "error": "Page is locked" })),
+            _ => HttpResponse::Ok().json(json!({ "id": "3a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d", "page_id": PAGE })),
+        }
+    }
+
+    #[actix_web::test]
+    async fn records_mutating_calls() {
+        let dir = log_dir("record");
+        let log = Arc::new(AuditLog::new(dir.join("audit.jsonl"), DEFAULT_MAX_BYTES));
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(Some(log.clone())))
+                .wrap(from_fn(record))
+                .wrap(from_fn(authenticated))
+                .default_service(web::to(tool)),
+        ).await;
+        for path in ["/api/search", "/api/update_page", "/api/archive_page"] {
+            call_service(&app, TestRequest::post().uri(path).to_request()).await;
+        }
+
+        let entries = log.query(&AuditQuery { limit: 10, ..Default::default() }).unwrap();
+        assert_eq!(entries.len(), 2, "reads are not logged");
+        let failed = entries.iter().find(|e| e.tool == "archive_page").unwrap();
+        assert_eq!((failed.outcome.as_str(), failed.error.as_deref()), ("error", Some("Page is locked")));
+        let updated = entries.iter().find(|e| e.tool == "update_page").unwrap();
+        assert_eq!(updated.caller.as_deref(), Some("key:ops"));
+        assert_eq!(updated.outcome, "ok");
+        assert_eq!(updated.targets, vec![PAGE, "p1", "3a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d"]);
+        assert_eq!(updated.notion_request_ids, vec!["req-1"]);
+        assert_eq!(updated.params["api_token"], "[redacted]");
+        assert!(updated.params["title"].as_str().unwrap().ends_with("… (2000 bytes)"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
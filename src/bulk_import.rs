@@ -0,0 +1,317 @@
+use crate::dry_run::{self, PropertyChange};
+use crate::error::{NotionMcpError, NotionResult};
+use crate::notion::{NotionClient, PageParent};
+use crate::properties::build_properties;
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+pub const DEFAULT_CONCURRENCY: usize = 3;
+
+#[derive(Debug, Clone, Default)]
+pub struct BulkImportOptions {
+    // Property used to find existing rows; without it every row is created
+    pub key_property: Option<String>,
+    // Input column -> database property. Unmapped columns use their own name.
+    pub mapping: BTreeMap<String, String>,
+    pub concurrency: usize,
+    // Report what each row would change without writing anything
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowStatus {
+    Created,
+    Updated,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RowResult {
+    // 1-based position in the input
+    pub row: usize,
+    pub key: Option<String>,
+    pub status: RowStatus,
+    pub page_id: Option<String>,
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ignored_columns: Vec<String>,
+    // Properties the row would change, in dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changes: Option<Vec<PropertyChange>>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct BulkImportReport {
+    pub dry_run: bool,
+    pub total: usize,
+    pub created: usize,
+    pub updated: usize,
+    pub failed: usize,
+    pub rows: Vec<RowResult>,
+}
+
+// Read rows from a CSV file (header row required) or a JSON Lines file
+pub fn read_rows(path: &Path) -> NotionResult<Vec<Map<String, Value>>> {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+
+    match extension.as_str() {
+        "csv" => {
+            let mut reader = csv::Reader::from_path(path)
+                .map_err(|e| NotionMcpError::InvalidParams(format!("Cannot read {}: {}", path.display(), e)))?;
+            let headers = reader.headers()
+                .map_err(|e| NotionMcpError::InvalidParams(e.to_string()))?
+                .clone();
+
+            let mut rows = Vec::new();
+            for record in reader.records() {
+                let record = record.map_err(|e| NotionMcpError::InvalidParams(e.to_string()))?;
+                let row = headers.iter()
+                    .zip(record.iter())
+                    .map(|(header, value)| (header.to_string(), json!(value)))
+                    .collect();
+                rows.push(row);
+            }
+            Ok(rows)
+        }
+        "jsonl" | "ndjson" => {
+            let content = std::fs::read_to_string(path)?;
+            content.lines()
+                .filter(|line| !line.trim().is_empty())
+                .enumerate()
+                .map(|(i, line)| match serde_json::from_str::<Value>(line) {
+                    Ok(Value::Object(row)) => Ok(row),
+                    Ok(_) => Err(NotionMcpError::InvalidParams(format!("Line {} is not a JSON object", i + 1))),
+                    Err(e) => Err(NotionMcpError::InvalidParams(format!("Line {}: {}", i + 1, e))),
+                })
+                .collect()
+        }
+        _ => Err(NotionMcpError::InvalidParams(format!(
+            "Unsupported file type '{}', expected .csv or .jsonl", extension
+        ))),
+    }
+}
+
+// Database filter that matches rows whose key property equals `value`
+fn key_filter(name: &str, schema: &Value, value: &str) -> Option<Value> {
+    let property_type = schema["type"].as_str()?;
+    let condition = match property_type {
+        "title" | "rich_text" | "url" | "email" | "phone_number" => json!({ "equals": value }),
+        "number" => json!({ "equals": value.trim().parse::<f64>().ok()? }),
+        "select" | "status" => json!({ "equals": value }),
+        "unique_id" => {
+            let number = value.rsplit('-').next()?.trim().parse::<i64>().ok()?;
+            json!({ "equals": number })
+        }
+        _ => return None,
+    };
+
+    Some(json!({
+        "property": name,
+        property_type: condition
+    }))
+}
+
+// What happened to a row, or would happen in a dry run
+struct RowOutcome {
+    status: RowStatus,
+    page_id: Option<String>,
+    ignored: Vec<String>,
+    changes: Option<Vec<PropertyChange>>,
+}
+
+async fn upsert_row(
+    client: &NotionClient,
+    database_id: &str,
+    schema: &Map<String, Value>,
+    key_property: Option<&str>,
+    values: Map<String, Value>,
+    dry_run: bool,
+) -> Result<RowOutcome, String> {
+    let (properties, ignored) = build_properties(schema, &values);
+    if properties.is_empty() {
+        return Err("No column matches a writable database property".to_string());
+    }
+
+    let existing = match key_property {
+        Some(key) => {
+            let key_value = values.get(key)
+                .map(|v| v.as_str().map(|s| s.to_string()).unwrap_or_else(|| v.to_string()))
+                .filter(|v| !v.trim().is_empty())
+                .ok_or_else(|| format!("Missing value for key property '{}'", key))?;
+            let filter = key_filter(key, &schema[key], &key_value)
+                .ok_or_else(|| format!("Property '{}' cannot be used as an upsert key", key))?;
+            let matches = client.query_database_pages(database_id, Some(filter)).await
+                .map_err(|e| e.to_string())?;
+            if matches.len() > 1 {
+                return Err(format!("{} existing rows share key '{}'", matches.len(), key_value));
+            }
+            matches.into_iter().next()
+        }
+        None => None,
+    };
+
+    if dry_run {
+        let current = existing.as_ref().and_then(|page| page["properties"].as_object());
+        let (changes, _) = dry_run::property_changes(schema, current, &properties);
+        return Ok(RowOutcome {
+            status: if existing.is_some() { RowStatus::Updated } else { RowStatus::Created },
+            page_id: existing.as_ref().and_then(|page| page["id"].as_str()).map(|s| s.to_string()),
+            ignored,
+            changes: Some(changes),
+        });
+    }
+
+    match existing.as_ref().and_then(|page| page["id"].as_str()) {
+        Some(page_id) => {
+            client.update_page(page_id, Value::Object(properties)).await
+                .map_err(|e| e.to_string())?;
+            Ok(RowOutcome { status: RowStatus::Updated, page_id: Some(page_id.to_string()), ignored, changes: None })
+        }
+        None => {
+            let page = client.create_page(&PageParent::Database(database_id.to_string()), Value::Object(properties), None).await
+                .map_err(|e| e.to_string())?;
+            let page_id = page["id"].as_str().unwrap_or("").to_string();
+            Ok(RowOutcome { status: RowStatus::Created, page_id: Some(page_id), ignored, changes: None })
+        }
+    }
+}
+
+// Create or update database rows from plain values. Columns are mapped to
+// properties through the database schema, and rows are upserted by the key
+// property with bounded concurrency; requests still go through the client's
+// rate limiter. Rows that are not objects fail without shifting the others.
+pub async fn bulk_import(
+    client: &NotionClient,
+    database_id: &str,
+    rows: Vec<Value>,
+    options: BulkImportOptions,
+) -> NotionResult<BulkImportReport> {
+    let database = client.get_database(database_id).await?;
+    let schema = database["properties"].as_object().cloned().unwrap_or_default();
+
+    if let Some(key) = &options.key_property {
+        if !schema.contains_key(key) {
+            return Err(NotionMcpError::InvalidParams(format!("Key property '{}' is not in the database", key)));
+        }
+    }
+
+    let schema = Arc::new(schema);
+    let semaphore = Arc::new(Semaphore::new(options.concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+    let mut results = Vec::new();
+    let mut seen_keys = HashSet::new();
+
+    for (index, row) in rows.into_iter().enumerate() {
+        let Value::Object(row) = row else {
+            results.push(RowResult {
+                row: index + 1,
+                key: None,
+                status: RowStatus::Failed,
+                page_id: None,
+                error: Some("Row is not a JSON object".to_string()),
+                ignored_columns: Vec::new(),
+                changes: None,
+            });
+            continue;
+        };
+
+        // Rename mapped columns to their property names
+        let values: Map<String, Value> = row.into_iter()
+            .map(|(column, value)| {
+                let property = options.mapping.get(&column).cloned().unwrap_or(column);
+                (property, value)
+            })
+            .collect();
+
+        let key = options.key_property.as_ref()
+            .and_then(|k| values.get(k))
+            .map(|v| v.as_str().map(|s| s.to_string()).unwrap_or_else(|| v.to_string()));
+
+        // Rows sharing a key would race to create the same page
+        if let Some(key) = &key {
+            if !seen_keys.insert(key.clone()) {
+                results.push(RowResult {
+                    row: index + 1,
+                    key: Some(key.clone()),
+                    status: RowStatus::Failed,
+                    page_id: None,
+                    error: Some("Duplicate key in input".to_string()),
+                    ignored_columns: Vec::new(),
+                    changes: None,
+                });
+                continue;
+            }
+        }
+
+        let client = client.clone();
+        let database_id = database_id.to_string();
+        let schema = schema.clone();
+        let key_property = options.key_property.clone();
+        let semaphore = semaphore.clone();
+        let dry_run = options.dry_run;
+
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            let outcome = upsert_row(&client, &database_id, &schema, key_property.as_deref(), values, dry_run).await;
+            (index, key, outcome)
+        });
+    }
+
+    while let Some(joined) = tasks.join_next().await {
+        let (index, key, outcome) = joined
+            .map_err(|e| NotionMcpError::Server(format!("Import task failed: {}", e)))?;
+
+        results.push(match outcome {
+            Ok(outcome) => RowResult {
+                row: index + 1,
+                key,
+                status: outcome.status,
+                page_id: outcome.page_id,
+                error: None,
+                ignored_columns: outcome.ignored,
+                changes: outcome.changes,
+            },
+            Err(error) => {
+                warn!("Row {} failed: {}", index + 1, error);
+                RowResult {
+                    row: index + 1,
+                    key,
+                    status: RowStatus::Failed,
+                    page_id: None,
+                    error: Some(error),
+                    ignored_columns: Vec::new(),
+                    changes: None,
+                }
+            }
+        });
+    }
+
+    results.sort_by_key(|r| r.row);
+
+    let mut report = BulkImportReport {
+        dry_run: options.dry_run,
+        total: results.len(),
+        ..BulkImportReport::default()
+    };
+    for result in &results {
+        match result.status {
+            RowStatus::Created => report.created += 1,
+            RowStatus::Updated => report.updated += 1,
+            RowStatus::Failed => report.failed += 1,
+        }
+    }
+    report.rows = results;
+
+    info!(
+        "Bulk import into {}{} finished: {} created, {} updated, {} failed",
+        database_id, if options.dry_run { " (dry run)" } else { "" }, report.created, report.updated, report.failed
+    );
+    Ok(report)
+}
@@ -0,0 +1,224 @@
+use crate::markdown::{block_children, block_to_markdown};
+use serde::Serialize;
+use serde_json::Value;
+
+pub const DEFAULT_MAX_TOKENS: usize = 1000;
+pub const MIN_MAX_TOKENS: usize = 50;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PageChunk {
+    pub chunk_id: String,
+    pub index: usize,
+    // Headings enclosing the chunk, outermost first
+    pub breadcrumb: Vec<String>,
+    // Top-level blocks in the chunk followed by the blocks nested in each
+    pub block_ids: Vec<String>,
+    pub tokens: usize,
+    pub text: String,
+}
+
+// Rough token count for English-like text: about four characters per token
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+fn heading_level(block: &Value) -> Option<usize> {
+    match block["type"].as_str()? {
+        "heading_1" => Some(1),
+        "heading_2" => Some(2),
+        "heading_3" => Some(3),
+        _ => None,
+    }
+}
+
+// A block's ID and those of every block nested in it, depth first
+fn block_ids(block: &Value, ids: &mut Vec<String>) {
+    if let Some(id) = block["id"].as_str().filter(|id| !id.is_empty()) {
+        ids.push(id.to_string());
+    }
+    for child in block_children(block).into_iter().flatten() {
+        block_ids(child, ids);
+    }
+}
+
+// Pieces of a segment within `max_tokens`, each with the separator joining
+// it to the previous one. Lines are split further on words, and words on
+// characters.
+fn split_oversized(segment: &str, max_tokens: usize) -> Vec<(&'static str, String)> {
+    let mut pieces = Vec::new();
+    for (i, line) in segment.lines().enumerate() {
+        let line_separator = if i == 0 { "" } else { "\n" };
+        if estimate_tokens(line) <= max_tokens {
+            pieces.push((line_separator, line.to_string()));
+            continue;
+        }
+        for (j, word) in line.split(' ').enumerate() {
+            let word_separator = if j == 0 { line_separator } else { " " };
+            if estimate_tokens(word) <= max_tokens {
+                pieces.push((word_separator, word.to_string()));
+                continue;
+            }
+            let chars: Vec<char> = word.chars().collect();
+            for (k, part) in chars.chunks(max_tokens * 4).enumerate() {
+                pieces.push((if k == 0 { word_separator } else { "" }, part.iter().collect()));
+            }
+        }
+    }
+    pieces
+}
+
+struct ChunkBuilder {
+    page_key: String,
+    max_tokens: usize,
+    chunks: Vec<PageChunk>,
+    breadcrumb: Vec<(usize, String)>,
+    text: String,
+    block_ids: Vec<String>,
+}
+
+impl ChunkBuilder {
+    fn flush(&mut self) {
+        if self.text.trim().is_empty() {
+            self.text.clear();
+            self.block_ids.clear();
+            return;
+        }
+
+        let index = self.chunks.len();
+        self.chunks.push(PageChunk {
+            chunk_id: format!("{}:{}", self.page_key, index),
+            index,
+            breadcrumb: self.breadcrumb.iter().map(|(_, title)| title.clone()).collect(),
+            block_ids: std::mem::take(&mut self.block_ids),
+            tokens: estimate_tokens(&self.text),
+            text: std::mem::take(&mut self.text),
+        });
+    }
+
+    fn push(&mut self, ids: &[String], segment: &str) {
+        let separator = if self.text.is_empty() { "" } else { "\n\n" };
+        let projected = estimate_tokens(&self.text) + estimate_tokens(separator) + estimate_tokens(segment);
+        if projected > self.max_tokens && !self.text.is_empty() {
+            self.flush();
+        }
+
+        // A single block larger than the budget is spread over several chunks
+        if estimate_tokens(segment) > self.max_tokens {
+            for (separator, piece) in split_oversized(segment, self.max_tokens) {
+                if !self.text.is_empty() && estimate_tokens(&format!("{}{}{}", self.text, separator, piece)) > self.max_tokens {
+                    self.flush();
+                }
+                if !self.block_ids.ends_with(ids) {
+                    self.block_ids.extend_from_slice(ids);
+                }
+                self.text.push_str(if self.text.is_empty() { "" } else { separator });
+                self.text.push_str(&piece);
+            }
+            return;
+        }
+
+        let separator = if self.text.is_empty() { "" } else { "\n\n" };
+        self.text.push_str(separator);
+        self.text.push_str(segment);
+        self.block_ids.extend_from_slice(ids);
+    }
+}
+
+// Split a page's block tree into Markdown chunks under `max_tokens`, starting
+// a new chunk at every top-level heading so each chunk stays within one
+// section. Nested blocks stay in the chunk of their top-level block, so a
+// heading inside a toggle or column neither starts a chunk nor enters the
+// breadcrumb.
+pub fn chunk_page(page_id: &str, blocks: &[Value], max_tokens: usize) -> Vec<PageChunk> {
+    let mut builder = ChunkBuilder {
+        page_key: page_id.replace('-', ""),
+        max_tokens: max_tokens.max(MIN_MAX_TOKENS),
+        chunks: Vec::new(),
+        breadcrumb: Vec::new(),
+        text: String::new(),
+        block_ids: Vec::new(),
+    };
+    let mut number = 0;
+
+    for block in blocks {
+        let block_type = block["type"].as_str().unwrap_or("");
+        number = if block_type == "numbered_list_item" { number + 1 } else { 0 };
+
+        if let Some(level) = heading_level(block) {
+            builder.flush();
+            builder.breadcrumb.retain(|(l, _)| *l < level);
+            let title = crate::properties::rich_text_to_plain(&block[block_type]["rich_text"]);
+            builder.breadcrumb.push((level, title));
+        }
+
+        let segment = block_to_markdown(block, number);
+        if segment.is_empty() {
+            continue;
+        }
+        let mut ids = Vec::new();
+        block_ids(block, &mut ids);
+        builder.push(&ids, &segment);
+    }
+
+    builder.flush();
+    builder.chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn paragraph(id: &str, text: &str) -> Value {
+        json!({
+            "id": id,
+            "type": "paragraph",
+            "paragraph": { "rich_text": [{ "type": "text", "text": { "content": text }, "plain_text": text }] }
+        })
+    }
+
+    #[test]
+    fn long_paragraphs_are_split_on_words() {
+        let text = vec!["word"; 400].join(" ");
+        let chunks = chunk_page("p", &[paragraph("b1", &text)], MIN_MAX_TOKENS);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.tokens <= MIN_MAX_TOKENS));
+        assert!(chunks.iter().all(|c| c.block_ids == vec!["b1"]));
+        assert_eq!(chunks.iter().map(|c| c.text.split_whitespace().count()).sum::<usize>(), 400);
+    }
+
+    #[test]
+    fn long_words_are_split_on_characters() {
+        let text = "x".repeat(1000);
+        let chunks = chunk_page("p", &[paragraph("b1", &text)], MIN_MAX_TOKENS);
+        assert!(chunks.iter().all(|c| c.tokens <= MIN_MAX_TOKENS));
+        assert_eq!(chunks.iter().map(|c| c.text.len()).sum::<usize>(), 1000);
+    }
+
+    #[test]
+    fn nested_blocks_are_listed_with_their_parent() {
+        let mut toggle = json!({
+            "id": "t1",
+            "type": "toggle",
+            "toggle": { "rich_text": [{ "type": "text", "text": { "content": "Details" }, "plain_text": "Details" }] }
+        });
+        let mut inner = paragraph("b2", "Inner");
+        inner["children"] = json!([paragraph("b3", "Deeper")]);
+        toggle["children"] = json!([inner, {
+            "id": "h2",
+            "type": "heading_2",
+            "heading_2": { "rich_text": [{ "type": "text", "text": { "content": "Nested" }, "plain_text": "Nested" }] }
+        }]);
+        let chunks = chunk_page("p", &[paragraph("b1", "Intro"), toggle, paragraph("b4", "After")], DEFAULT_MAX_TOKENS);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].block_ids, vec!["b1", "t1", "b2", "b3", "h2", "b4"]);
+        assert!(chunks[0].breadcrumb.is_empty());
+    }
+
+    #[test]
+    fn budget_has_a_minimum() {
+        let text = vec!["word"; 30].join(" ");
+        let chunks = chunk_page("p", &[paragraph("b1", &text)], 1);
+        assert_eq!(chunks.len(), 1);
+    }
+}
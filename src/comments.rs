@@ -0,0 +1,46 @@
+use crate::markdown::rich_text_to_markdown;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CommentView {
+    pub id: String,
+    pub discussion_id: String,
+    pub created_time: String,
+    pub created_by: String,
+    // Comment body rendered as Markdown
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Discussion {
+    pub discussion_id: String,
+    pub comments: Vec<CommentView>,
+}
+
+pub fn render_comment(comment: &Value) -> CommentView {
+    let field = |key: &str| comment[key].as_str().unwrap_or("").to_string();
+    CommentView {
+        id: field("id"),
+        discussion_id: field("discussion_id"),
+        created_time: field("created_time"),
+        created_by: comment["created_by"]["id"].as_str().unwrap_or("").to_string(),
+        text: rich_text_to_markdown(&comment["rich_text"]),
+    }
+}
+
+// Group comments into discussion threads, keeping the order in which the
+// API returned them (oldest first)
+pub fn group_discussions(comments: &[Value]) -> Vec<Discussion> {
+    let mut discussions: Vec<Discussion> = Vec::new();
+    for comment in comments.iter().map(render_comment) {
+        match discussions.iter_mut().find(|d| d.discussion_id == comment.discussion_id) {
+            Some(discussion) => discussion.comments.push(comment),
+            None => discussions.push(Discussion {
+                discussion_id: comment.discussion_id.clone(),
+                comments: vec![comment],
+            }),
+        }
+    }
+    discussions
+}
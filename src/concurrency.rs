@@ -0,0 +1,296 @@
+use crate::error::{NotionMcpError, NotionResult};
+use crate::ids::normalize_id;
+use crate::properties::property_to_plain_json;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+// What the caller expects of the page it is about to update. Notion keeps
+// last_edited_time to the minute, so an edit made in the same minute as the
+// caller's read leaves it unchanged and the precondition still holds.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Precondition {
+    // `expected_last_edited_time`: the page is exactly as last read
+    LastEdited(String),
+    // `if_unmodified_since`: nobody edited the page after this instant
+    UnmodifiedSince(i64),
+}
+
+impl Precondition {
+    pub fn from_params(params: &Value) -> NotionResult<Option<Self>> {
+        if let Some(expected) = params.get("expected_last_edited_time").and_then(|v| v.as_str()) {
+            return Ok(Some(Precondition::LastEdited(expected.to_string())));
+        }
+        match params.get("if_unmodified_since").and_then(|v| v.as_str()) {
+            Some(since) => parse_timestamp(since)
+                .map(|seconds| Some(Precondition::UnmodifiedSince(seconds)))
+                .ok_or_else(|| NotionMcpError::InvalidParams(format!(
+                    "'if_unmodified_since' must be an ISO 8601 date and time, got '{}'", since
+                ))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn holds(&self, page: &Value) -> bool {
+        let Some(current) = page["last_edited_time"].as_str() else {
+            return false;
+        };
+        match self {
+            Precondition::LastEdited(expected) => match (parse_timestamp(current), parse_timestamp(expected)) {
+                (Some(current), Some(expected)) => current == expected,
+                _ => current == expected,
+            },
+            Precondition::UnmodifiedSince(since) => parse_timestamp(current).is_some_and(|current| current <= *since),
+        }
+    }
+}
+
+// A property both the caller and someone else changed since the caller read it
+#[derive(Debug, Serialize)]
+pub struct PropertyConflict {
+    pub property: String,
+    pub base: Value,
+    pub current: Value,
+    pub requested: Value,
+}
+
+// Outcome of a merge: the properties to write, the ones the caller left
+// as read, and those changed on both sides
+#[derive(Debug, Default)]
+pub struct Merge {
+    pub properties: Map<String, Value>,
+    pub unchanged: Vec<String>,
+    pub conflicts: Vec<PropertyConflict>,
+}
+
+// Value of a property as the merge compares it. People and relations are
+// compared by normalized ID: Notion answers with names and dashed IDs where
+// callers write bare IDs, so their plain values would never match.
+fn comparable(property: &Value) -> Value {
+    match property["type"].as_str() {
+        Some(kind @ ("people" | "relation")) => property[kind].as_array().into_iter().flatten()
+            .map(|item| match item["id"].as_str() {
+                Some(id) => Value::String(normalize_id(id).unwrap_or_else(|_| id.to_string())),
+                None => item.clone(),
+            })
+            .collect(),
+        _ => property_to_plain_json(property),
+    }
+}
+
+// Three-way merge of an update. `base` holds the properties as the caller
+// read them, `current` as they are now; only the properties whose requested
+// value differs from `base` are written.
+pub fn merge(base: &Map<String, Value>, current: &Map<String, Value>, requested: &Map<String, Value>) -> Merge {
+    let mut merge = Merge::default();
+    for (name, payload) in requested {
+        // Unknown properties are left for Notion to reject
+        let Some(property_type) = current.get(name).and_then(|p| p["type"].as_str()) else {
+            merge.properties.insert(name.clone(), payload.clone());
+            continue;
+        };
+        let mut typed = payload.clone();
+        typed["type"] = Value::String(property_type.to_string());
+        let requested_value = comparable(&typed);
+        let Some(base_value) = base.get(name).map(comparable) else {
+            merge.properties.insert(name.clone(), payload.clone());
+            continue;
+        };
+
+        if requested_value == base_value {
+            merge.unchanged.push(name.clone());
+            continue;
+        }
+        let current_value = comparable(&current[name]);
+        if current_value != base_value && current_value != requested_value {
+            merge.conflicts.push(PropertyConflict {
+                property: name.clone(),
+                base: base_value,
+                current: current_value,
+                requested: requested_value,
+            });
+            continue;
+        }
+        merge.properties.insert(name.clone(), payload.clone());
+    }
+    merge
+}
+
+// Unix seconds of an ISO 8601 date and time such as the `last_edited_time`
+// Notion returns (`2024-05-01T10:20:00.000Z`). A bare date is midnight UTC.
+pub fn parse_timestamp(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let number = |s: &str| -> Option<i64> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        s.parse().ok()
+    };
+    let (date, time) = match value.split_once(['T', ' ']) {
+        Some((date, time)) => (date, time),
+        None => (value, "00:00Z"),
+    };
+
+    let mut parts = date.splitn(3, '-');
+    let year = number(parts.next()?)?;
+    let month = number(parts.next()?)?;
+    let day = number(parts.next()?)?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Offset from UTC, in seconds
+    let (clock, offset) = if let Some(clock) = time.strip_suffix(['Z', 'z']) {
+        (clock, 0)
+    } else if let Some(sign_at) = time.rfind(['+', '-']) {
+        let (clock, zone) = time.split_at(sign_at);
+        let sign = if zone.starts_with('-') { -1 } else { 1 };
+        let zone = &zone[1..];
+        let (hours, minutes) = zone.split_once(':')
+            .unwrap_or(if zone.len() == 4 { zone.split_at(2) } else { (zone, "0") });
+        (clock, sign * (number(hours)? * 3600 + number(minutes)? * 60))
+    } else {
+        (time, 0)
+    };
+    let clock = clock.split('.').next()?;
+    let mut fields = clock.split(':');
+    let hour = number(fields.next()?)?;
+    let minute = number(fields.next()?)?;
+    let second = fields.next().map(number).unwrap_or(Some(0))?;
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    // Days-from-civil for the proleptic Gregorian calendar
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    Some(days * 86_400 + hour * 3600 + minute * 60 + second - offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const ALICE: &str = "1a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d";
+    const BOB: &str = "2a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d";
+    const CAROL: &str = "3a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d";
+
+    fn properties(value: Value) -> Map<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    // Page properties as Notion returns them
+    fn page(status: &str, owner: &str, owner_name: &str) -> Map<String, Value> {
+        properties(json!({
+            "Status": { "type": "select", "select": { "name": status } },
+            "Estimate": { "type": "number", "number": 3 },
+            "Owner": { "type": "people", "people": [{ "object": "user", "id": owner, "name": owner_name }] },
+            "Tasks": { "type": "relation", "relation": [{ "id": BOB }], "has_more": false }
+        }))
+    }
+
+    #[test]
+    fn writes_only_what_the_caller_changed() {
+        let base = page("Draft", ALICE, "Alice");
+        let requested = properties(json!({
+            "Status": { "select": { "name": "Done" } },
+            "Estimate": { "number": 3 }
+        }));
+        let merged = merge(&base, &base, &requested);
+        assert_eq!(merged.properties.keys().collect::<Vec<_>>(), vec!["Status"]);
+        assert_eq!(merged.unchanged, vec!["Estimate"]);
+        assert!(merged.conflicts.is_empty());
+    }
+
+    #[test]
+    fn keeps_changes_made_by_others_to_other_properties() {
+        let base = page("Draft", ALICE, "Alice");
+        let mut current = page("Review", ALICE, "Alice");
+        current["Estimate"]["number"] = json!(5);
+        let requested = properties(json!({ "Estimate": { "number": 8 }, "Status": { "select": { "name": "Draft" } } }));
+
+        let merged = merge(&base, &current, &requested);
+        assert_eq!(merged.unchanged, vec!["Status"]);
+        assert_eq!(merged.conflicts.len(), 1);
+        assert_eq!(merged.conflicts[0].property, "Estimate");
+        assert_eq!((merged.conflicts[0].current.clone(), merged.conflicts[0].requested.clone()), (json!(5), json!(8)));
+    }
+
+    #[test]
+    fn a_change_both_sides_agree_on_is_no_conflict() {
+        let base = page("Draft", ALICE, "Alice");
+        let current = page("Done", ALICE, "Alice");
+        let merged = merge(&base, &current, &properties(json!({ "Status": { "select": { "name": "Done" } } })));
+        assert!(merged.conflicts.is_empty());
+        assert!(merged.properties.contains_key("Status"));
+    }
+
+    #[test]
+    fn compares_people_and_relations_by_id() {
+        let base = page("Draft", ALICE, "Alice");
+        // Meanwhile the status changed and the owner's display name too
+        let current = page("Review", ALICE, "Alice Smith");
+        let requested = properties(json!({
+            "Owner": { "people": [{ "id": ALICE.replace('-', "") }] },
+            "Tasks": { "relation": [{ "id": BOB.replace('-', "").to_uppercase() }] }
+        }));
+        let merged = merge(&base, &current, &requested);
+        assert_eq!(merged.unchanged, vec!["Owner", "Tasks"]);
+        assert!(merged.properties.is_empty());
+
+        let requested = properties(json!({ "Owner": { "people": [{ "id": BOB }] } }));
+        let current = page("Draft", BOB, "Bob");
+        assert!(merge(&base, &current, &requested).conflicts.is_empty());
+        let current = page("Draft", CAROL, "Carol");
+        let merged = merge(&base, &current, &requested);
+        assert_eq!(merged.conflicts[0].requested, json!([BOB]));
+    }
+
+    #[test]
+    fn parses_utc_timestamps() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_timestamp("2024-05-01T10:20:00.000Z"), Some(1_714_558_800));
+        assert_eq!(parse_timestamp("2024-05-01 10:20"), Some(1_714_558_800));
+    }
+
+    #[test]
+    fn applies_offsets() {
+        let utc = parse_timestamp("2024-05-01T10:20:00Z");
+        assert_eq!(parse_timestamp("2024-05-01T12:20:00+02:00"), utc);
+        assert_eq!(parse_timestamp("2024-05-01T05:20:00-0500"), utc);
+        assert_eq!(parse_timestamp("2024-05-01T11:20:00+01"), utc);
+    }
+
+    #[test]
+    fn ignores_fractional_seconds() {
+        assert_eq!(parse_timestamp("2024-05-01T10:20:00.999Z"), parse_timestamp("2024-05-01T10:20:00Z"));
+        assert_eq!(parse_timestamp("2024-05-01T12:20:00.5+02:00"), parse_timestamp("2024-05-01T10:20:00Z"));
+    }
+
+    #[test]
+    fn bare_dates_are_midnight_utc() {
+        assert_eq!(parse_timestamp("2024-05-01"), Some(1_714_521_600));
+        assert_eq!(parse_timestamp("2024-02-29"), parse_timestamp("2024-02-29T00:00:00Z"));
+    }
+
+    #[test]
+    fn rejects_malformed_values() {
+        assert_eq!(parse_timestamp("yesterday"), None);
+        assert_eq!(parse_timestamp("2024-13-01"), None);
+        assert_eq!(parse_timestamp("2024-05-01T25:00:00Z"), None);
+        assert_eq!(parse_timestamp("2024-05-01T10:20:00+xx"), None);
+    }
+
+    #[test]
+    fn same_minute_edits_pass_the_precondition() {
+        let page = serde_json::json!({ "last_edited_time": "2024-05-01T10:20:00.000Z" });
+        assert!(Precondition::LastEdited("2024-05-01T10:20:00Z".to_string()).holds(&page));
+        assert!(Precondition::UnmodifiedSince(parse_timestamp("2024-05-01T10:20:30Z").unwrap()).holds(&page));
+        assert!(!Precondition::UnmodifiedSince(parse_timestamp("2024-05-01T10:19:59Z").unwrap()).holds(&page));
+    }
+}
@@ -0,0 +1,166 @@
+use crate::error::{NotionMcpError, NotionResult};
+use crate::notion::NotionClient;
+use crate::properties::{page_title, property_to_plain_json};
+use log::{debug, info};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+// Page fields that can be selected as columns alongside the properties
+const PAGE_COLUMNS: &[&str] = &["id", "url", "created_time", "last_edited_time"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TableFormat {
+    Csv,
+    JsonLines,
+}
+
+impl TableFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "csv" => Some(TableFormat::Csv),
+            "jsonl" | "ndjson" | "json_lines" => Some(TableFormat::JsonLines),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension().and_then(|e| Self::parse(&e.to_string_lossy()))
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DatabaseTable {
+    pub columns: Vec<String>,
+    pub rows: Vec<Map<String, Value>>,
+}
+
+fn cell_to_string(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(cell_to_string).collect::<Vec<_>>().join(", "),
+        other => other.to_string(),
+    }
+}
+
+// Titles of related pages, fetched once per page
+async fn relation_titles(client: &NotionClient, ids: &[String], cache: &mut HashMap<String, String>) -> Vec<String> {
+    let mut titles = Vec::new();
+    for id in ids {
+        if !cache.contains_key(id) {
+            let title = match client.get_page_object(id).await {
+                Ok(page) => page_title(&page),
+                Err(e) => {
+                    debug!("Could not resolve related page {}: {}", id, e);
+                    id.clone()
+                }
+            };
+            cache.insert(id.clone(), title);
+        }
+        titles.push(cache[id].clone());
+    }
+    titles
+}
+
+// Read every row of a database as flattened, human-readable values. Columns
+// default to the title followed by the other properties; relations are
+// resolved to the titles of the related pages.
+pub async fn read_database_table(
+    client: &NotionClient,
+    database_id: &str,
+    columns: Option<Vec<String>>,
+    filter: Option<Value>,
+) -> NotionResult<DatabaseTable> {
+    let database = client.get_database(database_id).await?;
+    let schema = database["properties"].as_object().cloned().unwrap_or_default();
+
+    let columns = match columns {
+        Some(columns) => {
+            for column in &columns {
+                if !schema.contains_key(column) && !PAGE_COLUMNS.contains(&column.as_str()) {
+                    return Err(NotionMcpError::InvalidParams(format!("Unknown column '{}'", column)));
+                }
+            }
+            columns
+        }
+        None => {
+            let mut columns: Vec<String> = schema.iter()
+                .filter(|(_, p)| p["type"] == "title")
+                .map(|(name, _)| name.clone())
+                .collect();
+            columns.extend(schema.iter().filter(|(_, p)| p["type"] != "title").map(|(name, _)| name.clone()));
+            columns
+        }
+    };
+
+    let mut pages = client.query_database_pages(database_id, filter).await?;
+    // Show people by name when the integration can read the user list
+    let has_people = schema.values().any(|p| matches!(p["type"].as_str(), Some("people" | "created_by" | "last_edited_by")));
+    if has_people {
+        if let Ok(directory) = client.directory().await {
+            pages.iter_mut().for_each(|page| directory.resolve_page(page));
+        }
+    }
+    let mut relation_cache = HashMap::new();
+    let mut rows = Vec::new();
+
+    for page in &pages {
+        let mut row = Map::new();
+        for column in &columns {
+            let value = if PAGE_COLUMNS.contains(&column.as_str()) && !schema.contains_key(column) {
+                page[column.as_str()].clone()
+            } else {
+                let property = &page["properties"][column.as_str()];
+                if property["type"] == "relation" {
+                    let ids: Vec<String> = property["relation"].as_array()
+                        .map(|r| r.iter().filter_map(|p| p["id"].as_str().map(|s| s.to_string())).collect())
+                        .unwrap_or_default();
+                    json!(relation_titles(client, &ids, &mut relation_cache).await)
+                } else {
+                    property_to_plain_json(property)
+                }
+            };
+            row.insert(column.clone(), value);
+        }
+        rows.push(row);
+    }
+
+    info!("Read {} rows from database {}", rows.len(), database_id);
+    Ok(DatabaseTable { columns, rows })
+}
+
+// Write a table as CSV (one string per cell, lists joined with ", ") or as
+// JSON Lines (one object per row, lists kept as arrays)
+pub fn write_table(table: &DatabaseTable, path: &Path, format: TableFormat) -> NotionResult<()> {
+    match format {
+        TableFormat::Csv => {
+            let mut writer = csv::Writer::from_path(path)
+                .map_err(|e| NotionMcpError::Server(format!("Cannot write {}: {}", path.display(), e)))?;
+            let write = |writer: &mut csv::Writer<File>| -> csv::Result<()> {
+                writer.write_record(&table.columns)?;
+                for row in &table.rows {
+                    writer.write_record(table.columns.iter().map(|c| cell_to_string(&row[c.as_str()])))?;
+                }
+                writer.flush()?;
+                Ok(())
+            };
+            write(&mut writer).map_err(|e| NotionMcpError::Server(e.to_string()))
+        }
+        TableFormat::JsonLines => {
+            let mut writer = BufWriter::new(File::create(path)?);
+            for row in &table.rows {
+                // Keep the requested column order
+                let ordered: Vec<String> = table.columns.iter()
+                    .map(|c| format!("{}:{}", json!(c), row[c.as_str()]))
+                    .collect();
+                writeln!(writer, "{{{}}}", ordered.join(","))?;
+            }
+            writer.flush()?;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use crate::error::NotionResult;
+use crate::notion::{NotionClient, PageParent};
+use crate::properties::property_to_plain_json;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+// Property types computed by Notion, which cannot be written
+const COMPUTED_TYPES: &[&str] = &[
+    "formula", "rollup", "created_time", "created_by", "last_edited_time", "last_edited_by", "unique_id", "button", "verification",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PropertyChange {
+    pub property: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockSummary {
+    #[serde(rename = "type")]
+    pub block_type: String,
+    pub text: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct BlockDiff {
+    pub before: Vec<BlockSummary>,
+    pub after: Vec<BlockSummary>,
+}
+
+// What a write would do, computed without sending it
+#[derive(Debug, Serialize)]
+pub struct Preview {
+    pub dry_run: bool,
+    pub action: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_id: Option<String>,
+    // Whether Notion is expected to accept the write
+    pub valid: bool,
+    pub errors: Vec<String>,
+    pub changes: Vec<PropertyChange>,
+    pub unchanged: Vec<String>,
+    // Only for create_page: updates change properties alone, and no tool
+    // that edits blocks supports dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blocks: Option<BlockDiff>,
+}
+
+// Problems Notion would reject a properties payload for, checked against
+// the schema (property name -> object with its `type`)
+pub fn validate(schema: &Map<String, Value>, properties: &Map<String, Value>) -> Vec<String> {
+    let mut errors = Vec::new();
+    for (name, payload) in properties {
+        let Some(property_type) = schema.get(name).and_then(|s| s["type"].as_str()) else {
+            errors.push(format!("Property '{}' does not exist", name));
+            continue;
+        };
+        if COMPUTED_TYPES.contains(&property_type) {
+            errors.push(format!("Property '{}' is a {} property and cannot be written", name, property_type));
+        } else if payload.get(property_type).is_none() {
+            let given = payload.as_object()
+                .map(|p| p.keys().filter(|k| *k != "type").cloned().collect::<Vec<_>>().join(", "))
+                .unwrap_or_default();
+            errors.push(format!("Property '{}' is a {} property, got '{}'", name, property_type, given));
+        } else if property_type == "status" {
+            // Unlike selects, statuses only take existing options
+            let options = schema[name]["status"]["options"].as_array();
+            let chosen = payload["status"]["name"].as_str();
+            if let (Some(options), Some(chosen)) = (options, chosen) {
+                if !options.iter().any(|o| o["name"].as_str() == Some(chosen)) {
+                    errors.push(format!("'{}' is not an option of status property '{}'", chosen, name));
+                }
+            }
+        }
+    }
+    errors
+}
+
+// Before/after values of every written property, as plain JSON. Returns
+// the changed properties and the names of those left as they are.
+pub fn property_changes(
+    schema: &Map<String, Value>,
+    current: Option<&Map<String, Value>>,
+    properties: &Map<String, Value>,
+) -> (Vec<PropertyChange>, Vec<String>) {
+    let mut changes = Vec::new();
+    let mut unchanged = Vec::new();
+    for (name, payload) in properties {
+        // Unknown properties are reported by `validate`
+        let Some(property_schema) = schema.get(name) else {
+            continue;
+        };
+        let mut after = payload.clone();
+        after["type"] = property_schema["type"].clone();
+        let after = property_to_plain_json(&after);
+        let before = current
+            .and_then(|c| c.get(name))
+            .map(property_to_plain_json)
+            .unwrap_or(Value::Null);
+        if before == after {
+            unchanged.push(name.clone());
+        } else {
+            changes.push(PropertyChange { property: name.clone(), before, after });
+        }
+    }
+    (changes, unchanged)
+}
+
+fn summarize(blocks: &[Value]) -> Vec<BlockSummary> {
+    blocks.iter()
+        .map(|block| BlockSummary {
+            block_type: block["type"].as_str().unwrap_or("").to_string(),
+            text: NotionClient::block_text(block),
+        })
+        .collect()
+}
+
+// Schema of the properties a new page can have under a parent. Pages
+// outside databases only have a title.
+pub async fn parent_schema(client: &NotionClient, parent: &PageParent) -> NotionResult<Map<String, Value>> {
+    let container = match parent.normalized()? {
+        PageParent::Database(id) => client.get_database(&id).await?,
+        PageParent::DataSource(id) => client.get_data_source(&id).await?,
+        PageParent::Page(_) | PageParent::Workspace => json!({ "properties": { "title": { "type": "title" } } }),
+    };
+    Ok(container["properties"].as_object().cloned().unwrap_or_default())
+}
+
+fn preview(action: &'static str, page_id: Option<String>, errors: Vec<String>, (changes, unchanged): (Vec<PropertyChange>, Vec<String>)) -> Preview {
+    Preview {
+        dry_run: true,
+        action,
+        page_id,
+        valid: errors.is_empty(),
+        errors,
+        changes,
+        unchanged,
+        blocks: None,
+    }
+}
+
+// People given by name or email are looked up as the real write would
+async fn resolve_people(client: &NotionClient, properties: &mut Value, errors: &mut Vec<String>) {
+    if let Err(e) = client.resolve_people_payload(properties).await {
+        errors.push(e.to_string());
+    }
+}
+
+pub async fn preview_create(client: &NotionClient, parent: &PageParent, mut properties: Value, content: Option<Vec<Value>>) -> NotionResult<Preview> {
+    let schema = parent_schema(client, parent).await?;
+    let mut errors = Vec::new();
+    resolve_people(client, &mut properties, &mut errors).await;
+    let properties = properties.as_object().cloned().unwrap_or_default();
+    errors.extend(validate(&schema, &properties));
+
+    let mut preview = preview("create_page", None, errors, property_changes(&schema, None, &properties));
+    preview.blocks = Some(BlockDiff {
+        before: Vec::new(),
+        after: summarize(&content.unwrap_or_default()),
+    });
+    Ok(preview)
+}
+
+pub async fn preview_update(client: &NotionClient, page_id: &str, mut properties: Value) -> NotionResult<Preview> {
+    let page = client.get_page_object(page_id).await?;
+    // A page's properties carry their types, so they double as the schema
+    let current = page["properties"].as_object().cloned().unwrap_or_default();
+    let mut errors = Vec::new();
+    resolve_people(client, &mut properties, &mut errors).await;
+    let properties = properties.as_object().cloned().unwrap_or_default();
+    errors.extend(validate(&current, &properties));
+
+    let changes = property_changes(&current, Some(&current), &properties);
+    Ok(preview("update_page", page["id"].as_str().map(|s| s.to_string()), errors, changes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> Map<String, Value> {
+        serde_json::from_value(json!({
+            "Name": { "type": "title" },
+            "Estimate": { "type": "number" },
+            "Total": { "type": "formula" },
+            "Status": { "type": "status", "status": { "options": [{ "name": "Todo" }, { "name": "Done" }] } }
+        })).unwrap()
+    }
+
+    fn payload(value: Value) -> Map<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn accepts_payloads_matching_the_schema() {
+        let properties = payload(json!({
+            "Name": { "title": [{ "text": { "content": "Plan" } }] },
+            "Estimate": { "number": 3 },
+            "Status": { "status": { "name": "Done" } }
+        }));
+        assert!(validate(&schema(), &properties).is_empty());
+    }
+
+    #[test]
+    fn reports_what_notion_would_reject() {
+        let properties = payload(json!({
+            "Owner": { "people": [] },
+            "Estimate": { "rich_text": [] },
+            "Total": { "formula": {} },
+            "Status": { "status": { "name": "Blocked" } }
+        }));
+        let errors = validate(&schema(), &properties);
+        assert_eq!(errors, vec![
+            "Property 'Estimate' is a number property, got 'rich_text'",
+            "Property 'Owner' does not exist",
+            "'Blocked' is not an option of status property 'Status'",
+            "Property 'Total' is a formula property and cannot be written",
+        ]);
+    }
+
+    #[test]
+    fn diffs_written_properties_against_the_current_values() {
+        let current = payload(json!({
+            "Name": { "type": "title", "title": [{ "plain_text": "Plan" }] },
+            "Estimate": { "type": "number", "number": 3 }
+        }));
+        let properties = payload(json!({
+            "Name": { "title": [{ "text": { "content": "Plan" }, "plain_text": "Plan" }] },
+            "Estimate": { "number": 5 },
+            "Unknown": { "number": 1 }
+        }));
+        let (changes, unchanged) = property_changes(&schema(), Some(&current), &properties);
+        assert_eq!(unchanged, vec!["Name"]);
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].property.as_str(), &changes[0].before, &changes[0].after), ("Estimate", &json!(3), &json!(5)));
+    }
+
+    #[test]
+    fn new_pages_change_every_written_property() {
+        let properties = payload(json!({ "Estimate": { "number": 5 } }));
+        let (changes, unchanged) = property_changes(&schema(), None, &properties);
+        assert!(unchanged.is_empty());
+        assert_eq!((&changes[0].before, &changes[0].after), (&Value::Null, &json!(5)));
+    }
+}
@@ -30,6 +30,18 @@ pub enum NotionMcpError {
     Unknown(String),
 }
 
+impl NotionMcpError {
+    // Whether Notion answered that the object does not exist or is not
+    // shared with the integration (404, `object_not_found`)
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            NotionMcpError::ResourceNotFound(_) => true,
+            NotionMcpError::NotionApi(message) => message.contains("HTTP 404") || message.contains("\"object_not_found\""),
+            _ => false,
+        }
+    }
+}
+
 impl From<reqwest::Error> for NotionMcpError {
     fn from(error: reqwest::Error) -> Self {
         NotionMcpError::NotionApi(error.to_string())
@@ -42,4 +54,31 @@ impl From<serde_json::Error> for NotionMcpError {
     }
 }
 
+impl From<std::io::Error> for NotionMcpError {
+    fn from(error: std::io::Error) -> Self {
+        NotionMcpError::Server(error.to_string())
+    }
+}
+
 pub type NotionResult<T> = std::result::Result<T, NotionMcpError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_missing_objects_are_not_found() {
+        let missing = NotionMcpError::NotionApi(
+            r#"Error HTTP 404 Not Found: {"object":"error","status":404,"code":"object_not_found"}"#.to_string()
+        );
+        assert!(missing.is_not_found());
+        assert!(NotionMcpError::ResourceNotFound("page".to_string()).is_not_found());
+
+        let limited = NotionMcpError::NotionApi(
+            r#"Error HTTP 429 Too Many Requests: {"object":"error","status":429,"code":"rate_limited"}"#.to_string()
+        );
+        assert!(!limited.is_not_found());
+        assert!(!NotionMcpError::Authentication("Invalid or expired Notion API token".to_string()).is_not_found());
+        assert!(!NotionMcpError::NotionApi("error sending request: connection refused".to_string()).is_not_found());
+    }
+}
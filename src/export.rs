@@ -0,0 +1,422 @@
+use crate::error::NotionResult;
+use crate::markdown::blocks_to_markdown;
+use crate::notion::NotionClient;
+use crate::properties::{page_title, property_to_plain_json, property_to_text};
+use crate::users::Directory;
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+
+type ExportFuture<'a, T> = Pin<Box<dyn Future<Output = NotionResult<T>> + Send + 'a>>;
+
+const ASSETS_DIR: &str = "_assets";
+
+#[derive(Debug, Default, Serialize)]
+pub struct ExportReport {
+    pub output_dir: String,
+    pub pages: usize,
+    pub databases: usize,
+    pub files: usize,
+    pub failed: Vec<String>,
+}
+
+// Writes pages as Markdown files with YAML front matter. A page's child pages,
+// databases and downloaded attachments go in a directory named after it:
+//
+//   My-Page.md
+//   My-Page/_assets/1-diagram.png
+//   My-Page/Child-Page.md
+//   My-Page/Tasks.csv
+//   My-Page/Tasks/Row.md
+struct Exporter<'a> {
+    client: &'a NotionClient,
+    // Pages and databases written so far, by ID
+    exported: HashMap<String, PathBuf>,
+    // Those being exported, so a page is never entered twice
+    in_progress: HashSet<String>,
+    used_paths: HashSet<PathBuf>,
+    report: ExportReport,
+    // Used to show people by name; None if the integration cannot list users
+    directory: Option<Arc<Directory>>,
+}
+
+// Filesystem-safe version of a title
+fn slugify(title: &str) -> String {
+    let slug = title.split_whitespace()
+        .map(|word| word.chars().filter(|c| c.is_alphanumeric() || *c == '_' || *c == '-').collect::<String>())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    if slug.is_empty() { "Untitled".to_string() } else { slug.chars().take(80).collect() }
+}
+
+// Path of `target` relative to the directory `from`, using forward slashes
+fn relative_link(from: &Path, target: &Path) -> String {
+    target.strip_prefix(from)
+        .unwrap_or(target)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn file_name_from_url(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let name = path.rsplit('/').next().unwrap_or("file");
+    let name: String = name.chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '.' | '-' | '_'))
+        .collect();
+    if name.is_empty() { "file".to_string() } else { name }
+}
+
+fn link_block(id: &Value, label: String, link: String) -> Value {
+    json!({
+        "id": id,
+        "type": "paragraph",
+        "paragraph": {
+            "rich_text": [{
+                "type": "text",
+                "plain_text": label,
+                "href": link
+            }]
+        }
+    })
+}
+
+// JSON scalars and flow collections are valid YAML, so front matter is written
+// as one JSON-encoded value per key
+fn front_matter(page: &Value, properties: &Map<String, Value>) -> String {
+    let mut lines = vec!["---".to_string()];
+    for key in ["id", "url", "created_time", "last_edited_time", "parent"] {
+        lines.push(format!("{}: {}", key, page[key]));
+    }
+    lines.push(format!("title: {}", json!(page_title(page))));
+    if !properties.is_empty() {
+        lines.push("properties:".to_string());
+        for (name, value) in properties {
+            lines.push(format!("  {}: {}", json!(name), value));
+        }
+    }
+    lines.push("---".to_string());
+    lines.join("\n")
+}
+
+impl<'a> Exporter<'a> {
+    // Reserve a file path in `dir`, disambiguating duplicate titles with the ID
+    fn reserve_path(&mut self, dir: &Path, title: &str, id: &str, extension: &str) -> PathBuf {
+        let slug = slugify(title);
+        let mut path = dir.join(format!("{}.{}", slug, extension));
+        if self.used_paths.contains(&path) {
+            let short_id: String = id.replace('-', "").chars().take(8).collect();
+            path = dir.join(format!("{}-{}.{}", slug, short_id, extension));
+        }
+        self.used_paths.insert(path.clone());
+        path
+    }
+
+    async fn download_asset(&mut self, url: &str, assets_dir: &Path) -> Option<PathBuf> {
+        match self.client.download(url).await {
+            Ok(bytes) => {
+                let path = assets_dir.join(format!("{}-{}", self.report.files + 1, file_name_from_url(url)));
+                let written = fs::create_dir_all(assets_dir).and_then(|_| fs::write(&path, bytes));
+                match written {
+                    Ok(_) => {
+                        self.report.files += 1;
+                        Some(path)
+                    }
+                    Err(e) => {
+                        warn!("Could not write {}: {}", path.display(), e);
+                        self.report.failed.push(url.to_string());
+                        None
+                    }
+                }
+            }
+            Err(e) => {
+                warn!("Could not download {}: {}", url, e);
+                self.report.failed.push(url.to_string());
+                None
+            }
+        }
+    }
+
+    // Download attachments and export child pages and databases, rewriting the
+    // blocks so the rendered Markdown links to the local copies
+    fn rewrite_blocks<'b>(&'b mut self, blocks: &'b mut [Value], page_dir: &'b Path, base_dir: &'b Path) -> ExportFuture<'b, ()> {
+        Box::pin(async move {
+            for block in blocks.iter_mut() {
+                let block_type = block["type"].as_str().unwrap_or("").to_string();
+                let id = block["id"].as_str().unwrap_or("").to_string();
+
+                match block_type.as_str() {
+                    "image" | "file" | "pdf" | "video" | "audio" if block[&block_type]["type"] == "file" => {
+                        let url = block[&block_type]["file"]["url"].as_str().unwrap_or("").to_string();
+                        if let Some(path) = self.download_asset(&url, &page_dir.join(ASSETS_DIR)).await {
+                            block[&block_type]["file"]["url"] = json!(relative_link(base_dir, &path));
+                        }
+                    }
+                    "child_page" => {
+                        let title = block["child_page"]["title"].as_str().unwrap_or("").to_string();
+                        match self.client.get_page_object(&id).await {
+                            Ok(child) => {
+                                if let Some(path) = self.export_page(child, page_dir.to_path_buf()).await {
+                                    *block = link_block(&block["id"], format!("📄 {}", title), relative_link(base_dir, &path));
+                                }
+                            }
+                            Err(e) => {
+                                warn!("Could not export child page {}: {}", id, e);
+                                self.report.failed.push(id);
+                            }
+                        }
+                    }
+                    "child_database" => {
+                        let title = block["child_database"]["title"].as_str().unwrap_or("").to_string();
+                        if let Some(path) = self.export_database(id, page_dir.to_path_buf()).await {
+                            *block = link_block(&block["id"], format!("🗂️ {}", title), relative_link(base_dir, &path));
+                        }
+                    }
+                    _ => {}
+                }
+
+                if let Some(children) = block.get_mut("children").and_then(|c| c.as_array_mut()) {
+                    self.rewrite_blocks(children, page_dir, base_dir).await?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    // Export a page and everything below it. Returns the Markdown file path,
+    // or None if the page could not be exported.
+    fn export_page(&mut self, mut page: Value, dir: PathBuf) -> Pin<Box<dyn Future<Output = Option<PathBuf>> + Send + '_>> {
+        Box::pin(async move {
+            let id = page["id"].as_str().unwrap_or("").to_string();
+            if let Some(path) = self.exported.get(&id) {
+                return Some(path.clone());
+            }
+            if !self.in_progress.insert(id.clone()) {
+                return None;
+            }
+
+            if let Some(directory) = &self.directory {
+                directory.resolve_page(&mut page);
+            }
+            let title = page_title(&page);
+            let md_path = self.reserve_path(&dir, &title, &id, "md");
+            let page_dir = md_path.with_extension("");
+
+            let mut blocks = match self.client.get_block_tree(&id).await {
+                Ok(blocks) => blocks,
+                Err(e) => {
+                    warn!("Could not export page {}: {}", id, e);
+                    self.report.failed.push(id);
+                    return None;
+                }
+            };
+
+            if let Err(e) = self.rewrite_blocks(&mut blocks, &page_dir, &dir).await {
+                warn!("Could not export children of page {}: {}", id, e);
+                self.report.failed.push(id.clone());
+            }
+
+            // Attachments in files properties are downloaded like file blocks
+            let mut properties = Map::new();
+            if let Some(props) = page["properties"].as_object() {
+                for (name, property) in props {
+                    if property["type"] == "title" {
+                        continue;
+                    }
+                    let mut value = property_to_plain_json(property);
+                    if property["type"] == "files" {
+                        let mut links = Vec::new();
+                        for file in property["files"].as_array().into_iter().flatten() {
+                            let url = crate::markdown::file_url(file).unwrap_or("").to_string();
+                            if file["type"] == "file" {
+                                if let Some(path) = self.download_asset(&url, &page_dir.join(ASSETS_DIR)).await {
+                                    links.push(json!(relative_link(&dir, &path)));
+                                    continue;
+                                }
+                            }
+                            links.push(json!(url));
+                        }
+                        value = json!(links);
+                    }
+                    properties.insert(name.clone(), value);
+                }
+            }
+
+            let document = format!(
+                "{}\n\n# {}\n\n{}\n",
+                front_matter(&page, &properties),
+                title,
+                blocks_to_markdown(&blocks)
+            );
+
+            let written = fs::create_dir_all(&dir).and_then(|_| fs::write(&md_path, document));
+            if let Err(e) = written {
+                warn!("Could not write {}: {}", md_path.display(), e);
+                self.report.failed.push(id);
+                return None;
+            }
+
+            self.exported.insert(id, md_path.clone());
+            self.report.pages += 1;
+            Some(md_path)
+        })
+    }
+
+    // Export a database as a CSV of flattened property values plus one
+    // Markdown file per row. Returns the CSV path.
+    fn export_database(&mut self, database_id: String, dir: PathBuf) -> Pin<Box<dyn Future<Output = Option<PathBuf>> + Send + '_>> {
+        Box::pin(async move {
+            if let Some(path) = self.exported.get(&database_id) {
+                return Some(path.clone());
+            }
+            if !self.in_progress.insert(database_id.clone()) {
+                return None;
+            }
+
+            let fetched = match self.client.get_database(&database_id).await {
+                Ok(database) => self.client.query_database_pages(&database_id, None).await
+                    .map(|rows| (database, rows)),
+                Err(e) => Err(e),
+            };
+            let (database, rows) = match fetched {
+                Ok(fetched) => fetched,
+                Err(e) => {
+                    warn!("Could not export database {}: {}", database_id, e);
+                    self.report.failed.push(database_id);
+                    return None;
+                }
+            };
+
+            let title = page_title(&database);
+            let csv_path = self.reserve_path(&dir, &title, &database_id, "csv");
+            let rows_dir = csv_path.with_extension("");
+
+            // Title column first, then the rest of the schema
+            let schema = database["properties"].as_object().cloned().unwrap_or_default();
+            let mut columns: Vec<String> = schema.iter()
+                .filter(|(_, p)| p["type"] == "title")
+                .map(|(name, _)| name.clone())
+                .collect();
+            columns.extend(schema.iter().filter(|(_, p)| p["type"] != "title").map(|(name, _)| name.clone()));
+
+            let mut records = Vec::new();
+            for mut row in rows {
+                if let Some(directory) = &self.directory {
+                    directory.resolve_page(&mut row);
+                }
+                let row_id = row["id"].as_str().unwrap_or("").to_string();
+                let mut record: Vec<String> = columns.iter()
+                    .map(|column| property_to_text(&row["properties"][column]))
+                    .collect();
+                let file = self.export_page(row, rows_dir.clone()).await
+                    .map(|path| relative_link(&dir, &path))
+                    .unwrap_or_default();
+                record.push(row_id);
+                record.push(file);
+                records.push(record);
+            }
+
+            let written = fs::create_dir_all(&dir)
+                .map_err(csv::Error::from)
+                .and_then(|_| {
+                    let mut writer = csv::Writer::from_path(&csv_path)?;
+                    let mut header = columns.clone();
+                    header.push("notion_id".to_string());
+                    header.push("file".to_string());
+                    writer.write_record(&header)?;
+                    for record in &records {
+                        writer.write_record(record)?;
+                    }
+                    writer.flush()?;
+                    Ok(())
+                });
+            if let Err(e) = written {
+                warn!("Could not write {}: {}", csv_path.display(), e);
+                self.report.failed.push(database_id);
+                return None;
+            }
+
+            self.exported.insert(database_id, csv_path.clone());
+            self.report.databases += 1;
+            Some(csv_path)
+        })
+    }
+}
+
+// Export a page subtree, a database, or (without a root ID) every top-level
+// page and database shared with the integration into `output_dir`
+pub async fn export(client: &NotionClient, root_id: Option<&str>, output_dir: &Path) -> NotionResult<ExportReport> {
+    fs::create_dir_all(output_dir)?;
+
+    let mut exporter = Exporter {
+        client,
+        exported: HashMap::new(),
+        in_progress: HashSet::new(),
+        used_paths: HashSet::new(),
+        directory: client.directory().await.ok(),
+        report: ExportReport {
+            output_dir: output_dir.display().to_string(),
+            ..ExportReport::default()
+        },
+    };
+
+    match root_id {
+        Some(id) => match client.get_page_object(id).await {
+            Ok(page) => {
+                exporter.export_page(page, output_dir.to_path_buf()).await;
+            }
+            Err(page_error) => {
+                // Not a page: try it as a database before giving up
+                if client.get_database(id).await.is_err() {
+                    return Err(page_error);
+                }
+                exporter.export_database(id.to_string(), output_dir.to_path_buf()).await;
+            }
+        },
+        None => {
+            let mut objects = Vec::new();
+            let mut cursor: Option<String> = None;
+            loop {
+                let response = client.search_with_cursor("", cursor.as_deref(), 100).await?;
+                objects.extend(response.results);
+                match response.next_cursor {
+                    Some(next) if response.has_more => cursor = Some(next),
+                    _ => break,
+                }
+            }
+
+            // Roots are objects whose parent was not shared with the integration
+            let shared: HashSet<String> = objects.iter()
+                .filter_map(|o| o["id"].as_str().map(|s| s.to_string()))
+                .collect();
+            for object in objects {
+                let parent = &object["parent"];
+                let parent_id = parent[parent["type"].as_str().unwrap_or("")].as_str().unwrap_or("");
+                if shared.contains(parent_id) {
+                    continue;
+                }
+                let id = object["id"].as_str().unwrap_or("").to_string();
+                if object["object"] == "database" {
+                    exporter.export_database(id, output_dir.to_path_buf()).await;
+                } else {
+                    exporter.export_page(object, output_dir.to_path_buf()).await;
+                }
+            }
+        }
+    }
+
+    info!(
+        "Export finished: {} pages, {} databases, {} files, {} failures",
+        exporter.report.pages, exporter.report.databases, exporter.report.files, exporter.report.failed.len()
+    );
+    Ok(exporter.report)
+}
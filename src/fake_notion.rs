@@ -0,0 +1,80 @@
+// A local stand-in for the Notion API, for tests that exercise code talking
+// to Notion through a real NotionClient
+use crate::notion::NotionClient;
+use actix_web::dev::ServerHandle;
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+
+// (method, path below the API base, JSON body or null)
+pub type Request = (String, String, Value);
+
+// Answers a request with a JSON body, or None for Notion's 404
+type Responder = dyn Fn(&str, &str, &Value) -> Option<Value> + Send + Sync;
+
+pub struct FakeNotion {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+    handle: ServerHandle,
+}
+
+impl FakeNotion {
+    pub fn start(respond: impl Fn(&str, &str, &Value) -> Option<Value> + Send + Sync + 'static) -> Self {
+        let respond: Arc<Responder> = Arc::new(respond);
+        let requests: Arc<Mutex<Vec<Request>>> = Arc::default();
+        let recorded = requests.clone();
+        let server = HttpServer::new(move || {
+            let respond = respond.clone();
+            let recorded = recorded.clone();
+            App::new().default_service(web::to(move |req: HttpRequest, body: web::Bytes| {
+                let respond = respond.clone();
+                let recorded = recorded.clone();
+                async move {
+                    let method = req.method().to_string();
+                    let path = req.path().trim_start_matches('/').to_string();
+                    let body: Value = serde_json::from_slice(&body).unwrap_or_default();
+                    recorded.lock().unwrap().push((method.clone(), path.clone(), body.clone()));
+                    match respond(&method, &path, &body) {
+                        Some(value) => HttpResponse::Ok().json(value),
+                        None => HttpResponse::NotFound().json(json!({
+                            "object": "error",
+                            "status": 404,
+                            "code": "object_not_found"
+                        })),
+                    }
+                }
+            }))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let url = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+        Self { url, requests, handle }
+    }
+
+    // A client sending every request here, without rate limiting
+    pub fn client(&self) -> NotionClient {
+        NotionClient::new("ntn_test".to_string())
+            .with_base_url(self.url.clone())
+            .with_rate_limit(1000.0)
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    // Bodies of the requests with this method and path
+    pub fn bodies(&self, method: &str, path: &str) -> Vec<Value> {
+        self.requests().into_iter()
+            .filter(|(m, p, _)| m == method && p == path)
+            .map(|(_, _, body)| body)
+            .collect()
+    }
+
+    pub async fn stop(self) {
+        self.handle.stop(false).await;
+    }
+}
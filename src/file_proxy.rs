@@ -0,0 +1,350 @@
+use crate::error::{NotionMcpError, NotionResult};
+use crate::ids::normalize_id;
+use crate::notion::NotionClient;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use log::{debug, warn};
+use ring::hmac;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Cached bytes are refetched after this long, in case the file was replaced
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+// Signed URLs are reused until shortly before they expire
+const URL_REUSE: Duration = Duration::from_secs(45 * 60);
+// How long a signed proxy link works by default
+pub const DEFAULT_LINK_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+// Link expiries are rounded up to this, so a link stays the same for a day
+const LINK_EXPIRY_STEP: u64 = 24 * 60 * 60;
+
+// Percent-encode a path segment
+fn encode_segment(segment: &str) -> String {
+    segment.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// Reverse of encode_segment; None for malformed escapes or non-UTF-8 text
+fn decode_segment(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+// Signs proxy links so they open without credentials, e.g. as `<img src>`
+// in a site built from query results. A signature covers the file, the
+// workspace, the caller the link was issued to and an expiry time.
+pub struct UrlSigner {
+    key: hmac::Key,
+    ttl: Duration,
+}
+
+impl fmt::Debug for UrlSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UrlSigner").field("ttl", &self.ttl).finish_non_exhaustive()
+    }
+}
+
+impl UrlSigner {
+    pub fn new(secret: &[u8], ttl: Duration) -> Self {
+        Self { key: hmac::Key::new(hmac::HMAC_SHA256, secret), ttl }
+    }
+
+    // A secret that lasts until the server restarts, and its links with it
+    pub fn random(ttl: Duration) -> Self {
+        let mut secret = [0u8; 32];
+        OsRng.fill_bytes(&mut secret);
+        Self::new(&secret, ttl)
+    }
+
+    fn message(path: &str, workspace: Option<&str>, caller: Option<&str>, expires: u64) -> String {
+        format!("{}\n{}\n{}\n{}", path, workspace.unwrap_or(""), caller.unwrap_or(""), expires)
+    }
+
+    // Query parameters that sign a link to `path`
+    fn sign(&self, path: &str, workspace: Option<&str>, caller: Option<&str>, now: u64) -> Vec<(&'static str, String)> {
+        let expires = (now + self.ttl.as_secs()).div_ceil(LINK_EXPIRY_STEP) * LINK_EXPIRY_STEP;
+        let tag = hmac::sign(&self.key, Self::message(path, workspace, caller, expires).as_bytes());
+        let mut params = vec![("expires", expires.to_string())];
+        if let Some(caller) = caller {
+            params.push(("as", caller.to_string()));
+        }
+        params.push(("signature", URL_SAFE_NO_PAD.encode(tag.as_ref())));
+        params
+    }
+
+    // For a link with a valid, unexpired signature, the caller it was issued
+    // to (None when issued to nobody in particular)
+    pub fn verify(&self, path: &str, query: &str, now: u64) -> Option<Option<String>> {
+        let mut params = HashMap::new();
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (name, value) = pair.split_once('=')?;
+            // A repeated parameter could mean something else to the handler
+            if params.insert(name, decode_segment(value)?).is_some() {
+                return None;
+            }
+        }
+        let expires: u64 = params.get("expires")?.parse().ok()?;
+        let signature = URL_SAFE_NO_PAD.decode(params.get("signature")?).ok()?;
+        if expires < now {
+            return None;
+        }
+        let caller = params.get("as").cloned();
+        let message = Self::message(path, params.get("workspace").map(|w| w.as_str()), caller.as_deref(), expires);
+        hmac::verify(&self.key, message.as_bytes(), &signature).ok()?;
+        Some(caller)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// Stable proxy URL for the `index`-th file of a files property. With a
+// signer the link also works without credentials, acting for `caller`.
+pub fn proxy_url(
+    base_url: &str,
+    page_id: &str,
+    property: &str,
+    index: usize,
+    workspace: Option<&str>,
+    signer: Option<&UrlSigner>,
+    caller: Option<&str>,
+) -> String {
+    let path = format!("/files/{}/{}/{}", page_id.replace('-', ""), encode_segment(property), index);
+    let mut params: Vec<(&str, String)> = workspace.map(|w| ("workspace", w.to_string())).into_iter().collect();
+    if let Some(signer) = signer {
+        params.extend(signer.sign(&path, workspace, caller, unix_now()));
+    }
+    let query: Vec<String> = params.iter().map(|(name, value)| format!("{}={}", name, encode_segment(value))).collect();
+    let url = format!("{}{}", base_url.trim_end_matches('/'), path);
+    if query.is_empty() {
+        url
+    } else {
+        format!("{}?{}", url, query.join("&"))
+    }
+}
+
+// A file of a page property, with a URL that is valid right now
+#[derive(Debug, Clone)]
+pub struct ResolvedFile {
+    pub url: String,
+    pub filename: String,
+    // Notion-hosted files have expiring URLs; external ones do not
+    pub hosted: bool,
+}
+
+// Serves files by page, property and position, so that links survive the
+// expiry of Notion's signed URLs. Bytes can optionally be cached on disk.
+#[derive(Debug, Default)]
+pub struct FileProxy {
+    cache_dir: Option<PathBuf>,
+    urls: Mutex<HashMap<String, (SystemTime, ResolvedFile)>>,
+    signer: Option<Arc<UrlSigner>>,
+}
+
+impl FileProxy {
+    pub fn new(cache_dir: Option<PathBuf>) -> Self {
+        Self { cache_dir, urls: Mutex::new(HashMap::new()), signer: None }
+    }
+
+    // Accept links signed with this signer in place of credentials
+    pub fn with_signer(mut self, signer: Option<Arc<UrlSigner>>) -> Self {
+        self.signer = signer;
+        self
+    }
+
+    // The caller a signed link acts for; see UrlSigner::verify
+    pub fn verify_link(&self, path: &str, query: &str) -> Option<Option<String>> {
+        self.signer.as_ref()?.verify(path, query, unix_now())
+    }
+
+    // `partition` keeps apart files fetched with different Notion accounts,
+    // so nobody is served a file they could not open themselves
+    fn key(partition: &str, page_id: &str, property: &str, index: usize) -> String {
+        format!("{}/{}/{}/{}", partition, page_id.replace('-', ""), property, index)
+    }
+
+    // Directory holding the cached copy of one file
+    fn cache_entry(&self, partition: &str, page_id: &str, property: &str, index: usize) -> Option<PathBuf> {
+        let hex = |s: &str| s.bytes().map(|b| format!("{:02x}", b)).collect::<String>();
+        self.cache_dir.as_ref()
+            .map(|dir| dir.join(hex(partition)).join(page_id.replace('-', "")).join(hex(property)).join(index.to_string()))
+    }
+
+    // A fresh cached copy, as (path, filename)
+    pub fn cached(&self, partition: &str, page_id: &str, property: &str, index: usize) -> Option<(PathBuf, String)> {
+        let entry = self.cache_entry(partition, page_id, property, index)?;
+        let path = fs::read_dir(&entry).ok()?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .find(|p| p.is_file())?;
+        let age = fs::metadata(&path).and_then(|m| m.modified()).ok()?.elapsed().ok()?;
+        if age > CACHE_TTL {
+            return None;
+        }
+        let filename = path.file_name()?.to_string_lossy().to_string();
+        Some((path, filename))
+    }
+
+    pub fn caches_bytes(&self) -> bool {
+        self.cache_dir.is_some()
+    }
+
+    // Keep a downloaded copy; failures only cost a later re-download
+    pub fn store(&self, partition: &str, page_id: &str, property: &str, index: usize, filename: &str, data: &[u8]) {
+        let Some(entry) = self.cache_entry(partition, page_id, property, index) else { return };
+        let _ = fs::remove_dir_all(&entry);
+        let safe_name: String = filename.chars()
+            .map(|c| if c == '/' || c == '\\' { '_' } else { c })
+            .collect();
+        let written = fs::create_dir_all(&entry).and_then(|_| fs::write(entry.join(safe_name), data));
+        if let Err(e) = written {
+            warn!("Could not cache file {}: {}", Self::key(partition, page_id, property, index), e);
+        }
+    }
+
+    // Look up the current URL of a file, reusing signed URLs that are still valid
+    pub async fn resolve(&self, client: &NotionClient, partition: &str, page_id: &str, property: &str, index: usize) -> NotionResult<ResolvedFile> {
+        let page_id = normalize_id(page_id)?;
+        let key = Self::key(partition, &page_id, property, index);
+
+        if let Some((resolved_at, file)) = self.urls.lock().unwrap().get(&key) {
+            if resolved_at.elapsed().is_ok_and(|age| age < URL_REUSE) {
+                return Ok(file.clone());
+            }
+        }
+
+        let page = client.get_page_object(&page_id).await?;
+        let files = page["properties"][property]["files"].as_array()
+            .ok_or_else(|| NotionMcpError::ResourceNotFound(format!("Page {} has no files property '{}'", page_id, property)))?;
+        let file: &Value = files.get(index)
+            .ok_or_else(|| NotionMcpError::ResourceNotFound(format!("'{}' has no file at position {}", property, index)))?;
+
+        let url = crate::markdown::file_url(file)
+            .ok_or_else(|| NotionMcpError::ResourceNotFound(format!("File {} of '{}' has no URL", index, property)))?
+            .to_string();
+        let filename = file["name"].as_str()
+            .filter(|n| !n.is_empty())
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| url.split('?').next().unwrap_or("").rsplit('/').next().unwrap_or("file").to_string());
+
+        let resolved = ResolvedFile { url, filename, hosted: file["type"] == "file" };
+        debug!("Resolved {} to a fresh URL", key);
+        self.urls.lock().unwrap().insert(key, (SystemTime::now(), resolved.clone()));
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{self, AuthConfig, Principal};
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::{web, App, HttpMessage, HttpRequest, HttpResponse};
+
+    const PAGE: &str = "1a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d";
+    const NOW: u64 = 1_714_558_800;
+
+    fn signer() -> UrlSigner {
+        UrlSigner::new(b"0123456789abcdef0123456789abcdef", Duration::from_secs(3600))
+    }
+
+    // Path and query of a signed link
+    fn link(workspace: Option<&str>, caller: Option<&str>) -> (String, String) {
+        let url = proxy_url("https://mcp.example.com/", PAGE, "Fotos del día", 1, workspace, Some(&signer()), caller);
+        let rest = url.strip_prefix("https://mcp.example.com").unwrap();
+        let (path, query) = rest.split_once('?').unwrap();
+        (path.to_string(), query.to_string())
+    }
+
+    #[test]
+    fn unsigned_links_keep_their_stable_form() {
+        assert_eq!(
+            proxy_url("https://mcp.example.com/", PAGE, "Fotos del día", 1, Some("ops"), None, None),
+            "https://mcp.example.com/files/1a2b3c4d5e6f4a1b8c2d3e4f5a6b7c8d/Fotos%20del%20d%C3%ADa/1?workspace=ops"
+        );
+    }
+
+    #[test]
+    fn signed_links_verify_until_they_expire() {
+        let (path, query) = link(Some("ops"), Some("key:web"));
+        assert_eq!(decode_segment("Fotos%20del%20d%C3%ADa").as_deref(), Some("Fotos del día"));
+        let signer = signer();
+        assert_eq!(signer.verify(&path, &query, unix_now()), Some(Some("key:web".to_string())));
+        assert_eq!(signer.verify(&path, &query, unix_now() + 2 * LINK_EXPIRY_STEP), None);
+        let (path, query) = link(None, None);
+        assert_eq!(signer.verify(&path, &query, unix_now()), Some(None));
+    }
+
+    #[test]
+    fn expiry_is_rounded_so_links_stay_stable() {
+        let signer = signer();
+        let first = signer.sign("/files/a/b/0", None, None, NOW);
+        let later = signer.sign("/files/a/b/0", None, None, NOW + 60);
+        assert_eq!(first, later);
+    }
+
+    #[test]
+    fn tampered_links_are_rejected() {
+        let signer = signer();
+        let (path, query) = link(Some("ops"), Some("key:web"));
+        let now = unix_now();
+        assert_eq!(signer.verify(&path.replace("/1", "/2"), &query, now), None);
+        assert_eq!(signer.verify(&path, &query.replace("key%3Aweb", "key%3Aops"), now), None);
+        assert_eq!(signer.verify(&path, &query.replace("workspace=ops", "workspace=default"), now), None);
+        assert_eq!(signer.verify(&path, &format!("{}&as=key%3Aops", query), now), None);
+        let other = UrlSigner::new(b"another secret of thirty-two byte", Duration::from_secs(3600));
+        assert_eq!(other.verify(&path, &query, now), None);
+    }
+
+    async fn whoami(req: HttpRequest) -> HttpResponse {
+        let name = req.extensions().get::<Principal>().map(|p| p.name.clone()).unwrap_or_default();
+        HttpResponse::Ok().body(name)
+    }
+
+    #[actix_web::test]
+    async fn signed_links_open_without_an_api_key() {
+        let auth = AuthConfig::parse("web:0123456789abcdef:read").unwrap();
+        let proxy = FileProxy::new(None).with_signer(Some(Arc::new(signer())));
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(Arc::new(auth)))
+                .app_data(web::Data::new(Arc::new(proxy)))
+                .wrap(from_fn(auth::require_api_key))
+                .route("/files/{page_id}/{property}/{index}", web::get().to(whoami)),
+        ).await;
+
+        let (path, query) = link(None, Some("key:web"));
+        let signed = TestRequest::get().uri(&format!("{}?{}", path, query)).to_request();
+        let response = call_service(&app, signed).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(read_body(response).await, "key:web");
+
+        let unsigned = TestRequest::get().uri(&path).to_request();
+        assert_eq!(call_service(&app, unsigned).await.status(), 401);
+        let tampered = TestRequest::get().uri(&format!("{}?{}", path.replace("/1", "/0"), query)).to_request();
+        assert_eq!(call_service(&app, tampered).await.status(), 401);
+    }
+}
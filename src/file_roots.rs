@@ -0,0 +1,112 @@
+use crate::error::{NotionMcpError, NotionResult};
+use std::env;
+use std::path::{Component, Path, PathBuf};
+
+// A server directory that file tools may use. Callers give paths relative
+// to it and cannot leave it, through `..` or through symlinks.
+#[derive(Debug, Clone)]
+pub struct FileRoot {
+    // Variable that configures the root, named in errors
+    variable: &'static str,
+    root: Option<PathBuf>,
+}
+
+impl FileRoot {
+    pub fn new(variable: &'static str, root: Option<PathBuf>) -> Self {
+        Self { variable, root }
+    }
+
+    pub fn from_env(variable: &'static str) -> Self {
+        Self::new(variable, env::var(variable).ok().filter(|v| !v.is_empty()).map(PathBuf::from))
+    }
+
+    // Absolute path of `relative` under the root. The path itself may not
+    // exist yet, but whatever part of it exists must lie inside the root.
+    pub fn resolve(&self, relative: &str) -> NotionResult<PathBuf> {
+        let root = self.root.as_ref().ok_or_else(|| NotionMcpError::InvalidParams(format!(
+            "This tool is disabled; set {} to the directory it may use", self.variable
+        )))?;
+        let invalid = || NotionMcpError::InvalidParams(format!(
+            "'{}' must be a relative path inside {}", relative, self.variable
+        ));
+
+        let relative_path = Path::new(relative);
+        if !relative_path.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return Err(invalid());
+        }
+
+        let root = root.canonicalize()
+            .map_err(|e| NotionMcpError::Server(format!("{} ({}) is not usable: {}", self.variable, root.display(), e)))?;
+        let path = root.join(relative_path);
+        let existing = path.ancestors()
+            .find(|ancestor| ancestor.symlink_metadata().is_ok())
+            .unwrap_or(&root)
+            .canonicalize()?;
+        if !existing.starts_with(&root) {
+            return Err(invalid());
+        }
+        Ok(path)
+    }
+}
+
+// Where file tools may read and write
+#[derive(Debug, Clone)]
+pub struct FileRoots {
+    pub export: FileRoot,
+    pub import: FileRoot,
+    pub uploads: FileRoot,
+}
+
+impl FileRoots {
+    pub fn from_env() -> Self {
+        Self {
+            export: FileRoot::from_env("MCP_EXPORT_DIR"),
+            import: FileRoot::from_env("MCP_IMPORT_DIR"),
+            uploads: FileRoot::from_env("MCP_UPLOAD_DIR"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn root(name: &str) -> (PathBuf, FileRoot) {
+        let dir = env::temp_dir().join(format!("notion-mcp-file-roots-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("inside")).unwrap();
+        let root = FileRoot::new("MCP_TEST_DIR", Some(dir.clone()));
+        (dir, root)
+    }
+
+    #[test]
+    fn resolves_relative_paths_inside_the_root() {
+        let (dir, root) = root("relative");
+        let dir = dir.canonicalize().unwrap();
+        assert_eq!(root.resolve("inside/new/file.md").unwrap(), dir.join("inside/new/file.md"));
+        assert_eq!(root.resolve(".").unwrap(), dir.join("."));
+    }
+
+    #[test]
+    fn rejects_absolute_and_parent_paths() {
+        let (_, root) = root("escape");
+        assert!(root.resolve("/etc/passwd").is_err());
+        assert!(root.resolve("../outside").is_err());
+        assert!(root.resolve("inside/../../outside").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_leaving_the_root() {
+        let (dir, root) = root("symlink");
+        std::os::unix::fs::symlink(env::temp_dir(), dir.join("link")).unwrap();
+        assert!(root.resolve("link/file").is_err());
+    }
+
+    #[test]
+    fn disabled_without_a_root() {
+        let root = FileRoot::new("MCP_TEST_DIR", None);
+        assert!(root.resolve("file").is_err());
+    }
+}
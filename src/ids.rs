@@ -0,0 +1,132 @@
+use crate::error::{NotionMcpError, NotionResult};
+
+const ID_LENGTH: usize = 32;
+// Hosts of Notion links, including their subdomains (www, workspace sites)
+const NOTION_HOSTS: &[&str] = &["notion.so", "notion.site", "notion.com"];
+
+// Format 32 hex digits as a dashed UUID (8-4-4-4-12)
+fn to_dashed(hex: &str) -> String {
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+// A bare ID (32 hex digits) or a dashed UUID, and nothing else
+fn exact_id(value: &str) -> Option<String> {
+    let hex = if value.len() == ID_LENGTH + 4 {
+        let dashes_in_place = value.char_indices()
+            .all(|(i, c)| (c == '-') == matches!(i, 8 | 13 | 18 | 23));
+        if !dashes_in_place {
+            return None;
+        }
+        value.replace('-', "")
+    } else {
+        value.to_string()
+    };
+
+    if hex.len() == ID_LENGTH && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(to_dashed(&hex.to_lowercase()))
+    } else {
+        None
+    }
+}
+
+// The ID a URL path segment ends in: the whole segment, or the 32 hex
+// digits after a title slug such as "My-Page-1a2b..."
+fn segment_id(segment: &str) -> Option<String> {
+    if let Some(id) = exact_id(segment) {
+        return Some(id);
+    }
+    let slug_end = segment.len().checked_sub(ID_LENGTH)?;
+    let slug = segment.get(..slug_end)?;
+    if !slug.ends_with('-') {
+        return None;
+    }
+    exact_id(&segment[slug_end..])
+}
+
+fn is_notion_host(host: &str) -> bool {
+    let host = host.to_lowercase();
+    NOTION_HOSTS.iter().any(|known| host == *known || host.ends_with(&format!(".{}", known)))
+}
+
+fn invalid(input: &str) -> NotionMcpError {
+    NotionMcpError::InvalidParams(format!("'{}' is not a Notion ID or URL", input))
+}
+
+// Turn a raw ID, a dashed UUID or a Notion URL into a canonical dashed ID.
+// For URLs the ID is taken from the last path segment, so database view
+// links (`?v=...`) resolve to the database and block anchors are ignored.
+pub fn normalize_id(input: &str) -> NotionResult<String> {
+    let trimmed = input.trim();
+    if let Some(id) = exact_id(trimmed) {
+        return Ok(id);
+    }
+
+    let url = trimmed.strip_prefix("https://")
+        .or_else(|| trimmed.strip_prefix("http://"))
+        .unwrap_or(trimmed);
+    let without_fragment = url.split('#').next().unwrap_or("");
+    let without_query = without_fragment.split('?').next().unwrap_or("");
+    let (host, path) = without_query.split_once('/').ok_or_else(|| invalid(input))?;
+    if !is_notion_host(host) {
+        return Err(invalid(input));
+    }
+    let segment = path.trim_end_matches('/').rsplit('/').next().unwrap_or("");
+
+    segment_id(segment).ok_or_else(|| invalid(input))
+}
+
+// Like normalize_id, but a block anchor (`...#1a2b...`) wins over the page,
+// so a link to a block targets that block
+pub fn normalize_block_id(input: &str) -> NotionResult<String> {
+    match input.trim().split_once('#') {
+        Some((_, anchor)) => match exact_id(anchor) {
+            Some(id) => Ok(id),
+            None => normalize_id(input),
+        },
+        None => normalize_id(input),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DASHED: &str = "1a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d";
+
+    #[test]
+    fn accepts_bare_and_dashed_ids() {
+        assert_eq!(normalize_id("1a2b3c4d5e6f4a1b8c2d3e4f5a6b7c8d").unwrap(), DASHED);
+        assert_eq!(normalize_id(" 1A2B3C4D-5E6F-4A1B-8C2D-3E4F5A6B7C8D ").unwrap(), DASHED);
+    }
+
+    #[test]
+    fn takes_the_id_from_notion_urls() {
+        assert_eq!(normalize_id("https://www.notion.so/team/My-Page-1a2b3c4d5e6f4a1b8c2d3e4f5a6b7c8d?pvs=4").unwrap(), DASHED);
+        assert_eq!(normalize_id("https://www.notion.so/1a2b3c4d5e6f4a1b8c2d3e4f5a6b7c8d?v=0000").unwrap(), DASHED);
+        assert_eq!(normalize_id("https://acme.notion.site/Page-1a2b3c4d5e6f4a1b8c2d3e4f5a6b7c8d/").unwrap(), DASHED);
+        assert_eq!(normalize_id("notion.so/Page-1a2b3c4d5e6f4a1b8c2d3e4f5a6b7c8d#ffff").unwrap(), DASHED);
+    }
+
+    #[test]
+    fn rejects_ids_with_anything_in_front() {
+        for input in [
+            "../../../home/x/1a2b3c4d5e6f4a1b8c2d3e4f5a6b7c8d",
+            "junk1a2b3c4d5e6f4a1b8c2d3e4f5a6b7c8d",
+            "My-Page-1a2b3c4d5e6f4a1b8c2d3e4f5a6b7c8d",
+            "1a2b-3c4d5e6f4a1b8c2d3e4f5a6b7c8d",
+            "--1a2b3c4d5e6f4a1b8c2d3e4f5a6b7c8d",
+            "https://example.com/Page-1a2b3c4d5e6f4a1b8c2d3e4f5a6b7c8d",
+            "https://www.notion.so/Pagex1a2b3c4d5e6f4a1b8c2d3e4f5a6b7c8d",
+            "https://evilnotion.so/Page-1a2b3c4d5e6f4a1b8c2d3e4f5a6b7c8d",
+        ] {
+            assert!(normalize_id(input).is_err(), "{} was accepted", input);
+        }
+    }
+
+    #[test]
+    fn block_anchors_win_over_the_page() {
+        let url = "https://www.notion.so/Page-1a2b3c4d5e6f4a1b8c2d3e4f5a6b7c8d#ffffffffffffffffffffffffffffffff";
+        assert_eq!(normalize_block_id(url).unwrap(), "ffffffff-ffff-ffff-ffff-ffffffffffff");
+        assert_eq!(normalize_id(url).unwrap(), DASHED);
+    }
+}
@@ -0,0 +1,486 @@
+use crate::error::{NotionMcpError, NotionResult};
+use crate::markdown::{markdown_to_blocks, text_to_rich_text};
+use crate::notion::{NotionClient, PageParent};
+use crate::properties::build_properties;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::future::Future;
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+
+// Notion accepts at most this many blocks in a single children array
+pub const MAX_BLOCKS_PER_REQUEST: usize = 100;
+const PROGRESS_FILE: &str = ".notion-import.json";
+// Front matter keys written by the exporter that are not database properties
+const RESERVED_KEYS: &[&str] = &["id", "url", "created_time", "last_edited_time", "parent", "title", "properties"];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct PageProgress {
+    page_id: String,
+    // Top-level block batches already appended
+    appended: usize,
+    // Top-level blocks of a batch whose nested content failed to append,
+    // deleted before the batch is appended again
+    #[serde(default)]
+    partial: Vec<String>,
+    complete: bool,
+}
+
+// Saved next to the imported files so an interrupted import can be resumed
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ImportProgress {
+    parent_id: String,
+    pages: BTreeMap<String, PageProgress>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportFailure {
+    pub path: String,
+    pub error: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub created: usize,
+    pub completed: usize,
+    pub already_imported: usize,
+    // Relative file path -> Notion page ID
+    pub pages: BTreeMap<String, String>,
+    pub failed: Vec<ImportFailure>,
+    pub progress_file: String,
+}
+
+struct ImportItem {
+    // Path relative to the source directory, with forward slashes. Folders
+    // without a matching Markdown file end in "/".
+    key: String,
+    parent_key: Option<String>,
+    title: String,
+    front_matter: Map<String, Value>,
+    body: String,
+    // Directory that relative links in the body are resolved against
+    dir: PathBuf,
+}
+
+fn relative_key(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// Resolve `.` and `..` without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+// Split optional YAML front matter from the Markdown body
+fn split_front_matter(content: &str) -> (Map<String, Value>, String) {
+    let content = content.trim_start_matches('\u{feff}');
+    if let Some(rest) = content.strip_prefix("---\n").or_else(|| content.strip_prefix("---\r\n")) {
+        // The closing delimiter is a line holding only `---`
+        let mut from = 0;
+        while let Some(found) = rest[from..].find("\n---") {
+            let end = from + found;
+            let after = &rest[end + 4..];
+            let body = if after.is_empty() {
+                Some(after)
+            } else {
+                after.strip_prefix("\r\n").or_else(|| after.strip_prefix('\n'))
+            };
+            let Some(body) = body else {
+                from = end + 4;
+                continue;
+            };
+            let yaml = &rest[..end];
+            match serde_yaml::from_str::<Value>(yaml) {
+                Ok(Value::Object(map)) => return (map, body.to_string()),
+                Ok(_) => return (Map::new(), body.to_string()),
+                Err(e) => warn!("Ignoring invalid front matter: {}", e),
+            }
+            break;
+        }
+    }
+    (Map::new(), content.to_string())
+}
+
+fn parse_file(path: &Path) -> NotionResult<(String, Map<String, Value>, String)> {
+    let content = fs::read_to_string(path)?;
+    let (front_matter, mut body) = split_front_matter(&content);
+
+    let stem = path.file_stem().map(|s| s.to_string_lossy().replace('-', " ")).unwrap_or_default();
+    let mut title = front_matter.get("title").and_then(|t| t.as_str()).map(|t| t.to_string());
+
+    // A leading "# Title" heading is the page title, not content
+    let trimmed = body.trim_start();
+    if let Some(heading) = trimmed.lines().next().and_then(|line| line.strip_prefix("# ")) {
+        if title.is_none() || title.as_deref() == Some(heading.trim()) {
+            title = Some(heading.trim().to_string());
+            body = trimmed[trimmed.lines().next().unwrap_or("").len()..].to_string();
+        }
+    }
+
+    Ok((title.unwrap_or(stem), front_matter, body))
+}
+
+// Collect Markdown files and folders, parents before children. A folder
+// next to a Markdown file of the same name holds that page's children.
+fn scan(root: &Path, dir: &Path, parent_key: Option<String>, items: &mut Vec<ImportItem>) -> NotionResult<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect();
+    entries.sort();
+
+    for path in entries.iter().filter(|p| p.is_file() && p.extension().is_some_and(|e| e == "md")) {
+        let (title, front_matter, body) = parse_file(path)?;
+        items.push(ImportItem {
+            key: relative_key(root, path),
+            parent_key: parent_key.clone(),
+            title,
+            front_matter,
+            body,
+            dir: dir.to_path_buf(),
+        });
+    }
+
+    for path in entries.iter().filter(|p| p.is_dir()) {
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        if name.starts_with('_') || name.starts_with('.') {
+            continue;
+        }
+
+        let page_file = dir.join(format!("{}.md", name));
+        let folder_parent = if page_file.is_file() {
+            relative_key(root, &page_file)
+        } else {
+            let key = format!("{}/", relative_key(root, path));
+            items.push(ImportItem {
+                key: key.clone(),
+                parent_key: parent_key.clone(),
+                title: name.replace('-', " "),
+                front_matter: Map::new(),
+                body: String::new(),
+                dir: path.clone(),
+            });
+            key
+        };
+        scan(root, path, Some(folder_parent), items)?;
+    }
+
+    Ok(())
+}
+
+// Replace relative links to imported files with page mentions; links that
+// cannot be resolved keep their text but lose the invalid URL
+fn resolve_links(value: &mut Value, root: &Path, dir: &Path, pages: &BTreeMap<String, PageProgress>) {
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                resolve_links(item, root, dir, pages);
+            }
+        }
+        Value::Object(obj) => {
+            let link = obj.get("text")
+                .and_then(|t| t["link"]["url"].as_str())
+                .map(|u| u.to_string());
+
+            if let Some(url) = link {
+                let is_relative = !url.contains("://") && !url.starts_with('#') && !url.starts_with("mailto:");
+                if is_relative {
+                    let path = url.split('#').next().unwrap_or("").replace("%20", " ");
+                    let key = relative_key(root, &normalize(&dir.join(path)));
+                    match pages.get(&key) {
+                        Some(progress) => {
+                            *value = json!({
+                                "type": "mention",
+                                "mention": {
+                                    "type": "page",
+                                    "page": { "id": progress.page_id }
+                                }
+                            });
+                        }
+                        None => {
+                            if let Some(text) = obj.get_mut("text").and_then(|t| t.as_object_mut()) {
+                                text.remove("link");
+                            }
+                        }
+                    }
+                    return;
+                }
+            }
+
+            for child in obj.values_mut() {
+                resolve_links(child, root, dir, pages);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Take the nested children out of a batch of blocks, so the batch can be
+// appended in one request and the children under the created blocks
+fn split_nested(batch: &[Value]) -> (Vec<Value>, Vec<Vec<Value>>) {
+    let mut stripped = Vec::new();
+    let mut nested = Vec::new();
+    for block in batch {
+        let mut block = block.clone();
+        let block_type = block["type"].as_str().unwrap_or("").to_string();
+        // Tables must be created together with their rows
+        let children = if block_type == "table" {
+            None
+        } else {
+            block[&block_type].as_object_mut().and_then(|data| data.remove("children"))
+        };
+        nested.push(children.and_then(|c| c.as_array().cloned()).unwrap_or_default());
+        stripped.push(block);
+    }
+    (stripped, nested)
+}
+
+async fn append_nested(client: &NotionClient, created: &[Value], nested: Vec<Vec<Value>>) -> NotionResult<()> {
+    for (block, children) in created.iter().zip(nested) {
+        if children.is_empty() {
+            continue;
+        }
+        if let Some(id) = block["id"].as_str() {
+            append_tree(client, id, children).await?;
+        }
+    }
+    Ok(())
+}
+
+// Append blocks in batches of 100. Nested children are appended afterwards
+// under the created blocks, so any depth and size is supported.
+fn append_tree<'a>(client: &'a NotionClient, parent_id: &'a str, blocks: Vec<Value>) -> Pin<Box<dyn Future<Output = NotionResult<()>> + Send + 'a>> {
+    Box::pin(async move {
+        for batch in blocks.chunks(MAX_BLOCKS_PER_REQUEST) {
+            let (stripped, nested) = split_nested(batch);
+            let created = client.append_block_children(parent_id, stripped).await?;
+            append_nested(client, &created, nested).await?;
+        }
+        Ok(())
+    })
+}
+
+// Append one top-level batch of a page. Its blocks are recorded in the
+// progress file until their nested content is in, so a retry can delete
+// them first instead of appending the batch twice.
+async fn append_batch(
+    client: &NotionClient,
+    progress: &mut ImportProgress,
+    progress_path: &Path,
+    key: &str,
+    page_id: &str,
+    batch: &[Value],
+) -> NotionResult<()> {
+    let partial = progress.pages.get(key).map(|p| p.partial.clone()).unwrap_or_default();
+    for block_id in &partial {
+        client.delete_block(block_id).await?;
+        if let Some(entry) = progress.pages.get_mut(key) {
+            entry.partial.retain(|id| id != block_id);
+        }
+        save_progress(progress_path, progress)?;
+    }
+
+    let (stripped, nested) = split_nested(batch);
+    let created = client.append_block_children(page_id, stripped).await?;
+    if nested.iter().all(|children| children.is_empty()) {
+        return Ok(());
+    }
+    if let Some(entry) = progress.pages.get_mut(key) {
+        entry.partial = created.iter().filter_map(|b| b["id"].as_str().map(|id| id.to_string())).collect();
+    }
+    save_progress(progress_path, progress)?;
+
+    append_nested(client, &created, nested).await?;
+    if let Some(entry) = progress.pages.get_mut(key) {
+        entry.partial.clear();
+    }
+    Ok(())
+}
+
+fn save_progress(path: &Path, progress: &ImportProgress) -> NotionResult<()> {
+    fs::write(path, serde_json::to_vec_pretty(progress)?)?;
+    Ok(())
+}
+
+// Import a folder of Markdown files as pages under `parent_id`. When the
+// parent is a database, front matter fields fill the matching properties.
+// Progress is saved in the folder, and running the import again resumes it.
+pub async fn import_markdown(client: &NotionClient, source_dir: &Path, parent_id: &str) -> NotionResult<ImportReport> {
+    if !source_dir.is_dir() {
+        return Err(NotionMcpError::InvalidParams(format!("{} is not a directory", source_dir.display())));
+    }
+
+    let mut items = Vec::new();
+    scan(source_dir, source_dir, None, &mut items)?;
+
+    let progress_path = source_dir.join(PROGRESS_FILE);
+    let mut progress: ImportProgress = fs::read(&progress_path).ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .filter(|p: &ImportProgress| p.parent_id == parent_id)
+        .unwrap_or_else(|| ImportProgress {
+            parent_id: parent_id.to_string(),
+            pages: BTreeMap::new(),
+        });
+
+    // A database parent gives us the schema to map front matter onto
+    let root_parent = client.detect_parent(parent_id).await?;
+    let schema = match &root_parent {
+        PageParent::Database(id) => client.get_database(id).await?["properties"].as_object().cloned(),
+        _ => None,
+    };
+    let title_property = schema.as_ref()
+        .and_then(|s| s.iter().find(|(_, p)| p["type"] == "title").map(|(name, _)| name.clone()))
+        .unwrap_or_else(|| "title".to_string());
+
+    let mut report = ImportReport {
+        progress_file: progress_path.display().to_string(),
+        ..ImportReport::default()
+    };
+
+    // First pass: create every page so links between files can be resolved
+    for item in &items {
+        if progress.pages.contains_key(&item.key) {
+            report.already_imported += 1;
+            continue;
+        }
+
+        let (parent, mut properties) = match &item.parent_key {
+            None => match &schema {
+                Some(schema) => {
+                    let mut values = item.front_matter.get("properties")
+                        .and_then(|p| p.as_object().cloned())
+                        .unwrap_or_default();
+                    for (key, value) in &item.front_matter {
+                        if !RESERVED_KEYS.contains(&key.as_str()) {
+                            values.insert(key.clone(), value.clone());
+                        }
+                    }
+                    values.remove(&title_property);
+                    let (properties, skipped) = build_properties(schema, &values);
+                    if !skipped.is_empty() {
+                        warn!("{}: ignoring fields not in the database schema: {:?}", item.key, skipped);
+                    }
+                    (root_parent.clone(), properties)
+                }
+                None => (root_parent.clone(), Map::new()),
+            },
+            Some(parent_key) => match progress.pages.get(parent_key) {
+                Some(parent) => (PageParent::Page(parent.page_id.clone()), Map::new()),
+                None => {
+                    report.failed.push(ImportFailure {
+                        path: item.key.clone(),
+                        error: format!("Parent {} was not imported", parent_key),
+                    });
+                    continue;
+                }
+            },
+        };
+
+        let title_key = if item.parent_key.is_none() { title_property.clone() } else { "title".to_string() };
+        properties.insert(title_key, json!({ "title": text_to_rich_text(&item.title) }));
+
+        match client.create_page(&parent, Value::Object(properties), None).await {
+            Ok(page) => {
+                let page_id = page["id"].as_str().unwrap_or("").to_string();
+                progress.pages.insert(item.key.clone(), PageProgress {
+                    page_id,
+                    appended: 0,
+                    partial: Vec::new(),
+                    complete: item.body.trim().is_empty(),
+                });
+                save_progress(&progress_path, &progress)?;
+                report.created += 1;
+            }
+            Err(e) => {
+                warn!("Could not create page for {}: {}", item.key, e);
+                report.failed.push(ImportFailure { path: item.key.clone(), error: e.to_string() });
+            }
+        }
+    }
+
+    // Second pass: append content in batches, resuming after the last saved batch
+    for item in &items {
+        let Some(page) = progress.pages.get(&item.key).cloned() else {
+            continue;
+        };
+        if page.complete {
+            continue;
+        }
+
+        let mut blocks = Value::Array(markdown_to_blocks(&item.body));
+        resolve_links(&mut blocks, source_dir, &item.dir, &progress.pages);
+        let blocks = blocks.as_array().cloned().unwrap_or_default();
+
+        let mut appended = page.appended;
+        let mut failed = false;
+        for batch in blocks.chunks(MAX_BLOCKS_PER_REQUEST).skip(page.appended) {
+            if let Err(e) = append_batch(client, &mut progress, &progress_path, &item.key, &page.page_id, batch).await {
+                warn!("Could not append content to {}: {}", item.key, e);
+                report.failed.push(ImportFailure { path: item.key.clone(), error: e.to_string() });
+                failed = true;
+                break;
+            }
+            appended += 1;
+            if let Some(entry) = progress.pages.get_mut(&item.key) {
+                entry.appended = appended;
+            }
+            save_progress(&progress_path, &progress)?;
+        }
+
+        if !failed {
+            if let Some(entry) = progress.pages.get_mut(&item.key) {
+                entry.complete = true;
+            }
+            save_progress(&progress_path, &progress)?;
+            report.completed += 1;
+        }
+    }
+
+    report.pages = progress.pages.iter()
+        .map(|(key, page)| (key.clone(), page.page_id.clone()))
+        .collect();
+
+    info!(
+        "Import finished: {} pages created, {} completed, {} failures",
+        report.created, report.completed, report.failed.len()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_front_matter_from_the_body() {
+        let (front_matter, body) = split_front_matter("---\ntitle: Notes\n---\n# Notes\n");
+        assert_eq!(front_matter["title"], "Notes");
+        assert_eq!(body, "# Notes\n");
+    }
+
+    #[test]
+    fn keeps_leading_dashes_of_the_body() {
+        let (_, body) = split_front_matter("---\ntitle: List\n---\n- a\n- b\n");
+        assert_eq!(body, "- a\n- b\n");
+
+        let (_, body) = split_front_matter("---\ntitle: Rule\n---\r\n---\ntext\n");
+        assert_eq!(body, "---\ntext\n");
+    }
+}
@@ -0,0 +1,674 @@
+use crate::error::{NotionMcpError, NotionResult};
+use crate::notion::NotionClient;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Oldest operations are forgotten beyond this many
+const MAX_OPERATIONS: usize = 5000;
+
+// Property types computed by Notion, or holding files whose signed URLs
+// cannot be written back
+const UNRESTORABLE_TYPES: &[&str] = &[
+    "formula", "rollup", "created_time", "created_by", "last_edited_time", "last_edited_by", "unique_id", "button", "verification",
+];
+// Property types whose arrays page objects cut off after this many items
+const TRUNCATED_TYPES: &[&str] = &["relation", "people", "title", "rich_text"];
+const PAGE_OBJECT_ITEMS: usize = 25;
+
+// State before a write, and what identifies the state the write left
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    // Prior values of the properties an update touched
+    Properties { page_id: String, before: Map<String, Value>, last_edited_time: Option<String> },
+    // Archived flag before archiving or restoring a page
+    Archived { page_id: String, before: bool, last_edited_time: Option<String> },
+    // A page created by the call; undone by archiving it
+    Created { page_id: String, last_edited_time: Option<String> },
+    // Blocks appended to an existing page or block; undone by deleting them.
+    // Each block is kept with its last_edited_time.
+    Appended { parent_id: String, blocks: Vec<(String, Option<String>)> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Operation {
+    pub id: String,
+    // Unix seconds
+    pub timestamp: u64,
+    pub caller: Option<String>,
+    pub session: Option<String>,
+    // Workspace, account and policy the write went through
+    pub partition: String,
+    pub tool: String,
+    pub change: Change,
+    #[serde(default)]
+    pub undone: bool,
+}
+
+// How reversing a change went
+enum Reversal {
+    // Done, with a note on anything that could not be put back
+    Restored(Option<String>),
+    // Left alone, because the object changed since
+    Conflict(String),
+}
+
+#[derive(Debug, Serialize)]
+pub struct UndoResult {
+    pub operation_id: String,
+    pub tool: String,
+    // restored, conflict or failed
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn last_edited(object: &Value) -> Option<String> {
+    object["last_edited_time"].as_str().map(|s| s.to_string())
+}
+
+// Local record of the writes made through the server, with what is needed
+// to reverse them, persisted as a JSON file
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    operations: Mutex<Vec<Operation>>,
+}
+
+impl Journal {
+    pub fn open(path: PathBuf) -> NotionResult<Self> {
+        let operations = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| NotionMcpError::JsonParse(format!("Invalid undo journal {}: {}", path.display(), e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, operations: Mutex::new(operations) })
+    }
+
+    fn save(path: &Path, operations: &[Operation]) -> NotionResult<()> {
+        let content = serde_json::to_string(operations)?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, content)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&tmp, fs::Permissions::from_mode(0o600))?;
+        }
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    fn record(&self, operation: Operation) {
+        let mut operations = self.operations.lock().unwrap();
+        operations.push(operation);
+        if operations.len() > MAX_OPERATIONS {
+            let excess = operations.len() - MAX_OPERATIONS;
+            operations.drain(..excess);
+        }
+        if let Err(e) = Self::save(&self.path, &operations) {
+            error!("Could not write the undo journal: {}", e);
+        }
+    }
+
+    fn mark_undone(&self, id: &str) {
+        let mut operations = self.operations.lock().unwrap();
+        if let Some(operation) = operations.iter_mut().find(|o| o.id == id) {
+            operation.undone = true;
+        }
+        if let Err(e) = Self::save(&self.path, &operations) {
+            error!("Could not write the undo journal: {}", e);
+        }
+    }
+
+    // Drop deleted blocks from the appends that created them, so undoing
+    // those appends does not touch blocks already in the trash
+    fn forget_block(&self, block_id: &str) {
+        let block_id = block_id.replace('-', "");
+        let mut operations = self.operations.lock().unwrap();
+        for operation in operations.iter_mut().filter(|o| !o.undone) {
+            if let Change::Appended { blocks, .. } = &mut operation.change {
+                blocks.retain(|(id, _)| id.replace('-', "") != block_id);
+            }
+        }
+        operations.retain(|o| !matches!(&o.change, Change::Appended { blocks, .. } if blocks.is_empty()));
+        if let Err(e) = Self::save(&self.path, &operations) {
+            error!("Could not write the undo journal: {}", e);
+        }
+    }
+
+    // Operations still to undo, newest first: one by ID, or every one of a session
+    pub fn select(&self, operation_id: Option<&str>, session: Option<&str>) -> NotionResult<Vec<Operation>> {
+        let operations = self.operations.lock().unwrap();
+        let selected: Vec<Operation> = match (operation_id, session) {
+            (Some(id), _) => {
+                let operation = operations.iter().find(|o| o.id == id)
+                    .ok_or_else(|| NotionMcpError::ResourceNotFound(format!("No operation '{}' in the undo journal", id)))?;
+                if operation.undone {
+                    return Err(NotionMcpError::InvalidParams(format!("Operation '{}' was already undone", id)));
+                }
+                vec![operation.clone()]
+            }
+            (None, Some(session)) => operations.iter()
+                .rev()
+                .filter(|o| !o.undone && o.session.as_deref() == Some(session))
+                .cloned()
+                .collect(),
+            (None, None) => return Err(NotionMcpError::InvalidParams("Missing 'operation_id' or 'session' parameter".to_string())),
+        };
+        Ok(selected)
+    }
+
+    // Recent operations, newest first
+    pub fn list(&self, session: Option<&str>) -> Vec<Operation> {
+        self.operations.lock().unwrap().iter()
+            .rev()
+            .filter(|o| session.is_none_or(|s| o.session.as_deref() == Some(s)))
+            .cloned()
+            .collect()
+    }
+
+    // Reverse one operation. Objects edited since the operation are left
+    // alone unless `force` is set.
+    pub async fn undo(&self, client: &NotionClient, operation: &Operation, force: bool) -> UndoResult {
+        let result = |status, message: Option<String>| UndoResult {
+            operation_id: operation.id.clone(),
+            tool: operation.tool.clone(),
+            status,
+            message,
+        };
+        match self.reverse(client, &operation.change, force).await {
+            Ok(Reversal::Restored(note)) => {
+                self.mark_undone(&operation.id);
+                info!("Undid operation {} ({})", operation.id, operation.tool);
+                result("restored", note)
+            }
+            Ok(Reversal::Conflict(conflict)) => result("conflict", Some(conflict)),
+            Err(e) => result("failed", Some(e.to_string())),
+        }
+    }
+
+    async fn reverse(&self, client: &NotionClient, change: &Change, force: bool) -> NotionResult<Reversal> {
+        let edited_since = |object: &Value, expected: &Option<String>| {
+            !force && expected.is_some() && last_edited(object) != *expected
+        };
+        match change {
+            Change::Properties { page_id, before, last_edited_time } => {
+                let page = client.get_page_object(page_id).await?;
+                if edited_since(&page, last_edited_time) {
+                    return Ok(Reversal::Conflict(format!(
+                        "Page {} was edited at {} after this change", page_id, last_edited(&page).unwrap_or_default()
+                    )));
+                }
+                let mut properties = Map::new();
+                let mut skipped = Vec::new();
+                for (name, property) in before {
+                    match restorable(property) {
+                        Some(value) => {
+                            properties.insert(name.clone(), value);
+                        }
+                        None => skipped.push(name.as_str()),
+                    }
+                }
+                if !properties.is_empty() {
+                    client.update_page(page_id, Value::Object(properties)).await?;
+                }
+                if !skipped.is_empty() {
+                    return Ok(Reversal::Restored(Some(format!("Not restored: {}", skipped.join(", ")))));
+                }
+            }
+            Change::Archived { page_id, before, last_edited_time } => {
+                let page = client.get_page_object(page_id).await?;
+                if edited_since(&page, last_edited_time) {
+                    return Ok(Reversal::Conflict(format!("Page {} was edited after this change", page_id)));
+                }
+                client.set_archived(page_id, *before).await?;
+            }
+            Change::Created { page_id, last_edited_time } => {
+                let page = client.get_page_object(page_id).await?;
+                if edited_since(&page, last_edited_time) {
+                    return Ok(Reversal::Conflict(format!("Page {} was edited after it was created", page_id)));
+                }
+                client.set_archived(page_id, true).await?;
+            }
+            Change::Appended { parent_id, blocks } => {
+                for (block_id, last_edited_time) in blocks {
+                    let block = client.get_block(block_id).await?;
+                    if edited_since(&block, last_edited_time) {
+                        return Ok(Reversal::Conflict(format!("Block {} under {} was edited after it was added", block_id, parent_id)));
+                    }
+                }
+                for (block_id, _) in blocks {
+                    client.delete_block(block_id).await?;
+                }
+            }
+        }
+        Ok(Reversal::Restored(None))
+    }
+}
+
+// Read in full the properties about to be written whose values the page
+// object may have cut off, so they can be restored without losing items.
+// A property that cannot be read in full is marked incomplete and is not
+// restored.
+pub async fn complete_pre_image(client: &NotionClient, page: &mut Value, names: &[String]) {
+    let Some(page_id) = page["id"].as_str().map(|id| id.to_string()) else {
+        return;
+    };
+    for name in names {
+        let property = &mut page["properties"][name];
+        let Some(property_type) = property["type"].as_str().map(|t| t.to_string()) else {
+            continue;
+        };
+        let items = property[&property_type].as_array().map(|a| a.len()).unwrap_or(0);
+        let truncated = property["has_more"] == true || items >= PAGE_OBJECT_ITEMS;
+        if !TRUNCATED_TYPES.contains(&property_type.as_str()) || !truncated {
+            continue;
+        }
+        let full = match property["id"].as_str() {
+            Some(property_id) => client.get_property_item(&page_id, property_id).await,
+            None => Err(NotionMcpError::JsonParse(format!("Property '{}' has no ID", name))),
+        };
+        match full {
+            Ok(full) => {
+                let values: Vec<Value> = full["results"].as_array().into_iter().flatten()
+                    .map(|item| item[&property_type].clone())
+                    .collect();
+                property[&property_type] = Value::Array(values);
+            }
+            Err(e) => {
+                warn!("Could not read all of '{}' on page {}; it will not be restored: {}", name, page_id, e);
+                property["incomplete"] = Value::Bool(true);
+            }
+        }
+    }
+}
+
+// Write payload that puts a property back to a value read from Notion
+fn restorable(property: &Value) -> Option<Value> {
+    let property_type = property["type"].as_str()?;
+    if UNRESTORABLE_TYPES.contains(&property_type) || property["incomplete"] == true {
+        return None;
+    }
+    // Notion-hosted files carry expiring URLs and cannot be written back
+    if property_type == "files" && property["files"].as_array()?.iter().any(|f| f["type"] == "file") {
+        return None;
+    }
+    let mut payload = Map::new();
+    payload.insert(property_type.to_string(), property[property_type].clone());
+    Some(Value::Object(payload))
+}
+
+// Who writes, and where, for the operations of one tool call
+#[derive(Debug, Clone)]
+pub struct JournalContext {
+    journal: Arc<Journal>,
+    caller: Option<String>,
+    session: Option<String>,
+    partition: String,
+    tool: String,
+    // Pages and blocks created by this call: appending to them needs no
+    // entry of its own, since undoing the creation removes them too
+    created: Arc<Mutex<HashSet<String>>>,
+}
+
+impl JournalContext {
+    pub fn new(journal: Arc<Journal>, caller: Option<String>, session: Option<String>, partition: String, tool: String) -> Self {
+        Self { journal, caller, session, partition, tool, created: Arc::default() }
+    }
+
+    fn record(&self, change: Change) {
+        let mut bytes = [0u8; 9];
+        OsRng.fill_bytes(&mut bytes);
+        self.journal.record(Operation {
+            id: URL_SAFE_NO_PAD.encode(bytes),
+            timestamp: now(),
+            caller: self.caller.clone(),
+            session: self.session.clone(),
+            partition: self.partition.clone(),
+            tool: self.tool.clone(),
+            change,
+            undone: false,
+        });
+    }
+
+    // `before` is the page as it was, `after` the page returned by the update
+    pub fn updated(&self, before: &Value, names: &[String], after: &Value) {
+        let Some(page_id) = after["id"].as_str() else {
+            return;
+        };
+        let before_properties: Map<String, Value> = names.iter()
+            .filter_map(|name| before["properties"].get(name).map(|p| (name.clone(), p.clone())))
+            .collect();
+        self.record(Change::Properties {
+            page_id: page_id.to_string(),
+            before: before_properties,
+            last_edited_time: last_edited(after),
+        });
+    }
+
+    pub fn archived(&self, before: &Value, after: &Value) {
+        let Some(page_id) = after["id"].as_str() else {
+            return;
+        };
+        self.record(Change::Archived {
+            page_id: page_id.to_string(),
+            before: before["archived"].as_bool().unwrap_or(false),
+            last_edited_time: last_edited(after),
+        });
+    }
+
+    pub fn created(&self, page: &Value) {
+        let Some(page_id) = page["id"].as_str() else {
+            return;
+        };
+        self.created.lock().unwrap().insert(page_id.replace('-', ""));
+        self.record(Change::Created { page_id: page_id.to_string(), last_edited_time: last_edited(page) });
+    }
+
+    pub fn deleted(&self, block_id: &str) {
+        self.created.lock().unwrap().remove(&block_id.replace('-', ""));
+        self.journal.forget_block(block_id);
+    }
+
+    pub fn appended(&self, parent_id: &str, blocks: &[Value]) {
+        let ids: Vec<(String, Option<String>)> = blocks.iter()
+            .filter_map(|b| b["id"].as_str().map(|id| (id.to_string(), last_edited(b))))
+            .collect();
+        let parent_is_new = {
+            let mut created = self.created.lock().unwrap();
+            created.extend(ids.iter().map(|(id, _)| id.replace('-', "")));
+            created.contains(&parent_id.replace('-', ""))
+        };
+        if !parent_is_new && !ids.is_empty() {
+            self.record(Change::Appended { parent_id: parent_id.to_string(), blocks: ids });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_notion::FakeNotion;
+    use crate::notion::PageParent;
+    use serde_json::json;
+
+    const PAGE: &str = "1a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d";
+    const NEW_PAGE: &str = "2a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d";
+    // Targets of the page's relation; the page object only shows 25
+    const RELATED: usize = 30;
+
+    fn related(index: usize) -> Value {
+        json!({ "id": format!("00000000-0000-4000-8000-{:012}", index) })
+    }
+
+    // Notion objects by ID, with a clock bumped by every write
+    #[derive(Default)]
+    struct State {
+        objects: Map<String, Value>,
+        clock: u32,
+        // Whether the paginated property endpoint answers
+        property_items: bool,
+    }
+
+    impl State {
+        fn touch(&mut self, id: &str) -> Value {
+            self.clock += 1;
+            let object = &mut self.objects[id];
+            object["last_edited_time"] = json!(format!("2024-01-01T00:00:{:02}.000Z", self.clock));
+            object.clone()
+        }
+    }
+
+    fn respond(state: &Mutex<State>, method: &str, path: &str, body: &Value) -> Option<Value> {
+        let mut state = state.lock().unwrap();
+        let segments: Vec<&str> = path.split('/').collect();
+        match (method, segments.as_slice()) {
+            ("GET", ["pages", _, "properties", _]) if state.property_items => {
+                let results: Vec<Value> = (0..RELATED)
+                    .map(|i| json!({ "object": "property_item", "type": "relation", "relation": related(i) }))
+                    .collect();
+                Some(json!({ "object": "list", "results": results, "has_more": false, "property_item": { "type": "relation" } }))
+            }
+            ("GET", ["pages" | "blocks", id]) => state.objects.get(*id).cloned(),
+            ("PATCH", ["pages", id]) => {
+                let page = state.objects.get_mut(*id)?;
+                if let Some(archived) = body.get("archived") {
+                    page["archived"] = archived.clone();
+                }
+                for (name, value) in body["properties"].as_object().into_iter().flatten() {
+                    let (kind, value) = value.as_object()?.iter().next()?;
+                    page["properties"][name]["type"] = json!(kind);
+                    page["properties"][name][kind] = value.clone();
+                    page["properties"][name].as_object_mut()?.remove("has_more");
+                }
+                Some(state.touch(id))
+            }
+            ("POST", ["pages"]) => {
+                state.objects.insert(NEW_PAGE.to_string(), json!({ "object": "page", "id": NEW_PAGE, "archived": false, "properties": {} }));
+                Some(state.touch(NEW_PAGE))
+            }
+            ("PATCH", ["blocks", _, "children"]) => {
+                let created: Vec<Value> = (0..body["children"].as_array()?.len())
+                    .map(|i| {
+                        let id = format!("b0000000-0000-4000-8000-{:012}", state.objects.len() + i);
+                        json!({ "object": "block", "id": id, "type": "paragraph" })
+                    })
+                    .collect();
+                for block in &created {
+                    let id = block["id"].as_str().unwrap().to_string();
+                    state.objects.insert(id.clone(), block.clone());
+                    state.touch(&id);
+                }
+                let created: Vec<Value> = created.iter().map(|b| state.objects[b["id"].as_str().unwrap()].clone()).collect();
+                Some(json!({ "results": created }))
+            }
+            ("DELETE", ["blocks", id]) => {
+                let block = state.objects.get_mut(*id)?;
+                if block["in_trash"] == true {
+                    return None;
+                }
+                block["in_trash"] = json!(true);
+                Some(state.touch(id))
+            }
+            _ => None,
+        }
+    }
+
+    struct Workspace {
+        notion: FakeNotion,
+        state: Arc<Mutex<State>>,
+        journal: Arc<Journal>,
+        path: PathBuf,
+    }
+
+    impl Workspace {
+        fn start(name: &str) -> Self {
+            let relation: Vec<Value> = (0..PAGE_OBJECT_ITEMS).map(related).collect();
+            let page = json!({
+                "object": "page",
+                "id": PAGE,
+                "archived": false,
+                "properties": {
+                    "Status": { "id": "s", "type": "select", "select": { "name": "Draft" } },
+                    "Tasks": { "id": "r%3Ax", "type": "relation", "relation": relation, "has_more": true },
+                    "Total": { "id": "f", "type": "formula", "formula": { "type": "number", "number": 1 } }
+                }
+            });
+            let state = Arc::new(Mutex::new(State { property_items: true, ..State::default() }));
+            state.lock().unwrap().objects.insert(PAGE.to_string(), page);
+            state.lock().unwrap().touch(PAGE);
+
+            let shared = state.clone();
+            let notion = FakeNotion::start(move |method, path, body| respond(&shared, method, path, body));
+            let path = std::env::temp_dir().join(format!("notion-mcp-journal-{}-{}.json", name, std::process::id()));
+            let _ = fs::remove_file(&path);
+            let journal = Arc::new(Journal::open(path.clone()).unwrap());
+            Self { notion, state, journal, path }
+        }
+
+        // A client journaling its writes under the session "s"
+        fn journaled(&self, tool: &str) -> NotionClient {
+            let context = JournalContext::new(self.journal.clone(), None, Some("s".to_string()), "default".to_string(), tool.to_string());
+            self.notion.client().with_journal(context)
+        }
+
+        async fn undo_all(&self, force: bool) -> Vec<UndoResult> {
+            let client = self.notion.client();
+            let mut results = Vec::new();
+            for operation in self.journal.select(None, Some("s")).unwrap() {
+                results.push(self.journal.undo(&client, &operation, force).await);
+            }
+            results
+        }
+
+        fn page(&self, id: &str) -> Value {
+            self.state.lock().unwrap().objects[id].clone()
+        }
+
+        async fn stop(self) {
+            let _ = fs::remove_file(&self.path);
+            self.notion.stop().await;
+        }
+    }
+
+    fn statuses(results: &[UndoResult]) -> Vec<&str> {
+        results.iter().map(|r| r.status).collect()
+    }
+
+    #[actix_web::test]
+    async fn undoes_property_updates_with_every_relation_target() {
+        let workspace = Workspace::start("properties");
+        workspace.journaled("update_page").update_page(PAGE, json!({
+            "Status": { "select": { "name": "Done" } },
+            "Tasks": { "relation": [] }
+        })).await.unwrap();
+        assert_eq!(workspace.page(PAGE)["properties"]["Tasks"]["relation"], json!([]));
+
+        let results = workspace.undo_all(false).await;
+        assert_eq!(statuses(&results), vec!["restored"]);
+        let page = workspace.page(PAGE);
+        assert_eq!(page["properties"]["Status"]["select"]["name"], "Draft");
+        assert_eq!(page["properties"]["Tasks"]["relation"].as_array().unwrap().len(), RELATED);
+        // Undone operations are not selected again
+        assert!(workspace.journal.select(None, Some("s")).unwrap().is_empty());
+        workspace.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn reports_properties_it_cannot_read_in_full_instead_of_truncating_them() {
+        let workspace = Workspace::start("truncated");
+        workspace.state.lock().unwrap().property_items = false;
+        workspace.journaled("update_page").update_page(PAGE, json!({
+            "Status": { "select": { "name": "Done" } },
+            "Tasks": { "relation": [] }
+        })).await.unwrap();
+
+        let results = workspace.undo_all(false).await;
+        assert_eq!(statuses(&results), vec!["restored"]);
+        assert_eq!(results[0].message.as_deref(), Some("Not restored: Tasks"));
+        let page = workspace.page(PAGE);
+        assert_eq!(page["properties"]["Status"]["select"]["name"], "Draft");
+        assert_eq!(page["properties"]["Tasks"]["relation"], json!([]));
+        workspace.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn undoes_archiving() {
+        let workspace = Workspace::start("archived");
+        workspace.journaled("archive_page").set_archived(PAGE, true).await.unwrap();
+        assert_eq!(workspace.page(PAGE)["archived"], true);
+
+        assert_eq!(statuses(&workspace.undo_all(false).await), vec!["restored"]);
+        assert_eq!(workspace.page(PAGE)["archived"], false);
+        workspace.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn undoes_creation_by_archiving_the_new_page() {
+        let workspace = Workspace::start("created");
+        let client = workspace.journaled("create_page");
+        client.create_page(&PageParent::Page(PAGE.to_string()), json!({}), None).await.unwrap();
+        // Blocks added to the new page need no entry of their own
+        client.append_block_children(NEW_PAGE, vec![json!({ "type": "paragraph" })]).await.unwrap();
+        assert_eq!(workspace.journal.list(Some("s")).len(), 1);
+
+        assert_eq!(statuses(&workspace.undo_all(false).await), vec!["restored"]);
+        assert_eq!(workspace.page(NEW_PAGE)["archived"], true);
+        workspace.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn undoes_appends_by_deleting_the_blocks() {
+        let workspace = Workspace::start("appended");
+        let blocks = workspace.journaled("import_markdown")
+            .append_block_children(PAGE, vec![json!({ "type": "paragraph" }), json!({ "type": "paragraph" })]).await.unwrap();
+
+        assert_eq!(statuses(&workspace.undo_all(false).await), vec!["restored"]);
+        for block in blocks {
+            assert_eq!(workspace.page(block["id"].as_str().unwrap())["in_trash"], true);
+        }
+        workspace.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn forgets_blocks_deleted_after_they_were_appended() {
+        let workspace = Workspace::start("deleted");
+        let client = workspace.journaled("import_markdown");
+        let blocks = client.append_block_children(PAGE, vec![json!({ "type": "paragraph" }), json!({ "type": "paragraph" })]).await.unwrap();
+        let first = blocks[0]["id"].as_str().unwrap();
+        let second = blocks[1]["id"].as_str().unwrap();
+        client.delete_block(first).await.unwrap();
+        assert!(matches!(
+            &workspace.journal.list(None)[0].change,
+            Change::Appended { blocks, .. } if blocks.len() == 1 && blocks[0].0 == second
+        ));
+
+        assert_eq!(statuses(&workspace.undo_all(false).await), vec!["restored"]);
+        assert_eq!(workspace.page(second)["in_trash"], true);
+
+        // An append whose blocks are all deleted leaves nothing to undo
+        let blocks = client.append_block_children(PAGE, vec![json!({ "type": "paragraph" })]).await.unwrap();
+        client.delete_block(blocks[0]["id"].as_str().unwrap()).await.unwrap();
+        assert!(workspace.journal.select(None, Some("s")).unwrap().is_empty());
+        workspace.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn leaves_pages_edited_since_alone_unless_forced() {
+        let workspace = Workspace::start("conflict");
+        workspace.journaled("update_page").update_page(PAGE, json!({ "Status": { "select": { "name": "Done" } } })).await.unwrap();
+        // Someone else edits the page afterwards
+        workspace.notion.client().update_page(PAGE, json!({ "Status": { "select": { "name": "Review" } } })).await.unwrap();
+
+        let results = workspace.undo_all(false).await;
+        assert_eq!(statuses(&results), vec!["conflict"]);
+        assert_eq!(workspace.page(PAGE)["properties"]["Status"]["select"]["name"], "Review");
+
+        assert_eq!(statuses(&workspace.undo_all(true).await), vec!["restored"]);
+        assert_eq!(workspace.page(PAGE)["properties"]["Status"]["select"]["name"], "Draft");
+        workspace.stop().await;
+    }
+
+    #[test]
+    fn never_restores_computed_or_hosted_values() {
+        assert!(restorable(&json!({ "type": "formula", "formula": {} })).is_none());
+        assert!(restorable(&json!({ "type": "files", "files": [{ "type": "file", "file": { "url": "u" } }] })).is_none());
+        assert!(restorable(&json!({ "type": "relation", "relation": [], "incomplete": true })).is_none());
+        assert_eq!(restorable(&json!({ "type": "number", "number": 2 })), Some(json!({ "number": 2 })));
+    }
+}
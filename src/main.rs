@@ -1,53 +1,293 @@
 use anyhow::Result;
 use dotenv::dotenv;
-use log::{info, error};
+use log::{info, error, warn};
 use std::env;
 use std::sync::Arc;
+use std::time::Duration;
 
+mod audit;
+mod auth;
+mod oauth;
 mod notion;
+mod notion_oauth;
 mod server;
 mod error;
+mod mirror;
+mod rate_limit;
+mod safety;
+mod ids;
+mod journal;
+mod file_proxy;
+mod file_roots;
+mod properties;
+mod relations;
+mod search_index;
+mod markdown;
+mod chunks;
+mod concurrency;
+mod comments;
+mod export;
+mod import;
+mod bulk_import;
+mod database_export;
+mod dry_run;
+mod page_ops;
+mod policy;
+mod users;
+mod uploads;
+mod workspaces;
+#[cfg(test)]
+mod fake_notion;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize environment variables from .env
     dotenv().ok();
-    
+
     // Configure logging
     env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
-    
-    // Get Notion API key
-    let notion_api_key = env::var("NOTION_API_KEY")
-        .expect("NOTION_API_KEY not found in environment variables");
-    
+
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    // Notion public integration, when each caller connects their own account
+    let notion_oauth = notion_oauth::NotionOAuthConfig::from_env()?;
+
+    // Extra workspaces, each with its own token
+    let named_workspaces = env::var("NOTION_WORKSPACES").is_ok();
+
+    // Get Notion API key; only optional for a server where every caller
+    // connects their own account or that only serves named workspaces
+    let notion_api_key = env::var("NOTION_API_KEY").ok();
+    if notion_api_key.is_none() && ((notion_oauth.is_none() && !named_workspaces) || !args.is_empty()) {
+        panic!("NOTION_API_KEY not found in environment variables");
+    }
+
     info!("Starting Notion MCP...");
-    
+
     // Create Notion client
-    let notion_client = notion::NotionClient::new(notion_api_key);
-    
+    let mut notion_client = notion::NotionClient::new(notion_api_key.clone().unwrap_or_default());
+    if let Ok(rate) = env::var("NOTION_RATE_LIMIT") {
+        let rate = rate.parse::<f64>().expect("NOTION_RATE_LIMIT must be a number of requests per second");
+        notion_client = notion_client.with_rate_limit(rate);
+    }
+    // Public URL of this server, used to emit stable links to Notion-hosted files
+    let file_proxy_url = env::var("NOTION_FILE_PROXY_URL").ok();
+    // Proxy links are signed, so they open without an API key
+    let url_signer = file_proxy_url.as_ref().map(|_| {
+        let ttl = env::var("MCP_FILE_LINK_TTL")
+            .map(|v| Duration::from_secs(v.parse().expect("MCP_FILE_LINK_TTL must be a number of seconds")))
+            .unwrap_or(file_proxy::DEFAULT_LINK_TTL);
+        match env::var("MCP_FILE_LINK_SECRET") {
+            Ok(secret) if secret.len() >= 32 => Arc::new(file_proxy::UrlSigner::new(secret.as_bytes(), ttl)),
+            Ok(_) => panic!("MCP_FILE_LINK_SECRET must be at least 32 characters long"),
+            Err(_) => Arc::new(file_proxy::UrlSigner::random(ttl)),
+        }
+    });
+    if let Some(base_url) = &file_proxy_url {
+        notion_client = notion_client.with_file_proxy(base_url.clone()).with_url_signer(url_signer.clone());
+    }
+
+    // Open the local mirror if configured
+    let mirror = match env::var("NOTION_MIRROR_DIR") {
+        Ok(dir) => Some(Arc::new(mirror::Mirror::open(&dir)?)),
+        Err(_) => None,
+    };
+    notion_client = notion_client.with_mirror(mirror.clone());
+
+    // One-off mirror sync: `notion-mcp sync [--full]`
+    if args.first().map(|a| a.as_str()) == Some("sync") {
+        let mirror = mirror.expect("NOTION_MIRROR_DIR must be set to run a sync");
+        let full = args.iter().any(|a| a == "--full");
+        let report = mirror::sync(&notion_client, &mirror, full).await?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    // Markdown export: `notion-mcp export <output_dir> [page_id]`
+    if args.first().map(|a| a.as_str()) == Some("export") {
+        let output_dir = args.get(1).expect("Usage: notion-mcp export <output_dir> [page_id]");
+        let page_id = args.get(2).map(|s| s.as_str());
+        let report = export::export(&notion_client, page_id, std::path::Path::new(output_dir)).await?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    // Markdown import: `notion-mcp import <source_dir> <parent_id>`
+    if args.first().map(|a| a.as_str()) == Some("import") {
+        let usage = "Usage: notion-mcp import <source_dir> <parent_id>";
+        let source_dir = args.get(1).expect(usage);
+        let parent_id = args.get(2).expect(usage);
+        let report = import::import_markdown(&notion_client, std::path::Path::new(source_dir), parent_id).await?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    // Bulk upsert: `notion-mcp bulk-import <database_id> <file> [--key <property>] [--concurrency <n>] [--dry-run]`
+    if args.first().map(|a| a.as_str()) == Some("bulk-import") {
+        let usage = "Usage: notion-mcp bulk-import <database_id> <file.csv|file.jsonl> [--key <property>] [--concurrency <n>] [--dry-run]";
+        let database_id = args.get(1).expect(usage);
+        let file = args.get(2).expect(usage);
+        let flag = |name: &str| args.iter().position(|a| a == name).and_then(|i| args.get(i + 1)).cloned();
+
+        let options = bulk_import::BulkImportOptions {
+            key_property: flag("--key"),
+            mapping: Default::default(),
+            concurrency: flag("--concurrency")
+                .map(|c| c.parse().expect("--concurrency must be a number"))
+                .unwrap_or(bulk_import::DEFAULT_CONCURRENCY),
+            dry_run: args.iter().any(|a| a == "--dry-run"),
+        };
+        let rows = bulk_import::read_rows(std::path::Path::new(file))?.into_iter().map(serde_json::Value::Object).collect();
+        let report = bulk_import::bulk_import(&notion_client, database_id, rows, options).await?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    // Tabular export: `notion-mcp export-database <database_id> <file.csv|file.jsonl> [--columns A,B,C]`
+    if args.first().map(|a| a.as_str()) == Some("export-database") {
+        let usage = "Usage: notion-mcp export-database <database_id> <file.csv|file.jsonl> [--columns A,B,C]";
+        let database_id = args.get(1).expect(usage);
+        let path = std::path::Path::new(args.get(2).expect(usage));
+        let format = database_export::TableFormat::from_path(path).expect("Output file must end in .csv or .jsonl");
+        let columns = args.iter().position(|a| a == "--columns")
+            .and_then(|i| args.get(i + 1))
+            .map(|c| c.split(',').map(|s| s.trim().to_string()).collect());
+
+        let table = database_export::read_database_table(&notion_client, database_id, columns, None).await?;
+        database_export::write_table(&table, path, format)?;
+        println!("Exported {} rows to {}", table.rows.len(), path.display());
+        return Ok(());
+    }
+
+    // The mirror is read with the shared token, so it would show every
+    // caller the same pages regardless of their own Notion permissions
+    if mirror.is_some() && notion_oauth.is_some() {
+        anyhow::bail!("NOTION_MIRROR_DIR cannot be combined with NOTION_OAUTH_CLIENT_ID");
+    }
+
     // Validate Notion connection
-    match notion_client.validate_connection().await {
-        Ok(_) => info!("Notion connection validated successfully"),
-        Err(e) => {
-            error!("Error validating Notion connection: {}", e);
-            eprintln!("\nError: Could not connect to Notion. Please verify your API key and Internet connection.");
-            eprintln!("Remember that the current API key format is: ntn_xxxxxxxxxx\n");
-            std::process::exit(1);
+    if notion_api_key.is_none() {
+        info!("No NOTION_API_KEY configured, skipping the connection check");
+    } else {
+        match notion_client.validate_connection().await {
+            Ok(_) => info!("Notion connection validated successfully"),
+            Err(e) if mirror.is_some() => {
+                warn!("Could not reach Notion ({}), serving reads from the local mirror", e);
+            }
+            Err(e) => {
+                error!("Error validating Notion connection: {}", e);
+                eprintln!("\nError: Could not connect to Notion. Please verify your API key and Internet connection.");
+                eprintln!("Remember that the current API key format is: ntn_xxxxxxxxxx\n");
+                std::process::exit(1);
+            }
         }
     }
-    
+
     let notion_client = Arc::new(notion_client);
-    
+    let search_index = Arc::new(search_index::SearchIndex::new());
+
+    // Keep the mirror and the search index built from it up to date in the background
+    if let Some(mirror) = mirror.clone() {
+        let interval = env::var("NOTION_SYNC_INTERVAL")
+            .unwrap_or_else(|_| "300".to_string())
+            .parse::<u64>()
+            .expect("NOTION_SYNC_INTERVAL must be a number of seconds");
+        let client = notion_client.clone();
+        let index = search_index.clone();
+        index.rebuild_from_mirror(&mirror);
+
+        tokio::spawn(async move {
+            loop {
+                match mirror::sync(&client, &mirror, false).await {
+                    Ok(report) if report.pages > 0 || report.pruned > 0 => index.rebuild_from_mirror(&mirror),
+                    Ok(_) => {}
+                    Err(e) => warn!("Mirror sync failed, keeping previous data: {}", e),
+                }
+                tokio::time::sleep(Duration::from_secs(interval)).await;
+            }
+        });
+    }
+
     // Start MCP server
     let port = env::var("MCP_PORT")
         .unwrap_or_else(|_| "3004".to_string())
         .parse::<u16>()
         .expect("PORT must be a valid number");
-    
-    match server::run_notion_mcp_server(notion_client, port).await {
+
+    let file_proxy = Arc::new(
+        file_proxy::FileProxy::new(env::var("NOTION_FILE_CACHE_DIR").ok().map(std::path::PathBuf::from))
+            .with_signer(url_signer.clone())
+    );
+
+    // API keys as `name:token:scope` entries, e.g. `site:s3cr3t-token-1234:read`
+    let mut auth = match env::var("MCP_API_KEYS") {
+        Ok(spec) => auth::AuthConfig::parse(&spec)?,
+        Err(_) => auth::AuthConfig::default(),
+    };
+    // OAuth access tokens for remote MCP clients
+    if let Some(oauth) = oauth::OAuthConfig::from_env()? {
+        auth = auth.with_oauth(oauth);
+    }
+    let cors_origins = env::var("MCP_CORS_ORIGINS")
+        .map(|origins| origins.split(',').map(|o| o.trim().to_string()).filter(|o| !o.is_empty()).collect())
+        .unwrap_or_default();
+    // Per-key allowlists of pages, databases and properties
+    let policies = match env::var("MCP_POLICY_FILE") {
+        Ok(path) => policy::PolicySet::load(std::path::Path::new(&path))?,
+        Err(_) => policy::PolicySet::default(),
+    };
+    // Read-only deployments, or a confirmation step before destructive tools
+    let flag = |name: &str| env::var(name).is_ok_and(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"));
+    let write_guard = safety::WriteGuard::new(flag("MCP_READ_ONLY"), flag("MCP_CONFIRM_DESTRUCTIVE"));
+    // Record of every call that changes Notion
+    let audit_log = match env::var("MCP_AUDIT_LOG") {
+        Ok(path) => {
+            let max_bytes = env::var("MCP_AUDIT_MAX_BYTES")
+                .map(|v| v.parse::<u64>().expect("MCP_AUDIT_MAX_BYTES must be a number of bytes"))
+                .unwrap_or(audit::DEFAULT_MAX_BYTES);
+            Some(Arc::new(audit::AuditLog::new(std::path::PathBuf::from(path), max_bytes)))
+        }
+        Err(_) => None,
+    };
+    // Prior states of pages and blocks, for undo
+    let journal = match env::var("MCP_UNDO_JOURNAL") {
+        Ok(path) => Some(Arc::new(journal::Journal::open(std::path::PathBuf::from(path))?)),
+        Err(_) => None,
+    };
+    let config = server::ServerConfig {
+        port,
+        auth: Arc::new(auth),
+        cors_origins,
+        policies: Arc::new(policies),
+        write_guard: Arc::new(write_guard),
+        audit_log,
+        journal,
+        file_roots: Arc::new(file_roots::FileRoots::from_env()),
+    };
+
+    // Per-user connections need to know who is calling
+    if notion_oauth.is_some() && !config.auth.is_enabled() {
+        anyhow::bail!("NOTION_OAUTH_CLIENT_ID requires MCP_API_KEYS or MCP_OAUTH_RESOURCE to identify callers");
+    }
+    if notion_oauth.is_some() && named_workspaces {
+        anyhow::bail!("NOTION_WORKSPACES cannot be combined with NOTION_OAUTH_CLIENT_ID");
+    }
+    let accounts = match notion_oauth {
+        Some(oauth_config) => workspaces::Accounts::PerUser(Box::new(notion_oauth::NotionOAuth::open(oauth_config, (*notion_client).clone())?)),
+        None => {
+            let default_client = notion_api_key.is_some().then(|| notion_client.clone());
+            let workspaces = workspaces::Workspaces::from_env(default_client, search_index, mirror, file_proxy_url.as_deref(), url_signer)?;
+            if workspaces.is_multiple() {
+                info!("Serving Notion workspaces: {}", workspaces.describe().iter().filter_map(|w| w["name"].as_str()).collect::<Vec<_>>().join(", "));
+            }
+            workspaces::Accounts::Shared(workspaces)
+        }
+    };
+
+    match server::run_notion_mcp_server(Arc::new(accounts), file_proxy, config).await {
         Ok(_) => info!("MCP server finished successfully"),
         Err(e) => error!("Error in MCP server: {}", e),
     }
-    
+
     Ok(())
 }
@@ -0,0 +1,491 @@
+use serde_json::Value;
+
+// Render a rich text array as Markdown, keeping links and inline annotations
+pub fn rich_text_to_markdown(rich_text: &Value) -> String {
+    let Some(parts) = rich_text.as_array() else {
+        return String::new();
+    };
+
+    parts.iter().map(|rt| {
+        let plain = rt["plain_text"].as_str()
+            .or_else(|| rt["text"]["content"].as_str())
+            .unwrap_or("");
+
+        if rt["type"] == "equation" {
+            return format!("${}$", rt["equation"]["expression"].as_str().unwrap_or(plain));
+        }
+        if plain.trim().is_empty() {
+            return plain.to_string();
+        }
+
+        let annotations = &rt["annotations"];
+        let mut text = plain.to_string();
+        if annotations["code"].as_bool().unwrap_or(false) {
+            text = format!("`{}`", text);
+        }
+        if annotations["bold"].as_bool().unwrap_or(false) {
+            text = format!("**{}**", text);
+        }
+        if annotations["italic"].as_bool().unwrap_or(false) {
+            text = format!("_{}_", text);
+        }
+        if annotations["strikethrough"].as_bool().unwrap_or(false) {
+            text = format!("~~{}~~", text);
+        }
+
+        let href = rt["href"].as_str().or_else(|| rt["text"]["link"]["url"].as_str());
+        match href {
+            Some(url) => format!("[{}]({})", text, url),
+            None => text,
+        }
+    }).collect()
+}
+
+// URL of a file-like block or property value, whether Notion-hosted or external
+pub fn file_url(file: &Value) -> Option<&str> {
+    file["file"]["url"].as_str().or_else(|| file["external"]["url"].as_str())
+}
+
+fn indent(text: &str, prefix: &str) -> String {
+    text.lines()
+        .map(|line| if line.is_empty() { line.to_string() } else { format!("{}{}", prefix, line) })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn is_list_item(block_type: &str) -> bool {
+    matches!(block_type, "bulleted_list_item" | "numbered_list_item" | "to_do")
+}
+
+// Nested blocks, either fetched (stored on the block) or to be created
+// (stored under the block type, as the API expects)
+pub fn block_children(block: &Value) -> Option<&Vec<Value>> {
+    let block_type = block["type"].as_str().unwrap_or("");
+    block["children"].as_array().or_else(|| block[block_type]["children"].as_array())
+}
+
+fn render_table(block: &Value) -> String {
+    let rows: Vec<Vec<String>> = block_children(block)
+        .map(|rows| rows.iter()
+            .map(|row| row["table_row"]["cells"].as_array()
+                .map(|cells| cells.iter()
+                    .map(|cell| rich_text_to_markdown(cell).replace('|', "\\|"))
+                    .collect())
+                .unwrap_or_default())
+            .collect())
+        .unwrap_or_default();
+
+    let Some(header) = rows.first() else {
+        return String::new();
+    };
+
+    let mut lines = vec![
+        format!("| {} |", header.join(" | ")),
+        format!("|{}|", vec![" --- "; header.len()].join("|")),
+    ];
+    for row in rows.iter().skip(1) {
+        lines.push(format!("| {} |", row.join(" | ")));
+    }
+    lines.join("\n")
+}
+
+// Render one block and its nested children. `number` is the position of a
+// numbered list item within its list.
+pub fn block_to_markdown(block: &Value, number: usize) -> String {
+    let block_type = block["type"].as_str().unwrap_or("");
+    let data = &block[block_type];
+    let text = rich_text_to_markdown(&data["rich_text"]);
+    let caption = rich_text_to_markdown(&data["caption"]);
+    let children = block_children(block)
+        .map(|c| blocks_to_markdown(c))
+        .unwrap_or_default();
+
+    let own = match block_type {
+        "paragraph" => text,
+        "heading_1" => format!("# {}", text),
+        "heading_2" => format!("## {}", text),
+        "heading_3" => format!("### {}", text),
+        "bulleted_list_item" => format!("- {}", text),
+        "numbered_list_item" => format!("{}. {}", number.max(1), text),
+        "to_do" => {
+            let checked = data["checked"].as_bool().unwrap_or(false);
+            format!("- [{}] {}", if checked { "x" } else { " " }, text)
+        }
+        "toggle" => format!("- {}", text),
+        "quote" => indent(&text, "> "),
+        "callout" => {
+            let icon = data["icon"]["emoji"].as_str().map(|e| format!("{} ", e)).unwrap_or_default();
+            indent(&format!("{}{}", icon, text), "> ")
+        }
+        "code" => {
+            let language = data["language"].as_str().unwrap_or("");
+            format!("```{}\n{}\n```", language, crate::properties::rich_text_to_plain(&data["rich_text"]))
+        }
+        "equation" => format!("$$\n{}\n$$", data["expression"].as_str().unwrap_or("")),
+        "divider" => "---".to_string(),
+        "image" => {
+            format!("![{}]({})", caption, file_url(data).unwrap_or(""))
+        }
+        "file" | "pdf" | "video" | "audio" => {
+            let name = data["name"].as_str().map(|n| n.to_string())
+                .filter(|n| !n.is_empty())
+                .unwrap_or_else(|| if caption.is_empty() { block_type.to_string() } else { caption.clone() });
+            format!("[{}]({})", name, file_url(data).unwrap_or(""))
+        }
+        "bookmark" | "embed" | "link_preview" => {
+            let url = data["url"].as_str().unwrap_or("");
+            let label = if caption.is_empty() { url.to_string() } else { caption.clone() };
+            format!("[{}]({})", label, url)
+        }
+        "child_page" => format!("📄 {}", data["title"].as_str().unwrap_or("")),
+        "child_database" => format!("🗂️ {}", data["title"].as_str().unwrap_or("")),
+        "table" => return render_table(block),
+        // Containers whose content lives entirely in their children
+        "column_list" | "column" | "synced_block" | "template" => return children,
+        _ => text,
+    };
+
+    if children.is_empty() {
+        return own;
+    }
+
+    // Children of list items and toggles are nested; others follow the block
+    if is_list_item(block_type) || block_type == "toggle" {
+        format!("{}\n{}", own, indent(&children, "  "))
+    } else if own.is_empty() {
+        children
+    } else {
+        format!("{}\n\n{}", own, children)
+    }
+}
+
+// Render a list of sibling blocks as a Markdown document
+pub fn blocks_to_markdown(blocks: &[Value]) -> String {
+    let mut out = String::new();
+    let mut number = 0;
+    let mut previous_was_list = false;
+
+    for block in blocks {
+        let block_type = block["type"].as_str().unwrap_or("");
+        number = if block_type == "numbered_list_item" { number + 1 } else { 0 };
+
+        let rendered = block_to_markdown(block, number);
+        if rendered.is_empty() {
+            continue;
+        }
+
+        let is_list = is_list_item(block_type);
+        if !out.is_empty() {
+            out.push_str(if is_list && previous_was_list { "\n" } else { "\n\n" });
+        }
+        out.push_str(&rendered);
+        previous_was_list = is_list;
+    }
+
+    out
+}
+
+// Notion rejects text objects longer than this many characters
+const MAX_TEXT_LENGTH: usize = 2000;
+
+// Languages accepted by Notion code blocks; anything else becomes "plain text"
+const CODE_LANGUAGES: &[&str] = &[
+    "bash", "c", "c#", "c++", "css", "diff", "docker", "go", "graphql", "haskell", "html", "java",
+    "javascript", "json", "kotlin", "latex", "lua", "makefile", "markdown", "mermaid", "php",
+    "plain text", "powershell", "python", "r", "ruby", "rust", "scala", "shell", "sql", "swift",
+    "toml", "typescript", "xml", "yaml",
+];
+
+// Build a rich text array from plain text, splitting it to respect Notion's length limit
+pub fn text_to_rich_text(text: &str) -> Value {
+    let chars: Vec<char> = text.chars().collect();
+    let parts: Vec<Value> = chars.chunks(MAX_TEXT_LENGTH)
+        .map(|chunk| serde_json::json!({
+            "type": "text",
+            "text": { "content": chunk.iter().collect::<String>() }
+        }))
+        .collect();
+    Value::Array(parts)
+}
+
+#[derive(Default, Clone, Copy)]
+struct Annotations {
+    bold: bool,
+    italic: bool,
+    strikethrough: bool,
+}
+
+enum Container {
+    Document,
+    Paragraph,
+    Heading(usize),
+    Quote,
+    List(bool),
+    Item { checked: Option<bool> },
+    Code { language: String, text: String },
+    Table,
+    TableRow { cells: Vec<Value> },
+    TableCell,
+}
+
+struct Frame {
+    container: Container,
+    rich_text: Vec<Value>,
+    children: Vec<Value>,
+}
+
+struct MarkdownParser {
+    stack: Vec<Frame>,
+    annotations: Annotations,
+    link: Option<String>,
+    image: Option<(String, String)>,
+}
+
+fn new_block(block_type: &str, mut data: Value, children: Vec<Value>) -> Value {
+    if !children.is_empty() {
+        data["children"] = Value::Array(children);
+    }
+    let mut block = serde_json::json!({
+        "object": "block",
+        "type": block_type
+    });
+    block[block_type] = data;
+    block
+}
+
+impl MarkdownParser {
+    fn frame(&mut self) -> &mut Frame {
+        self.stack.last_mut().expect("document frame")
+    }
+
+    fn push_frame(&mut self, container: Container) {
+        self.stack.push(Frame { container, rich_text: Vec::new(), children: Vec::new() });
+    }
+
+    fn push_text(&mut self, text: &str, code: bool) {
+        if let Some((_, alt)) = self.image.as_mut() {
+            alt.push_str(text);
+            return;
+        }
+        if let Container::Code { text: code_text, .. } = &mut self.frame().container {
+            code_text.push_str(text);
+            return;
+        }
+
+        let annotations = self.annotations;
+        let link = self.link.clone();
+        let chars: Vec<char> = text.chars().collect();
+        for chunk in chars.chunks(MAX_TEXT_LENGTH) {
+            let mut rich_text = serde_json::json!({
+                "type": "text",
+                "text": { "content": chunk.iter().collect::<String>() },
+                "annotations": {
+                    "bold": annotations.bold,
+                    "italic": annotations.italic,
+                    "strikethrough": annotations.strikethrough,
+                    "code": code
+                }
+            });
+            if let Some(url) = &link {
+                rich_text["text"]["link"] = serde_json::json!({ "url": url });
+            }
+            self.frame().rich_text.push(rich_text);
+        }
+    }
+
+    // Attach a finished block to the enclosing container
+    fn emit(&mut self, block: Value) {
+        self.frame().children.push(block);
+    }
+
+    fn start(&mut self, tag: pulldown_cmark::Tag) {
+        use pulldown_cmark::Tag;
+
+        match tag {
+            Tag::Paragraph => self.push_frame(Container::Paragraph),
+            Tag::Heading { level, .. } => self.push_frame(Container::Heading((level as usize).min(3))),
+            Tag::BlockQuote(_) => self.push_frame(Container::Quote),
+            Tag::CodeBlock(kind) => {
+                let language = match kind {
+                    pulldown_cmark::CodeBlockKind::Fenced(lang) => lang.to_lowercase(),
+                    _ => String::new(),
+                };
+                self.push_frame(Container::Code { language, text: String::new() });
+            }
+            Tag::List(start) => self.push_frame(Container::List(start.is_some())),
+            Tag::Item => self.push_frame(Container::Item { checked: None }),
+            Tag::Table(_) => self.push_frame(Container::Table),
+            Tag::TableHead | Tag::TableRow => self.push_frame(Container::TableRow { cells: Vec::new() }),
+            Tag::TableCell => self.push_frame(Container::TableCell),
+            Tag::Emphasis => self.annotations.italic = true,
+            Tag::Strong => self.annotations.bold = true,
+            Tag::Strikethrough => self.annotations.strikethrough = true,
+            Tag::Link { dest_url, .. } => self.link = Some(dest_url.to_string()),
+            Tag::Image { dest_url, .. } => self.image = Some((dest_url.to_string(), String::new())),
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: pulldown_cmark::TagEnd) {
+        use pulldown_cmark::TagEnd;
+
+        match tag {
+            TagEnd::Emphasis => self.annotations.italic = false,
+            TagEnd::Strong => self.annotations.bold = false,
+            TagEnd::Strikethrough => self.annotations.strikethrough = false,
+            TagEnd::Link => self.link = None,
+            TagEnd::Image => {
+                if let Some((url, alt)) = self.image.take() {
+                    if url.starts_with("http://") || url.starts_with("https://") {
+                        self.emit(new_block("image", serde_json::json!({
+                            "type": "external",
+                            "external": { "url": url },
+                            "caption": text_to_rich_text(&alt)
+                        }), Vec::new()));
+                    } else {
+                        // Local images cannot be referenced by URL; keep the alt text
+                        self.push_text(&alt, false);
+                    }
+                }
+            }
+            TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::BlockQuote(_) | TagEnd::CodeBlock
+            | TagEnd::List(_) | TagEnd::Item | TagEnd::Table | TagEnd::TableHead | TagEnd::TableRow
+            | TagEnd::TableCell => self.close_frame(),
+            _ => {}
+        }
+    }
+
+    fn close_frame(&mut self) {
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+        let rich_text = Value::Array(frame.rich_text);
+        let empty_text = rich_text.as_array().map(|a| a.is_empty()).unwrap_or(true);
+
+        match frame.container {
+            Container::Paragraph => {
+                // The first paragraph of a list item or quote becomes its text
+                let parent = self.frame();
+                let absorbs = matches!(parent.container, Container::Item { .. } | Container::Quote)
+                    && parent.rich_text.is_empty()
+                    && parent.children.is_empty();
+                if absorbs {
+                    parent.rich_text = rich_text.as_array().cloned().unwrap_or_default();
+                    parent.children.extend(frame.children);
+                } else {
+                    if !empty_text {
+                        parent.children.push(new_block("paragraph", serde_json::json!({ "rich_text": rich_text }), Vec::new()));
+                    }
+                    // Images found inside the paragraph follow it
+                    parent.children.extend(frame.children);
+                }
+            }
+            Container::Heading(level) => {
+                let block_type = format!("heading_{}", level.max(1));
+                self.emit(new_block(&block_type, serde_json::json!({ "rich_text": rich_text }), Vec::new()));
+                self.frame().children.extend(frame.children);
+            }
+            Container::Quote => {
+                self.emit(new_block("quote", serde_json::json!({ "rich_text": rich_text }), frame.children));
+            }
+            Container::Code { language, text } => {
+                let language = if CODE_LANGUAGES.contains(&language.as_str()) { language } else { "plain text".to_string() };
+                self.emit(new_block("code", serde_json::json!({
+                    "rich_text": text_to_rich_text(text.trim_end_matches('\n')),
+                    "language": language
+                }), Vec::new()));
+            }
+            Container::List(_) => {
+                // Items were collected by the list; hand them to its parent
+                self.frame().children.extend(frame.children);
+            }
+            Container::Item { checked } => {
+                let ordered = matches!(self.frame().container, Container::List(true));
+                let block = match checked {
+                    Some(checked) => new_block("to_do", serde_json::json!({ "rich_text": rich_text, "checked": checked }), frame.children),
+                    None if ordered => new_block("numbered_list_item", serde_json::json!({ "rich_text": rich_text }), frame.children),
+                    None => new_block("bulleted_list_item", serde_json::json!({ "rich_text": rich_text }), frame.children),
+                };
+                self.emit(block);
+            }
+            Container::TableCell => {
+                if let Container::TableRow { cells } = &mut self.frame().container {
+                    cells.push(rich_text);
+                }
+            }
+            Container::TableRow { cells } => {
+                self.emit(new_block("table_row", serde_json::json!({ "cells": cells }), Vec::new()));
+            }
+            Container::Table => {
+                let width = frame.children.first()
+                    .and_then(|row| row["table_row"]["cells"].as_array())
+                    .map(|cells| cells.len())
+                    .unwrap_or(1);
+                let table = serde_json::json!({
+                    "table_width": width,
+                    "has_column_header": true,
+                    "has_row_header": false
+                });
+                self.emit(new_block("table", table, frame.children));
+            }
+            Container::Document => {}
+        }
+    }
+}
+
+// Parse Markdown into Notion block objects ready to be sent to the API.
+// Nested blocks are placed under the block type's "children" key.
+pub fn markdown_to_blocks(markdown: &str) -> Vec<Value> {
+    use pulldown_cmark::{Event, Options, Parser};
+
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut parser = MarkdownParser {
+        stack: Vec::new(),
+        annotations: Annotations::default(),
+        link: None,
+        image: None,
+    };
+    parser.push_frame(Container::Document);
+
+    for event in Parser::new_ext(markdown, options) {
+        match event {
+            Event::Start(tag) => parser.start(tag),
+            Event::End(tag) => parser.end(tag),
+            Event::Text(text) => parser.push_text(&text, false),
+            Event::Code(code) => parser.push_text(&code, true),
+            Event::SoftBreak => parser.push_text(" ", false),
+            Event::HardBreak => parser.push_text("\n", false),
+            Event::Html(html) | Event::InlineHtml(html) => parser.push_text(&html, false),
+            Event::Rule => parser.emit(new_block("divider", serde_json::json!({}), Vec::new())),
+            Event::TaskListMarker(checked) => {
+                if let Container::Item { checked: state } = &mut parser.frame().container {
+                    *state = Some(checked);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    parser.stack.pop().map(|frame| frame.children).unwrap_or_default()
+}
+
+// Parse Markdown into a single rich text array, for places such as comments
+// that take formatted text but no blocks. Paragraphs are separated by newlines.
+pub fn markdown_to_rich_text(markdown: &str) -> Value {
+    let mut parts = Vec::new();
+    for block in markdown_to_blocks(markdown) {
+        let block_type = block["type"].as_str().unwrap_or("");
+        if let Some(rich_text) = block[block_type]["rich_text"].as_array() {
+            if !parts.is_empty() {
+                parts.push(serde_json::json!({ "type": "text", "text": { "content": "\n" } }));
+            }
+            parts.extend(rich_text.iter().cloned());
+        }
+    }
+
+    if parts.is_empty() {
+        text_to_rich_text(markdown)
+    } else {
+        Value::Array(parts)
+    }
+}
@@ -0,0 +1,506 @@
+use crate::error::{NotionMcpError, NotionResult};
+use crate::ids::normalize_id;
+use crate::notion::NotionClient;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+const SEARCH_PAGE_SIZE: u32 = 100;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SyncState {
+    // Newest last_edited_time seen by a completed sync
+    pub last_edited_watermark: Option<String>,
+    // Unix timestamp of the last completed sync
+    pub last_synced_at: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SyncReport {
+    pub full: bool,
+    pub pages: usize,
+    pub databases: usize,
+    // Pages and databases dropped because Notion no longer shares them
+    pub pruned: usize,
+    pub failed: Vec<String>,
+}
+
+// Local on-disk copy of the pages and databases shared with the integration.
+//
+// Layout under the root directory:
+//   pages/{id}.json       page objects
+//   blocks/{id}.json      full block trees of each page
+//   databases/{id}.json   database objects
+//   rows/{id}.json        IDs of the pages that belong to each database
+//   state.json            sync watermark
+#[derive(Debug)]
+pub struct Mirror {
+    root: PathBuf,
+    sync_lock: Mutex<()>,
+}
+
+impl Mirror {
+    pub fn open(root: impl Into<PathBuf>) -> NotionResult<Self> {
+        let root = root.into();
+        for dir in ["pages", "blocks", "databases", "rows"] {
+            fs::create_dir_all(root.join(dir))?;
+        }
+
+        Ok(Self {
+            root,
+            sync_lock: Mutex::new(()),
+        })
+    }
+
+    // Files are keyed by the undashed, lowercase ID so both ID forms resolve.
+    // Anything that is not a Notion ID is refused before it reaches a path.
+    fn key(id: &str) -> NotionResult<String> {
+        normalize_id(id).map(|id| id.replace('-', ""))
+    }
+
+    fn path(&self, dir: &str, id: &str) -> NotionResult<PathBuf> {
+        Ok(self.root.join(dir).join(format!("{}.json", Self::key(id)?)))
+    }
+
+    fn read_json(path: &Path) -> Option<Value> {
+        let data = fs::read(path).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    // Write through a temporary file so readers never see a partial document
+    fn write_json(path: &Path, value: &Value) -> NotionResult<()> {
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(value)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn get_page(&self, page_id: &str) -> Option<Value> {
+        Self::read_json(&self.path("pages", page_id).ok()?)
+    }
+
+    pub fn get_blocks(&self, page_id: &str) -> Option<Vec<Value>> {
+        Self::read_json(&self.path("blocks", page_id).ok()?)?.as_array().cloned()
+    }
+
+    pub fn get_database(&self, database_id: &str) -> Option<Value> {
+        Self::read_json(&self.path("databases", database_id).ok()?)
+    }
+
+    // Rows of a mirrored database, or None if the database has not been
+    // mirrored or one of its rows was written since the last sync
+    pub fn database_rows(&self, database_id: &str) -> Option<Vec<Value>> {
+        self.get_database(database_id)?;
+
+        let db_key = Self::key(database_id).ok()?;
+        let ids = Self::read_json(&self.path("rows", database_id).ok()?).unwrap_or_else(|| json!([]));
+
+        let pages = ids.as_array()
+            .map(|ids| ids.iter()
+                .filter_map(|id| id.as_str())
+                .map(|id| self.get_page(id))
+                .collect::<Option<Vec<Value>>>())
+            .unwrap_or(Some(Vec::new()))?;
+        let rows = pages.into_iter()
+            .filter(|page| page["parent"]["database_id"].as_str().and_then(|id| Self::key(id).ok()).as_ref() == Some(&db_key))
+            .collect();
+
+        Some(rows)
+    }
+
+    // IDs of every mirrored page
+    pub fn page_ids(&self) -> Vec<String> {
+        self.ids("pages")
+    }
+
+    fn ids(&self, dir: &str) -> Vec<String> {
+        fs::read_dir(self.root.join(dir))
+            .map(|entries| entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    name.strip_suffix(".json").map(|id| id.to_string())
+                })
+                .collect())
+            .unwrap_or_default()
+    }
+
+    pub fn state(&self) -> SyncState {
+        Self::read_json(&self.root.join("state.json"))
+            .and_then(|v| serde_json::from_value(v).ok())
+            .unwrap_or_default()
+    }
+
+    fn save_state(&self, state: &SyncState) -> NotionResult<()> {
+        Self::write_json(&self.root.join("state.json"), &serde_json::to_value(state)?)
+    }
+
+    fn store_page(&self, page: &Value, blocks: &[Value]) -> NotionResult<()> {
+        let page_id = page["id"].as_str()
+            .ok_or_else(|| NotionMcpError::JsonParse("Page without 'id'".to_string()))?;
+
+        Self::write_json(&self.path("pages", page_id)?, page)?;
+        Self::write_json(&self.path("blocks", page_id)?, &json!(blocks))?;
+
+        if let Some(database_id) = page["parent"]["database_id"].as_str() {
+            self.add_row(database_id, page_id)?;
+        }
+
+        Ok(())
+    }
+
+    fn store_database(&self, database: &Value) -> NotionResult<()> {
+        let database_id = database["id"].as_str()
+            .ok_or_else(|| NotionMcpError::JsonParse("Database without 'id'".to_string()))?;
+
+        Self::write_json(&self.path("databases", database_id)?, database)
+    }
+
+    // Forget the copy of a page that was written. A database row stays
+    // listed, so queries on its database go to Notion until a sync stores
+    // the page again.
+    pub fn invalidate(&self, page_id: &str) -> NotionResult<()> {
+        for dir in ["pages", "blocks"] {
+            match fs::remove_file(self.path(dir, page_id)?) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Drop an archived page, including from the rows of its database, since
+    // syncs no longer see it
+    pub fn remove(&self, page_id: &str) -> NotionResult<()> {
+        if let Some(database_id) = self.get_page(page_id).and_then(|p| p["parent"]["database_id"].as_str().map(|s| s.to_string())) {
+            let path = self.path("rows", &database_id)?;
+            if let Some(ids) = Self::read_json(&path).and_then(|v| v.as_array().cloned()) {
+                let key = Self::key(page_id)?;
+                let ids: Vec<Value> = ids.into_iter().filter(|id| id.as_str() != Some(key.as_str())).collect();
+                Self::write_json(&path, &json!(ids))?;
+            }
+        }
+        self.invalidate(page_id)
+    }
+
+    // Drop a database that is no longer shared, with its list of rows
+    fn remove_database(&self, database_id: &str) -> NotionResult<()> {
+        for dir in ["databases", "rows"] {
+            match fs::remove_file(self.path(dir, database_id)?) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Remove what a full crawl did not see: pages and databases deleted,
+    // archived or no longer shared. Returns how many were removed.
+    fn prune(&self, pages: &HashSet<String>, databases: &HashSet<String>) -> NotionResult<usize> {
+        let mut pruned = 0;
+        for id in self.ids("pages") {
+            if !pages.contains(&id) {
+                self.remove(&id)?;
+                pruned += 1;
+            }
+        }
+        // Block trees left behind by pages invalidated before they vanished
+        for id in self.ids("blocks") {
+            if !pages.contains(&id) {
+                self.invalidate(&id)?;
+            }
+        }
+        // Row lists are only read alongside their database
+        let stale: HashSet<String> = self.ids("databases").into_iter()
+            .chain(self.ids("rows"))
+            .filter(|id| !databases.contains(id))
+            .collect();
+        for id in stale {
+            if self.get_database(&id).is_some() {
+                pruned += 1;
+            }
+            self.remove_database(&id)?;
+        }
+        Ok(pruned)
+    }
+
+    // Whether a page is mirrored, as opposed to a block or an unknown page
+    pub fn has_page(&self, id: &str) -> bool {
+        self.path("pages", id).is_ok_and(|path| path.exists())
+    }
+
+    // List a page created in a database before it is mirrored, so queries
+    // on the database go to Notion until a sync stores it
+    pub fn add_row(&self, database_id: &str, page_id: &str) -> NotionResult<()> {
+        let path = self.path("rows", database_id)?;
+        let mut ids = Self::read_json(&path)
+            .and_then(|v| v.as_array().cloned())
+            .unwrap_or_default();
+
+        let key = Self::key(page_id)?;
+        if !ids.iter().any(|id| id.as_str() == Some(key.as_str())) {
+            ids.push(json!(key));
+            Self::write_json(&path, &json!(ids))?;
+        }
+
+        Ok(())
+    }
+}
+
+// Mirror everything shared with the integration. The first run (or a forced
+// full run) crawls the whole workspace and prunes whatever it did not find;
+// later runs walk the search results newest first and stop once they reach
+// content older than the watermark.
+pub async fn sync(client: &NotionClient, mirror: &Mirror, force_full: bool) -> NotionResult<SyncReport> {
+    let _guard = mirror.sync_lock.lock().await;
+
+    let mut state = mirror.state();
+    let since = if force_full { None } else { state.last_edited_watermark.clone() };
+    let mut report = SyncReport {
+        full: since.is_none(),
+        ..SyncReport::default()
+    };
+    let mut newest = since.clone();
+    // Edit time of the oldest page that could not be fetched
+    let mut oldest_failed: Option<String> = None;
+    // What a full crawl saw, by key; the rest is pruned at the end
+    let mut seen_pages = HashSet::new();
+    let mut seen_databases = HashSet::new();
+    let mut cursor: Option<String> = None;
+
+    info!("Starting {} mirror sync", if report.full { "full" } else { "incremental" });
+
+    'crawl: loop {
+        let response = client.search_with_cursor("", cursor.as_deref(), SEARCH_PAGE_SIZE).await?;
+
+        for object in &response.results {
+            let edited = object["last_edited_time"].as_str().unwrap_or("").to_string();
+
+            // Notion rounds last_edited_time to the minute, so anything edited
+            // during the watermark minute is fetched again
+            if let Some(since) = &since {
+                if edited.as_str() < since.as_str() {
+                    break 'crawl;
+                }
+            }
+
+            if newest.as_deref().is_none_or(|n| edited.as_str() > n) {
+                newest = Some(edited.clone());
+            }
+
+            let id = object["id"].as_str().unwrap_or("").to_string();
+            // Trashed content is dropped rather than served
+            if object["archived"] == true || object["in_trash"] == true {
+                let removed = match object["object"].as_str() {
+                    Some("page") if mirror.has_page(&id) => mirror.remove(&id).map(|_| true),
+                    Some("database") if mirror.get_database(&id).is_some() => mirror.remove_database(&id).map(|_| true),
+                    _ => Ok(false),
+                };
+                if removed? {
+                    report.pruned += 1;
+                }
+                continue;
+            }
+            if let Ok(key) = Mirror::key(&id) {
+                match object["object"].as_str() {
+                    Some("page") => seen_pages.insert(key),
+                    Some("database") => seen_databases.insert(key),
+                    _ => false,
+                };
+            }
+            match object["object"].as_str() {
+                Some("page") => {
+                    match client.get_block_tree(&id).await {
+                        Ok(blocks) => {
+                            mirror.store_page(object, &blocks)?;
+                            report.pages += 1;
+                        }
+                        Err(e) => {
+                            warn!("Could not mirror page {}: {}", id, e);
+                            if oldest_failed.as_deref().is_none_or(|o| edited.as_str() < o) {
+                                oldest_failed = Some(edited.clone());
+                            }
+                            report.failed.push(id);
+                        }
+                    }
+                }
+                Some("database") => {
+                    mirror.store_database(object)?;
+                    report.databases += 1;
+                }
+                _ => debug!("Skipping search result {} of unknown type", id),
+            }
+        }
+
+        match response.next_cursor {
+            Some(next) if response.has_more => cursor = Some(next),
+            _ => break,
+        }
+    }
+
+    if report.full {
+        report.pruned += mirror.prune(&seen_pages, &seen_databases)?;
+    }
+
+    // Pages edited during the watermark minute are fetched again, so holding
+    // the watermark at the oldest failure makes the next sync retry it
+    state.last_edited_watermark = oldest_failed.or(newest);
+    state.last_synced_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs());
+    mirror.save_state(&state)?;
+
+    info!(
+        "Mirror sync finished: {} pages, {} databases, {} pruned, {} failures",
+        report.pages, report.databases, report.pruned, report.failed.len()
+    );
+    Ok(report)
+}
+
+// Evaluate a query filter against a mirrored row. Only the filters the
+// server itself builds are supported; None means the caller must ask Notion.
+pub fn matches_filter(page: &Value, filter: &Value) -> Option<bool> {
+    let property = &page["properties"][filter["property"].as_str()?];
+
+    if let Some(expected) = filter["checkbox"]["equals"].as_bool() {
+        return Some(property["checkbox"].as_bool().unwrap_or(false) == expected);
+    }
+
+    if let Some(expected) = filter["multi_select"]["contains"].as_str() {
+        let contains = property["multi_select"].as_array()
+            .map(|options| options.iter().any(|o| o["name"].as_str() == Some(expected)))
+            .unwrap_or(false);
+        return Some(contains);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_notion::FakeNotion;
+    use std::sync::{Arc, Mutex as StdMutex};
+
+    const ROADMAP: &str = "1a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d";
+    const TASK: &str = "2a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d";
+    const TASKS_DB: &str = "3a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d";
+
+    fn page(id: &str, minute: u32, parent: Value) -> Value {
+        json!({ "object": "page", "id": id, "parent": parent, "last_edited_time": format!("2024-05-01T10:{:02}:00.000Z", minute) })
+    }
+
+    fn database(minute: u32) -> Value {
+        json!({ "object": "database", "id": TASKS_DB, "last_edited_time": format!("2024-05-01T10:{:02}:00.000Z", minute) })
+    }
+
+    // Search results, newest first as Notion sorts them
+    fn workspace(results: Arc<StdMutex<Vec<Value>>>) -> FakeNotion {
+        FakeNotion::start(move |method, path, _| match (method, path) {
+            ("POST", "search") => {
+                let mut results = results.lock().unwrap().clone();
+                results.sort_by(|a, b| b["last_edited_time"].as_str().cmp(&a["last_edited_time"].as_str()));
+                Some(json!({ "results": results, "has_more": false, "next_cursor": null }))
+            }
+            ("GET", p) if p.ends_with("/children") => Some(json!({
+                "results": [{ "object": "block", "id": "ffffffff-0000-4000-8000-000000000001", "type": "paragraph", "has_children": false }],
+                "has_more": false
+            })),
+            _ => None,
+        })
+    }
+
+    fn temp_mirror(name: &str) -> (Mirror, PathBuf) {
+        let root = std::env::temp_dir().join(format!("notion-mcp-mirror-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        (Mirror::open(&root).unwrap(), root)
+    }
+
+    #[actix_web::test]
+    async fn incremental_syncs_fetch_only_what_changed_since_the_watermark() {
+        let results = Arc::new(StdMutex::new(vec![
+            page(ROADMAP, 5, json!({ "type": "workspace" })),
+            page(TASK, 3, json!({ "type": "database_id", "database_id": TASKS_DB })),
+            database(1),
+        ]));
+        let notion = workspace(results.clone());
+        let (mirror, root) = temp_mirror("incremental");
+
+        let report = sync(&notion.client(), &mirror, false).await.unwrap();
+        assert!(report.full);
+        assert_eq!((report.pages, report.databases), (2, 1));
+        assert_eq!(mirror.state().last_edited_watermark.as_deref(), Some("2024-05-01T10:05:00.000Z"));
+        assert_eq!(mirror.database_rows(TASKS_DB).unwrap().len(), 1);
+        assert_eq!(mirror.get_blocks(ROADMAP).unwrap().len(), 1);
+
+        // Only the task is edited; the roadmap is fetched again because it
+        // shares the watermark minute, the older database is not
+        results.lock().unwrap()[1] = page(TASK, 9, json!({ "type": "database_id", "database_id": TASKS_DB }));
+        let report = sync(&notion.client(), &mirror, false).await.unwrap();
+        assert!(!report.full);
+        assert_eq!((report.pages, report.databases), (2, 0));
+        assert_eq!(mirror.state().last_edited_watermark.as_deref(), Some("2024-05-01T10:09:00.000Z"));
+        assert_eq!(mirror.get_page(TASK).unwrap()["last_edited_time"], "2024-05-01T10:09:00.000Z");
+
+        notion.stop().await;
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn full_syncs_prune_what_notion_no_longer_shares() {
+        let results = Arc::new(StdMutex::new(vec![
+            page(ROADMAP, 5, json!({ "type": "workspace" })),
+            page(TASK, 3, json!({ "type": "database_id", "database_id": TASKS_DB })),
+            database(1),
+        ]));
+        let notion = workspace(results.clone());
+        let (mirror, root) = temp_mirror("prune");
+        sync(&notion.client(), &mirror, true).await.unwrap();
+
+        // The task is unshared; an incremental sync cannot notice
+        results.lock().unwrap().remove(1);
+        let report = sync(&notion.client(), &mirror, false).await.unwrap();
+        assert_eq!(report.pruned, 0);
+        assert!(mirror.has_page(TASK));
+
+        let report = sync(&notion.client(), &mirror, true).await.unwrap();
+        assert_eq!(report.pruned, 1);
+        assert!(!mirror.has_page(TASK));
+        assert!(mirror.get_blocks(TASK).is_none());
+        assert_eq!(mirror.database_rows(TASKS_DB).unwrap().len(), 0);
+
+        // The database goes too once it is no longer shared
+        results.lock().unwrap().pop();
+        let report = sync(&notion.client(), &mirror, true).await.unwrap();
+        assert_eq!(report.pruned, 1);
+        assert!(mirror.get_database(TASKS_DB).is_none());
+        assert_eq!(mirror.page_ids(), vec![ROADMAP.replace('-', "")]);
+
+        notion.stop().await;
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn trashed_pages_are_dropped_by_any_sync() {
+        let results = Arc::new(StdMutex::new(vec![page(ROADMAP, 5, json!({ "type": "workspace" }))]));
+        let notion = workspace(results.clone());
+        let (mirror, root) = temp_mirror("trashed");
+        sync(&notion.client(), &mirror, false).await.unwrap();
+
+        let mut trashed = page(ROADMAP, 7, json!({ "type": "workspace" }));
+        trashed["in_trash"] = json!(true);
+        results.lock().unwrap()[0] = trashed;
+        let report = sync(&notion.client(), &mirror, false).await.unwrap();
+        assert_eq!((report.pages, report.pruned), (0, 1));
+        assert!(mirror.get_page(ROADMAP).is_none());
+
+        notion.stop().await;
+        fs::remove_dir_all(root).unwrap();
+    }
+}
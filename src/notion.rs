@@ -1,16 +1,56 @@
+use crate::audit::RequestIds;
 use crate::error::{NotionMcpError, NotionResult};
-use log::{debug, error};
-use reqwest::Client;
+use crate::file_proxy::{self, UrlSigner};
+use crate::ids::{normalize_block_id, normalize_id};
+use crate::journal::{self, JournalContext};
+use crate::mirror::Mirror;
+use crate::policy::Policy;
+use crate::properties::rich_text_to_plain;
+use crate::rate_limit::{RateLimiter, DEFAULT_REQUESTS_PER_SECOND};
+use crate::users::{payload_needs_directory, Directory, DirectoryCache};
+use log::{debug, error, warn};
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
 
 const NOTION_API_VERSION: &str = "2022-06-28";
+// Data source parents only exist from this API version on
+const NOTION_DATA_SOURCES_API_VERSION: &str = "2025-09-03";
 const NOTION_BASE_URL: &str = "https://api.notion.com/v1";
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+// Levels of nested blocks walked up to find the page a write changed
+const MAX_BLOCK_DEPTH: usize = 32;
 
 #[derive(Debug, Clone)]
 pub struct NotionClient {
     client: Client,
     api_key: String,
+    rate_limiter: Arc<RateLimiter>,
+    // Shared by clones so the user list is fetched once
+    directory: Arc<DirectoryCache>,
+    // Public base URL of the file proxy; when set, projections link
+    // Notion-hosted files through it instead of expiring signed URLs
+    file_proxy_base: Option<String>,
+    // Signs proxy links so they open without credentials
+    url_signer: Option<Arc<UrlSigner>>,
+    // Principal the signed links are issued to
+    link_caller: Option<String>,
+    base_url: String,
+    // Name of the workspace this client serves, when not the default one
+    workspace: Option<String>,
+    // Restrictions of the API key this client acts for
+    policy: Option<Arc<Policy>>,
+    // Collects Notion's request IDs of the writes made for one tool call
+    request_ids: Option<RequestIds>,
+    // Undo journal receiving the prior state of every write
+    journal: Option<JournalContext>,
+    // Local mirror whose copies are dropped when written through this client
+    mirror: Option<Arc<Mirror>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -20,24 +60,234 @@ pub struct NotionSearchResponse {
     pub has_more: bool,
 }
 
+// Where a new page is created
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageParent {
+    Database(String),
+    Page(String),
+    DataSource(String),
+    // Top level of the workspace (public integrations only)
+    Workspace,
+}
+
+impl PageParent {
+    // Parse an explicit parent: {"type": "database" | "page" | "data_source" | "workspace", "id": "..."}
+    pub fn from_params(value: &Value) -> NotionResult<Self> {
+        let parent_type = value["type"].as_str()
+            .ok_or_else(|| NotionMcpError::InvalidParams("Missing 'parent.type'".to_string()))?;
+        let id = || value["id"].as_str()
+            .map(|s| s.to_string())
+            .ok_or_else(|| NotionMcpError::InvalidParams("Missing 'parent.id'".to_string()));
+
+        match parent_type {
+            "database" | "database_id" => Ok(PageParent::Database(id()?)),
+            "page" | "page_id" => Ok(PageParent::Page(id()?)),
+            "data_source" | "data_source_id" => Ok(PageParent::DataSource(id()?)),
+            "workspace" => Ok(PageParent::Workspace),
+            other => Err(NotionMcpError::InvalidParams(format!(
+                "Unknown parent type '{}', expected database, page, data_source or workspace", other
+            ))),
+        }
+    }
+
+    // Same parent with its ID in canonical form
+    pub fn normalized(&self) -> NotionResult<Self> {
+        Ok(match self {
+            PageParent::Database(id) => PageParent::Database(normalize_id(id)?),
+            PageParent::Page(id) => PageParent::Page(normalize_id(id)?),
+            PageParent::DataSource(id) => PageParent::DataSource(normalize_id(id)?),
+            PageParent::Workspace => PageParent::Workspace,
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        match self {
+            PageParent::Database(id) => json!({ "type": "database_id", "database_id": id }),
+            PageParent::Page(id) => json!({ "type": "page_id", "page_id": id }),
+            PageParent::DataSource(id) => json!({ "type": "data_source_id", "data_source_id": id }),
+            PageParent::Workspace => json!({ "type": "workspace", "workspace": true }),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NotionPageResponse {
     pub id: String,
     pub url: String,
     pub properties: Value,
+    // Precondition for concurrent updates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_edited_time: Option<String>,
     pub content: Option<Vec<Value>>,
 }
 
 impl NotionClient {
     pub fn new(api_key: String) -> Self {
         // Validate API key format
-        if !api_key.starts_with("ntn_") && !api_key.starts_with("secret_") {
+        // Left empty when every caller connects their own account
+        if !api_key.is_empty() && !api_key.starts_with("ntn_") && !api_key.starts_with("secret_") {
             log::warn!("The Notion API key format doesn't seem valid. Current keys start with 'ntn_'");
         }
         
         Self {
             client: Client::new(),
             api_key,
+            rate_limiter: Arc::new(RateLimiter::new(DEFAULT_REQUESTS_PER_SECOND)),
+            directory: Arc::new(DirectoryCache::default()),
+            file_proxy_base: None,
+            url_signer: None,
+            link_caller: None,
+            base_url: NOTION_BASE_URL.to_string(),
+            workspace: None,
+            policy: None,
+            request_ids: None,
+            journal: None,
+            mirror: None,
+        }
+    }
+
+    // Enforce a policy on every request made through this client
+    pub fn with_policy(mut self, policy: Arc<Policy>) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    // Record the request IDs Notion returns for writes, for the audit log
+    pub fn with_request_ids(mut self, request_ids: RequestIds) -> Self {
+        self.request_ids = Some(request_ids);
+        self
+    }
+
+    // Capture the prior state of pages and blocks before each write
+    pub fn with_journal(mut self, journal: JournalContext) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    // Keep the mirror consistent with writes made through this client
+    pub fn with_mirror(mut self, mirror: Option<Arc<Mirror>>) -> Self {
+        self.mirror = mirror;
+        self
+    }
+
+    // Forget the mirrored page a written block belongs to, walking up from
+    // nested blocks. Reads go to Notion until the next sync.
+    async fn invalidate_mirrored(&self, block_id: &str) {
+        let Some(mirror) = &self.mirror else {
+            return;
+        };
+        let mut parent = json!({ "type": "block_id", "block_id": block_id });
+        for _ in 0..MAX_BLOCK_DEPTH {
+            let (kind, id) = match parent["type"].as_str() {
+                Some(kind @ ("page_id" | "block_id")) => (kind, parent[kind].as_str().unwrap_or("").to_string()),
+                _ => return,
+            };
+            if mirror.has_page(&id) {
+                if let Err(e) = mirror.invalidate(&id) {
+                    warn!("Could not invalidate mirrored page {}: {}", id, e);
+                }
+                return;
+            }
+            parent = match self.unrestricted().get_parent(kind.trim_end_matches("_id"), &id).await {
+                Ok(parent) => parent,
+                Err(e) => {
+                    warn!("Could not find the page of block {} to invalidate the mirror: {}", id, e);
+                    return;
+                }
+            };
+        }
+    }
+
+    // The same client without its policy, used by the policy itself to look
+    // up where objects sit
+    fn unrestricted(&self) -> Self {
+        Self { policy: None, ..self.clone() }
+    }
+
+    // Path segments of a request URL below the API base URL
+    fn route(&self, url: &reqwest::Url) -> Vec<String> {
+        let base_path = reqwest::Url::parse(&self.base_url).map(|u| u.path().to_string()).unwrap_or_default();
+        url.path()
+            .strip_prefix(base_path.trim_end_matches('/'))
+            .unwrap_or(url.path())
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    // Talk to another API endpoint, e.g. a proxy in front of Notion
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    // Mark the client as serving a named workspace, so links it emits
+    // lead back to the same workspace
+    pub fn with_workspace(mut self, name: String) -> Self {
+        self.workspace = Some(name);
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    // Emit stable proxy URLs for Notion-hosted files in projections
+    pub fn with_file_proxy(mut self, base_url: String) -> Self {
+        self.file_proxy_base = Some(base_url);
+        self
+    }
+
+    // Sign the proxy links this client emits
+    pub fn with_url_signer(mut self, signer: Option<Arc<UrlSigner>>) -> Self {
+        self.url_signer = signer;
+        self
+    }
+
+    pub fn signs_links(&self) -> bool {
+        self.file_proxy_base.is_some() && self.url_signer.is_some()
+    }
+
+    // Issue signed links to a caller, so opening them acts for that caller
+    // (its policy and Notion account) with read scope
+    pub fn with_link_caller(mut self, caller: String) -> Self {
+        self.link_caller = Some(caller);
+        self
+    }
+
+    // URL to show for the `index`-th file of a files property
+    fn media_url(&self, page_id: &str, property: &str, index: usize, file: &Value) -> Option<String> {
+        let url = file.get("url").and_then(|u| u.as_str())
+            .or_else(|| file.get("file").and_then(|f| f.get("url")).and_then(|u| u.as_str()))
+            .or_else(|| file.get("external").and_then(|f| f.get("url")).and_then(|u| u.as_str()))?;
+        match &self.file_proxy_base {
+            Some(base) if file["type"] == "file" => Some(file_proxy::proxy_url(
+                base,
+                page_id,
+                property,
+                index,
+                self.workspace.as_deref(),
+                self.url_signer.as_deref(),
+                self.link_caller.as_deref(),
+            )),
+            _ => Some(url.to_string()),
+        }
+    }
+
+    // Override the default request rate
+    pub fn with_rate_limit(mut self, requests_per_second: f64) -> Self {
+        self.rate_limiter = Arc::new(RateLimiter::new(requests_per_second));
+        self
+    }
+
+    // Same settings with another token. The rate limiter stays shared, since
+    // Notion counts requests per integration rather than per user.
+    pub fn with_token(&self, api_key: String) -> Self {
+        Self {
+            api_key,
+            directory: Arc::new(DirectoryCache::default()),
+            ..self.clone()
         }
     }
     
@@ -46,8 +296,8 @@ impl NotionClient {
         debug!("Validating Notion API connection...");
         
         let response = self.client
-            .post(&format!("{}/search", NOTION_BASE_URL))
-            .headers(self.headers())
+            .post(format!("{}/search", self.base_url))
+            .headers(self.headers(NOTION_API_VERSION))
             .json(&json!({
                 "query": "",
                 "page_size": 1
@@ -77,7 +327,7 @@ impl NotionClient {
     }
 
     // Authentication headers
-    fn headers(&self) -> reqwest::header::HeaderMap {
+    fn headers(&self, api_version: &str) -> reqwest::header::HeaderMap {
         let mut headers = reqwest::header::HeaderMap::new();
         headers.insert(
             "Authorization",
@@ -85,114 +335,216 @@ impl NotionClient {
         );
         headers.insert(
             "Notion-Version",
-            NOTION_API_VERSION.parse().unwrap(),
-        );
-        headers.insert(
-            "Content-Type",
-            "application/json".parse().unwrap(),
+            api_version.parse().unwrap(),
         );
+        // Content-Type is set by the body: JSON for most calls, multipart for uploads
         headers
     }
 
+    // Send a request through the rate limiter, retrying when Notion answers 429
+    async fn send(&self, request: RequestBuilder, context: &str) -> NotionResult<Value> {
+        self.send_with_version(request, context, NOTION_API_VERSION).await
+    }
+
+    async fn send_with_version(&self, request: RequestBuilder, context: &str, api_version: &str) -> NotionResult<Value> {
+        self.send_built(|| request.try_clone(), context, api_version).await
+    }
+
+    // Requests with streamed bodies (multipart uploads) cannot be cloned, so
+    // they are rebuilt for every attempt instead
+    async fn send_built(&self, build: impl Fn() -> Option<RequestBuilder>, context: &str, api_version: &str) -> NotionResult<Value> {
+        let mut attempt = 0;
+
+        let mut checked = None;
+        if let Some(policy) = &self.policy {
+            let request = build()
+                .and_then(|b| b.build().ok())
+                .ok_or_else(|| NotionMcpError::Server(format!("{}: request cannot be inspected", context)))?;
+            let route = self.route(request.url());
+            let route: Vec<&str> = route.iter().map(|s| s.as_str()).collect();
+            let body: Option<Value> = request.body()
+                .and_then(|b| b.as_bytes())
+                .and_then(|b| serde_json::from_slice(b).ok());
+            let raw = self.unrestricted();
+            policy.authorize(&raw, request.method(), &route, request.url().query(), body.as_ref()).await?;
+            checked = Some((policy, raw, request.url().clone()));
+        }
+
+        loop {
+            let attempt_request = build()
+                .ok_or_else(|| NotionMcpError::Server(format!("{}: request cannot be retried", context)))?;
+
+            self.rate_limiter.acquire().await;
+
+            let request = attempt_request
+                .headers(self.headers(api_version))
+                .build()
+                .map_err(|e| NotionMcpError::NotionApi(format!("{}: {}", context, e)))?;
+            // Searches and queries are POSTs too, but change nothing
+            let is_write = request.method() != reqwest::Method::GET
+                && !matches!(request.url().path().rsplit('/').next(), Some("search" | "query"));
+            let response = self.client.execute(request)
+                .await
+                .map_err(|e| {
+                    error!("{}: {}", context, e);
+                    NotionMcpError::NotionApi(format!("{}: {}", context, e))
+                })?;
+
+            let status = response.status();
+            let header_request_id = response.headers()
+                .get("x-notion-request-id")
+                .or_else(|| response.headers().get("x-request-id"))
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string());
+
+            if status.as_u16() == 429 && attempt < MAX_RATE_LIMIT_RETRIES {
+                let retry_after = response.headers()
+                    .get("Retry-After")
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .unwrap_or(1);
+                warn!("Notion rate limit reached, retrying in {}s", retry_after);
+                tokio::time::sleep(Duration::from_secs(retry_after)).await;
+                attempt += 1;
+                continue;
+            }
+
+            if !status.is_success() {
+                let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+                if is_write {
+                    let body_request_id = serde_json::from_str::<Value>(&error_text).ok()
+                        .and_then(|body| body["request_id"].as_str().map(|id| id.to_string()));
+                    self.record_request_id(body_request_id.or(header_request_id));
+                }
+                error!("Error en respuesta de Notion ({}): {}", status, error_text);
+                return Err(NotionMcpError::NotionApi(format!("Error HTTP {}: {}", status, error_text)));
+            }
+
+            let mut value = response.json::<Value>().await
+                .map_err(|e| {
+                    error!("Error al parsear respuesta JSON: {}", e);
+                    NotionMcpError::JsonParse(e.to_string())
+                })?;
+            if is_write {
+                self.record_request_id(value["request_id"].as_str().map(|id| id.to_string()).or(header_request_id));
+            }
+            if let Some((policy, raw, url)) = &checked {
+                let route = self.route(url);
+                let route: Vec<&str> = route.iter().map(|s| s.as_str()).collect();
+                policy.filter_response(raw, &route, &mut value).await?;
+            }
+            return Ok(value);
+        }
+    }
+
+    fn record_request_id(&self, request_id: Option<String>) {
+        if let (Some(request_ids), Some(request_id)) = (&self.request_ids, request_id) {
+            request_ids.lock().unwrap().push(request_id);
+        }
+    }
+
+    // Parent of a page, block, database or data source
+    pub async fn get_parent(&self, kind: &str, id: &str) -> NotionResult<Value> {
+        let version = if kind == "data_source" { NOTION_DATA_SOURCES_API_VERSION } else { NOTION_API_VERSION };
+        let object = self.send_with_version(
+            self.client.get(format!("{}/{}s/{}", self.base_url, kind, id)),
+            &format!("Error reading {} {}", kind, id),
+            version,
+        ).await?;
+        Ok(object["parent"].clone())
+    }
+
     // Search in Notion
     pub async fn search(&self, query: &str, limit: Option<u32>) -> NotionResult<NotionSearchResponse> {
         let limit = limit.unwrap_or(10);
         debug!("Searching in Notion: '{}' (limit: {})", query, limit);
         
-        let payload = json!({
+        let search_response = self.search_with_cursor(query, None, limit).await?;
+        
+        debug!("Search completed, {} results found", search_response.results.len());
+        Ok(search_response)
+    }
+
+    // Search one page of results, most recently edited first
+    pub async fn search_with_cursor(&self, query: &str, start_cursor: Option<&str>, page_size: u32) -> NotionResult<NotionSearchResponse> {
+        let mut payload = json!({
             "query": query,
-            "page_size": limit,
+            "page_size": page_size,
             "sort": {
                 "direction": "descending",
                 "timestamp": "last_edited_time"
             }
         });
         
-        let response = self.client
-            .post(&format!("{}/search", NOTION_BASE_URL))
-            .headers(self.headers())
-            .json(&payload)
-            .send()
-            .await
-            .map_err(|e| {
-                error!("Error searching in Notion: {}", e);
-                NotionMcpError::NotionApi(format!("Search error: {}", e))
-            })?;
-        
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            error!("Error en respuesta de Notion ({}): {}", status, error_text);
-            return Err(NotionMcpError::NotionApi(format!("Error HTTP {}: {}", status, error_text)));
+        if let Some(cursor) = start_cursor {
+            payload["start_cursor"] = json!(cursor);
         }
         
-        let search_response = response.json::<NotionSearchResponse>().await
-            .map_err(|e| {
-                error!("Error al parsear respuesta JSON: {}", e);
-                NotionMcpError::JsonParse(e.to_string())
-            })?;
+        let response = self.send(
+            self.client.post(format!("{}/search", self.base_url)).json(&payload),
+            "Search error",
+        ).await?;
         
-        debug!("Search completed, {} results found", search_response.results.len());
-        Ok(search_response)
+        Ok(serde_json::from_value(response)?)
     }
 
     // Get a page by ID
     pub async fn get_page(&self, page_id: &str) -> NotionResult<NotionPageResponse> {
+        let page_id = normalize_id(page_id)?;
         debug!("Getting page with ID: {}", page_id);
         
-        let response = self.client
-            .get(&format!("{}/pages/{}", NOTION_BASE_URL, page_id))
-            .headers(self.headers())
-            .send()
-            .await
-            .map_err(|e| {
-                error!("Error getting page: {}", e);
-                NotionMcpError::NotionApi(format!("Error getting page: {}", e))
-            })?;
+        let response = self.send(
+            self.client.get(format!("{}/pages/{}", self.base_url, page_id)),
+            "Error getting page",
+        ).await?;
         
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            error!("Error en respuesta de Notion ({}): {}", status, error_text);
-            return Err(NotionMcpError::NotionApi(format!("Error HTTP {}: {}", status, error_text)));
-        }
-        
-        let page = response.json::<NotionPageResponse>().await
-            .map_err(|e| {
-                error!("Error al parsear respuesta JSON: {}", e);
-                NotionMcpError::JsonParse(e.to_string())
-            })?;
+        let page: NotionPageResponse = serde_json::from_value(response)?;
         
         debug!("Page retrieved successfully: {}", page.id);
         Ok(page)
     }
 
+    // Get the raw page object, including parent and timestamps
+    pub async fn get_page_object(&self, page_id: &str) -> NotionResult<Value> {
+        let page_id = normalize_id(page_id)?;
+        self.send(
+            self.client.get(format!("{}/pages/{}", self.base_url, page_id)),
+            "Error getting page",
+        ).await
+    }
+
+    // Get a database object, including its property schema
+    pub async fn get_database(&self, database_id: &str) -> NotionResult<Value> {
+        let database_id = normalize_id(database_id)?;
+        debug!("Getting database with ID: {}", database_id);
+        
+        self.send(
+            self.client.get(format!("{}/databases/{}", self.base_url, database_id)),
+            "Error getting database",
+        ).await
+    }
+
+    // Get a data source object, including its property schema
+    pub async fn get_data_source(&self, data_source_id: &str) -> NotionResult<Value> {
+        let data_source_id = normalize_id(data_source_id)?;
+        debug!("Getting data source with ID: {}", data_source_id);
+        
+        self.send_with_version(
+            self.client.get(format!("{}/data_sources/{}", self.base_url, data_source_id)),
+            "Error getting data source",
+            NOTION_DATA_SOURCES_API_VERSION,
+        ).await
+    }
+
     // Get page content
     pub async fn get_page_content(&self, page_id: &str) -> NotionResult<Vec<Value>> {
+        let page_id = normalize_id(page_id)?;
         debug!("Getting page content with ID: {}", page_id);
         
-        let response = self.client
-            .get(&format!("{}/blocks/{}/children", NOTION_BASE_URL, page_id))
-            .headers(self.headers())
-            .send()
-            .await
-            .map_err(|e| {
-                error!("Error getting page content: {}", e);
-                NotionMcpError::NotionApi(format!("Error getting content: {}", e))
-            })?;
-        
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            error!("Error en respuesta de Notion ({}): {}", status, error_text);
-            return Err(NotionMcpError::NotionApi(format!("Error HTTP {}: {}", status, error_text)));
-        }
-        
-        let content_response: Value = response.json().await
-            .map_err(|e| {
-                error!("Error al parsear respuesta JSON: {}", e);
-                NotionMcpError::JsonParse(e.to_string())
-            })?;
+        let content_response = self.send(
+            self.client.get(format!("{}/blocks/{}/children", self.base_url, page_id)),
+            "Error getting content",
+        ).await?;
         
         let results = content_response["results"].as_array()
             .ok_or_else(|| NotionMcpError::JsonParse("No se encontró campo 'results'".to_string()))?
@@ -202,8 +554,62 @@ impl NotionClient {
         Ok(results)
     }
 
+    // Get every direct child of a block, following pagination
+    pub async fn get_block_children(&self, block_id: &str) -> NotionResult<Vec<Value>> {
+        let block_id = normalize_block_id(block_id)?;
+        let mut blocks = Vec::new();
+        let mut cursor: Option<String> = None;
+        
+        loop {
+            let mut request = self.client
+                .get(format!("{}/blocks/{}/children", self.base_url, block_id))
+                .query(&[("page_size", "100")]);
+            if let Some(c) = &cursor {
+                request = request.query(&[("start_cursor", c.as_str())]);
+            }
+            
+            let response = self.send(request, "Error getting block children").await?;
+            
+            if let Some(results) = response["results"].as_array() {
+                blocks.extend(results.iter().cloned());
+            }
+            
+            match response["next_cursor"].as_str() {
+                Some(next) if response["has_more"].as_bool().unwrap_or(false) => cursor = Some(next.to_string()),
+                _ => break,
+            }
+        }
+        
+        Ok(blocks)
+    }
+
+    // Get the full block tree of a page. Nested blocks are stored under a
+    // "children" key; child pages and databases are not descended into.
+    pub fn get_block_tree<'a>(&'a self, block_id: &'a str) -> Pin<Box<dyn Future<Output = NotionResult<Vec<Value>>> + Send + 'a>> {
+        Box::pin(async move {
+            let mut blocks = self.get_block_children(block_id).await?;
+            
+            for block in blocks.iter_mut() {
+                let block_type = block["type"].as_str().unwrap_or("");
+                let descend = block["has_children"].as_bool().unwrap_or(false)
+                    && block_type != "child_page"
+                    && block_type != "child_database";
+                
+                if descend {
+                    if let Some(id) = block["id"].as_str().map(|s| s.to_string()) {
+                        let children = self.get_block_tree(&id).await?;
+                        block["children"] = json!(children);
+                    }
+                }
+            }
+            
+            Ok(blocks)
+        })
+    }
+
     // Extraer información relevante de una página
-    fn extract_page_info(&self, page: &Value) -> Option<Value> {
+    pub fn extract_page_info(&self, page: &Value) -> Option<Value> {
+        let page_id = page["id"].as_str()?;
         let properties = page.get("properties")?;
         
         let brand_name = properties.get("Brand Name")?
@@ -256,13 +662,8 @@ impl NotionClient {
             if let Some(files) = properties.get(&key)
                 .and_then(|f| f.get("files"))
                 .and_then(|f| f.as_array()) {
-                for file in files {
-                    if let Some(url) = file.get("url").and_then(|u| u.as_str()) {
-                        images.push(json!({
-                            "id": i,
-                            "url": url
-                        }));
-                    } else if let Some(url) = file.get("file").and_then(|f| f.get("url")).and_then(|u| u.as_str()) {
+                for (index, file) in files.iter().enumerate() {
+                    if let Some(url) = self.media_url(page_id, &key, index, file) {
                         images.push(json!({
                             "id": i,
                             "url": url
@@ -287,12 +688,8 @@ impl NotionClient {
                 if let Some(files) = properties.get(prop_name)
                     .and_then(|f| f.get("files"))
                     .and_then(|f| f.as_array()) {
-                    if let Some(file) = files.first() {
-                        if let Some(url) = file.get("url").and_then(|u| u.as_str()) {
-                            obj.insert(key.to_string(), json!({ "url": url }));
-                        } else if let Some(url) = file.get("file").and_then(|f| f.get("url")).and_then(|u| u.as_str()) {
-                            obj.insert(key.to_string(), json!({ "url": url }));
-                        }
+                    if let Some(url) = files.first().and_then(|file| self.media_url(page_id, prop_name, 0, file)) {
+                        obj.insert(key.to_string(), json!({ "url": url }));
                     }
                 }
             }
@@ -305,7 +702,7 @@ impl NotionClient {
         });
         
         Some(json!({
-            "id": page["id"].as_str()?,
+            "id": page_id,
             "name": brand_name,
             "services": services,
             "description": description,
@@ -319,6 +716,7 @@ impl NotionClient {
     
     // Consultar una base de datos
     pub async fn query_database(&self, database_id: &str, filter: Option<Value>, limit: Option<u32>) -> NotionResult<Vec<Value>> {
+        let database_id = normalize_id(database_id)?;
         let limit = limit.unwrap_or(100);
         debug!("Consultando base de datos: {} (límite: {})", database_id, limit);
         
@@ -332,29 +730,10 @@ impl NotionClient {
             }
         }
         
-        let response = self.client
-            .post(&format!("{}/databases/{}/query", NOTION_BASE_URL, database_id))
-            .headers(self.headers())
-            .json(&payload)
-            .send()
-            .await
-            .map_err(|e| {
-                error!("Error al consultar base de datos: {}", e);
-                NotionMcpError::NotionApi(format!("Error en consulta de base de datos: {}", e))
-            })?;
-        
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            error!("Error en respuesta de Notion ({}): {}", status, error_text);
-            return Err(NotionMcpError::NotionApi(format!("Error HTTP {}: {}", status, error_text)));
-        }
-        
-        let db_response: Value = response.json().await
-            .map_err(|e| {
-                error!("Error al parsear respuesta JSON: {}", e);
-                NotionMcpError::JsonParse(e.to_string())
-            })?;
+        let db_response = self.send(
+            self.client.post(format!("{}/databases/{}/query", self.base_url, database_id)).json(&payload),
+            "Error en consulta de base de datos",
+        ).await?;
         
         let results = db_response["results"].as_array()
             .ok_or_else(|| NotionMcpError::JsonParse("No se encontró campo 'results'".to_string()))?
@@ -366,16 +745,159 @@ impl NotionClient {
         Ok(results)
     }
 
-    // Crear una página
-    pub async fn create_page(&self, parent_id: &str, properties: Value, content: Option<Vec<Value>>) -> NotionResult<Value> {
-        debug!("Creando nueva página en parent_id: {}", parent_id);
+    // Get every row of a database as raw page objects, following pagination
+    pub async fn query_database_pages(&self, database_id: &str, filter: Option<Value>) -> NotionResult<Vec<Value>> {
+        let database_id = normalize_id(database_id)?;
+        let mut pages = Vec::new();
+        let mut cursor: Option<String> = None;
         
-        let is_database = parent_id.contains("-");
+        loop {
+            let mut payload = json!({
+                "page_size": 100
+            });
+            if let Some(f) = &filter {
+                payload["filter"] = f.clone();
+            }
+            if let Some(c) = &cursor {
+                payload["start_cursor"] = json!(c);
+            }
+            
+            let response = self.send(
+                self.client.post(format!("{}/databases/{}/query", self.base_url, database_id)).json(&payload),
+                "Error en consulta de base de datos",
+            ).await?;
+            
+            if let Some(results) = response["results"].as_array() {
+                pages.extend(results.iter().cloned());
+            }
+            
+            match response["next_cursor"].as_str() {
+                Some(next) if response["has_more"].as_bool().unwrap_or(false) => cursor = Some(next.to_string()),
+                _ => break,
+            }
+        }
+        
+        debug!("Database {} returned {} rows", database_id, pages.len());
+        Ok(pages)
+    }
+
+    // Get the full value of a page property, following pagination. Needed for
+    // relations and rollups, which page objects truncate at 25 items. Returns
+    // every item in "results" and the aggregate value in "property_item".
+    pub async fn get_property_item(&self, page_id: &str, property_id: &str) -> NotionResult<Value> {
+        let page_id = normalize_id(page_id)?;
+        let mut results = Vec::new();
+        let mut cursor: Option<String> = None;
+        
+        loop {
+            let mut request = self.client
+                .get(format!("{}/pages/{}/properties/{}", self.base_url, page_id, property_id))
+                .query(&[("page_size", "100")]);
+            if let Some(c) = &cursor {
+                request = request.query(&[("start_cursor", c.as_str())]);
+            }
+            
+            let response = self.send(request, "Error getting property item").await?;
+            
+            // Single-value properties are returned as the item itself
+            if response["object"] != "list" {
+                return Ok(json!({ "results": [], "property_item": response }));
+            }
+            if let Some(items) = response["results"].as_array() {
+                results.extend(items.iter().cloned());
+            }
+            
+            match response["next_cursor"].as_str() {
+                Some(next) if response["has_more"].as_bool().unwrap_or(false) => cursor = Some(next.to_string()),
+                _ => return Ok(json!({ "results": results, "property_item": response["property_item"] })),
+            }
+        }
+    }
+
+    // Start a file upload. `payload` holds the mode, filename, content type
+    // and, for multi-part uploads, the number of parts.
+    pub async fn create_file_upload(&self, payload: Value) -> NotionResult<Value> {
+        self.send(
+            self.client.post(format!("{}/file_uploads", self.base_url)).json(&payload),
+            "Error creating file upload",
+        ).await
+    }
+
+    // Send the contents of a file upload, or one part of a multi-part upload
+    pub async fn send_file_upload(
+        &self,
+        upload_id: &str,
+        data: &[u8],
+        filename: &str,
+        content_type: &str,
+        part_number: Option<usize>,
+    ) -> NotionResult<Value> {
+        let upload_id = normalize_id(upload_id)?;
+        let url = format!("{}/file_uploads/{}/send", self.base_url, upload_id);
+        let build = || {
+            let file = Part::bytes(data.to_vec())
+                .file_name(filename.to_string())
+                .mime_str(content_type)
+                .ok()?;
+            let mut form = Form::new().part("file", file);
+            if let Some(part) = part_number {
+                form = form.text("part_number", part.to_string());
+            }
+            Some(self.client.post(&url).multipart(form))
+        };
+        self.send_built(build, "Error sending file upload", NOTION_API_VERSION).await
+    }
+
+    // Finish a multi-part upload once every part has been sent
+    pub async fn complete_file_upload(&self, upload_id: &str) -> NotionResult<Value> {
+        let upload_id = normalize_id(upload_id)?;
+        self.send(
+            self.client.post(format!("{}/file_uploads/{}/complete", self.base_url, upload_id)).json(&json!({})),
+            "Error completing file upload",
+        ).await
+    }
+
+    // Download a file such as a Notion-hosted attachment. Signed file URLs
+    // must not receive the integration's Authorization header.
+    pub async fn download(&self, url: &str) -> NotionResult<Vec<u8>> {
+        let response = self.client.get(url).send().await
+            .map_err(|e| NotionMcpError::NotionApi(format!("Error downloading file: {}", e)))?;
+        
+        if !response.status().is_success() {
+            return Err(NotionMcpError::NotionApi(format!("Error HTTP {} downloading {}", response.status(), url)));
+        }
+        
+        let bytes = response.bytes().await
+            .map_err(|e| NotionMcpError::NotionApi(format!("Error downloading file: {}", e)))?;
+        Ok(bytes.to_vec())
+    }
+
+    // Work out whether an ID is a database or a page by asking Notion. Only
+    // a not-found answer moves on to the next kind; any other failure (bad
+    // token, rate limit, network) is returned as is.
+    pub async fn detect_parent(&self, id: &str) -> NotionResult<PageParent> {
+        let id = normalize_id(id)?;
+        match self.get_database(&id).await {
+            Ok(_) => return Ok(PageParent::Database(id)),
+            Err(e) if e.is_not_found() => {}
+            Err(e) => return Err(e),
+        }
+        match self.get_page_object(&id).await {
+            Ok(_) => Ok(PageParent::Page(id)),
+            Err(e) if e.is_not_found() => Err(NotionMcpError::ResourceNotFound(format!(
+                "{} is not a page or database shared with the integration", id
+            ))),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Crear una página
+    pub async fn create_page(&self, parent: &PageParent, mut properties: Value, content: Option<Vec<Value>>) -> NotionResult<Value> {
+        debug!("Creando nueva página en {:?}", parent);
+        self.resolve_people_payload(&mut properties).await?;
         
         let mut payload = json!({
-            "parent": {
-                if is_database { "database_id" } else { "page_id" }: parent_id
-            },
+            "parent": parent.normalized()?.to_json(),
             "properties": properties
         });
         
@@ -385,68 +907,266 @@ impl NotionClient {
             }
         }
         
-        let response = self.client
-            .post(&format!("{}/pages", NOTION_BASE_URL))
-            .headers(self.headers())
-            .json(&payload)
-            .send()
-            .await
-            .map_err(|e| {
-                error!("Error al crear página: {}", e);
-                NotionMcpError::NotionApi(format!("Error al crear página: {}", e))
-            })?;
+        let api_version = match parent {
+            PageParent::DataSource(_) => NOTION_DATA_SOURCES_API_VERSION,
+            _ => NOTION_API_VERSION,
+        };
         
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            error!("Error en respuesta de Notion ({}): {}", status, error_text);
-            return Err(NotionMcpError::NotionApi(format!("Error HTTP {}: {}", status, error_text)));
-        }
-        
-        let page_response: Value = response.json().await
-            .map_err(|e| {
-                error!("Error al parsear respuesta JSON: {}", e);
-                NotionMcpError::JsonParse(e.to_string())
-            })?;
+        let page_response = self.send_with_version(
+            self.client.post(format!("{}/pages", self.base_url)).json(&payload),
+            "Error al crear página",
+            api_version,
+        ).await?;
         
         debug!("Página creada correctamente: {}", page_response["id"].as_str().unwrap_or("unknown"));
+        if let Some(journal) = &self.journal {
+            journal.created(&page_response);
+        }
+        let page_id = page_response["id"].as_str().unwrap_or("");
+        match &page_response["parent"] {
+            parent if parent["database_id"].is_string() => {
+                if let Some(Err(e)) = self.mirror.as_ref().map(|m| m.add_row(parent["database_id"].as_str().unwrap_or(""), page_id)) {
+                    warn!("Could not add page {} to the mirrored rows: {}", page_id, e);
+                }
+            }
+            parent => {
+                if let Some(parent_id) = parent["page_id"].as_str() {
+                    self.invalidate_mirrored(parent_id).await;
+                }
+            }
+        }
         Ok(page_response)
     }
 
+    // Append children to a block or page (at most 100 per request).
+    // Returns the created blocks.
+    pub async fn append_block_children(&self, block_id: &str, children: Vec<Value>) -> NotionResult<Vec<Value>> {
+        let block_id = normalize_block_id(block_id)?;
+        debug!("Appending {} blocks to {}", children.len(), block_id);
+        
+        let response = self.send(
+            self.client.patch(format!("{}/blocks/{}/children", self.base_url, block_id)).json(&json!({
+                "children": children
+            })),
+            "Error appending blocks",
+        ).await?;
+        
+        let created = response["results"].as_array().cloned().unwrap_or_default();
+        if let Some(journal) = &self.journal {
+            journal.appended(&block_id, &created);
+        }
+        self.invalidate_mirrored(&block_id).await;
+        Ok(created)
+    }
+
+    // Get a single block
+    pub async fn get_block(&self, block_id: &str) -> NotionResult<Value> {
+        let block_id = normalize_block_id(block_id)?;
+        self.send(
+            self.client.get(format!("{}/blocks/{}", self.base_url, block_id)),
+            "Error getting block",
+        ).await
+    }
+
+    // Move a block to the trash
+    pub async fn delete_block(&self, block_id: &str) -> NotionResult<Value> {
+        let block_id = normalize_block_id(block_id)?;
+        debug!("Deleting block {}", block_id);
+        let block = self.send(
+            self.client.delete(format!("{}/blocks/{}", self.base_url, block_id)),
+            "Error deleting block",
+        ).await?;
+        if let Some(journal) = &self.journal {
+            journal.deleted(&block_id);
+        }
+        self.invalidate_mirrored(&block_id).await;
+        Ok(block)
+    }
+
     // Actualizar una página
-    pub async fn update_page(&self, page_id: &str, properties: Value) -> NotionResult<Value> {
+    pub async fn update_page(&self, page_id: &str, mut properties: Value) -> NotionResult<Value> {
+        let page_id = normalize_id(page_id)?;
         debug!("Actualizando página con ID: {}", page_id);
+        self.resolve_people_payload(&mut properties).await?;
         
+        let names: Vec<String> = properties.as_object().map(|p| p.keys().cloned().collect()).unwrap_or_default();
+        let before = match &self.journal {
+            Some(_) => {
+                let mut page = self.get_page_object(&page_id).await?;
+                journal::complete_pre_image(self, &mut page, &names).await;
+                Some(page)
+            }
+            None => None,
+        };
         let payload = json!({
             "properties": properties
         });
         
-        let response = self.client
-            .patch(&format!("{}/pages/{}", NOTION_BASE_URL, page_id))
-            .headers(self.headers())
-            .json(&payload)
-            .send()
-            .await
-            .map_err(|e| {
-                error!("Error al actualizar página: {}", e);
-                NotionMcpError::NotionApi(format!("Error al actualizar página: {}", e))
-            })?;
+        let page_response = self.send(
+            self.client.patch(format!("{}/pages/{}", self.base_url, page_id)).json(&payload),
+            "Error al actualizar página",
+        ).await?;
         
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            error!("Error en respuesta de Notion ({}): {}", status, error_text);
-            return Err(NotionMcpError::NotionApi(format!("Error HTTP {}: {}", status, error_text)));
+        debug!("Página actualizada correctamente");
+        if let (Some(journal), Some(before)) = (&self.journal, &before) {
+            journal.updated(before, &names, &page_response);
+        }
+        if let Some(Err(e)) = self.mirror.as_ref().map(|m| m.invalidate(&page_id)) {
+            warn!("Could not invalidate mirrored page {}: {}", page_id, e);
         }
+        Ok(page_response)
+    }
+
+    // Get every comment on a page or block, following pagination. Only
+    // unresolved comments are returned by the API.
+    pub async fn list_comments(&self, block_id: &str) -> NotionResult<Vec<Value>> {
+        let block_id = normalize_block_id(block_id)?;
+        let mut comments = Vec::new();
+        let mut cursor: Option<String> = None;
         
-        let page_response: Value = response.json().await
-            .map_err(|e| {
-                error!("Error al parsear respuesta JSON: {}", e);
-                NotionMcpError::JsonParse(e.to_string())
-            })?;
+        loop {
+            let mut request = self.client
+                .get(format!("{}/comments", self.base_url))
+                .query(&[("block_id", block_id.as_str()), ("page_size", "100")]);
+            if let Some(c) = &cursor {
+                request = request.query(&[("start_cursor", c.as_str())]);
+            }
+            
+            let response = self.send(request, "Error getting comments").await?;
+            
+            if let Some(results) = response["results"].as_array() {
+                comments.extend(results.iter().cloned());
+            }
+            
+            match response["next_cursor"].as_str() {
+                Some(next) if response["has_more"].as_bool().unwrap_or(false) => cursor = Some(next.to_string()),
+                _ => break,
+            }
+        }
         
-        debug!("Página actualizada correctamente");
-        Ok(page_response)
+        debug!("{} comments found on {}", comments.len(), block_id);
+        Ok(comments)
+    }
+
+    // Start a new discussion on a page
+    pub async fn add_comment(&self, page_id: &str, rich_text: Value) -> NotionResult<Value> {
+        let page_id = normalize_id(page_id)?;
+        
+        self.send(
+            self.client.post(format!("{}/comments", self.base_url)).json(&json!({
+                "parent": { "page_id": page_id },
+                "rich_text": rich_text
+            })),
+            "Error adding comment",
+        ).await
+    }
+
+    // Reply to an existing discussion thread
+    pub async fn reply_to_discussion(&self, discussion_id: &str, rich_text: Value) -> NotionResult<Value> {
+        let discussion_id = normalize_id(discussion_id)?;
+        
+        self.send(
+            self.client.post(format!("{}/comments", self.base_url)).json(&json!({
+                "discussion_id": discussion_id,
+                "rich_text": rich_text
+            })),
+            "Error replying to discussion",
+        ).await
+    }
+
+    // Get every user of the workspace, following pagination. Guests are not
+    // included by the API.
+    pub async fn list_users(&self) -> NotionResult<Vec<Value>> {
+        let mut users = Vec::new();
+        let mut cursor: Option<String> = None;
+        
+        loop {
+            let mut request = self.client
+                .get(format!("{}/users", self.base_url))
+                .query(&[("page_size", "100")]);
+            if let Some(c) = &cursor {
+                request = request.query(&[("start_cursor", c.as_str())]);
+            }
+            
+            let response = self.send(request, "Error listing users").await?;
+            
+            if let Some(results) = response["results"].as_array() {
+                users.extend(results.iter().cloned());
+            }
+            
+            match response["next_cursor"].as_str() {
+                Some(next) if response["has_more"].as_bool().unwrap_or(false) => cursor = Some(next.to_string()),
+                _ => break,
+            }
+        }
+        
+        self.directory.store(Directory::from_users(users.clone()));
+        Ok(users)
+    }
+
+    pub async fn get_user(&self, user_id: &str) -> NotionResult<Value> {
+        let user_id = normalize_id(user_id)?;
+        self.send(
+            self.client.get(format!("{}/users/{}", self.base_url, user_id)),
+            "Error getting user",
+        ).await
+    }
+
+    // The bot user of this integration, including its workspace name
+    pub async fn get_bot_user(&self) -> NotionResult<Value> {
+        self.send(
+            self.client.get(format!("{}/users/me", self.base_url)),
+            "Error getting bot user",
+        ).await
+    }
+
+    // Cached ID -> user directory, refreshed when older than a few minutes
+    pub async fn directory(&self) -> NotionResult<Arc<Directory>> {
+        if let Some(directory) = self.directory.get() {
+            return Ok(directory);
+        }
+        self.list_users().await?;
+        self.directory.get()
+            .ok_or_else(|| NotionMcpError::Server("User directory could not be loaded".to_string()))
+    }
+
+    // Replace people given by name or email with their user IDs
+    pub async fn resolve_people_payload(&self, properties: &mut Value) -> NotionResult<()> {
+        if !payload_needs_directory(properties) {
+            return Ok(());
+        }
+        self.directory().await?.resolve_people_payload(properties)
+    }
+
+    // Archive (move to trash) or restore a page
+    pub async fn set_archived(&self, page_id: &str, archived: bool) -> NotionResult<Value> {
+        let page_id = normalize_id(page_id)?;
+        debug!("Setting archived={} on page {}", archived, page_id);
+        
+        let before = match &self.journal {
+            Some(_) => Some(self.get_page_object(&page_id).await?),
+            None => None,
+        };
+        let page = self.send(
+            self.client.patch(format!("{}/pages/{}", self.base_url, page_id)).json(&json!({
+                "archived": archived
+            })),
+            if archived { "Error archiving page" } else { "Error restoring page" },
+        ).await?;
+        if let (Some(journal), Some(before)) = (&self.journal, &before) {
+            journal.archived(before, &page);
+        }
+        if let Some(mirror) = &self.mirror {
+            let result = if archived { mirror.remove(&page_id) } else { mirror.invalidate(&page_id) };
+            if let Err(e) = result {
+                warn!("Could not invalidate mirrored page {}: {}", page_id, e);
+            }
+            // The parent page lists its child pages
+            if let Some(parent_id) = page["parent"]["page_id"].as_str() {
+                self.invalidate_mirrored(parent_id).await;
+            }
+        }
+        Ok(page)
     }
 
     // Convertir texto plano a bloques de Notion
@@ -502,4 +1222,27 @@ impl NotionClient {
             .collect::<Vec<String>>()
             .join("\n\n")
     }
+
+    // Plain text of a single block, whatever its type
+    pub fn block_text(block: &Value) -> String {
+        let block_type = block["type"].as_str().unwrap_or("");
+        let data = &block[block_type];
+        
+        match block_type {
+            "child_page" | "child_database" => data["title"].as_str().unwrap_or("").to_string(),
+            "table_row" => data["cells"].as_array()
+                .map(|cells| cells.iter().map(rich_text_to_plain).collect::<Vec<_>>().join(" | "))
+                .unwrap_or_default(),
+            "equation" => data["expression"].as_str().unwrap_or("").to_string(),
+            _ => {
+                let text = rich_text_to_plain(&data["rich_text"]);
+                let caption = rich_text_to_plain(&data["caption"]);
+                match (text.is_empty(), caption.is_empty()) {
+                    (_, true) => text,
+                    (true, false) => caption,
+                    (false, false) => format!("{} {}", text, caption),
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,295 @@
+use crate::error::{NotionMcpError, NotionResult};
+use crate::notion::NotionClient;
+use crate::search_index::SearchIndex;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload as AeadPayload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const NOTION_AUTHORIZE_URL: &str = "https://api.notion.com/v1/oauth/authorize";
+const NOTION_TOKEN_URL: &str = "https://api.notion.com/v1/oauth/token";
+// Path Notion redirects the browser to after the user approves access
+pub const CALLBACK_PATH: &str = "/oauth/notion/callback";
+// How long an authorization link stays usable
+const STATE_TTL: Duration = Duration::from_secs(10 * 60);
+const NONCE_LEN: usize = 12;
+
+// A connected user's Notion client and their own full-text index
+pub type UserClient = (Arc<NotionClient>, Arc<SearchIndex>);
+
+// Credentials of the Notion public integration and where tokens are kept
+#[derive(Debug)]
+pub struct NotionOAuthConfig {
+    pub client_id: String,
+    client_secret: String,
+    pub redirect_uri: String,
+    pub token_file: PathBuf,
+    encryption_key: Vec<u8>,
+}
+
+impl NotionOAuthConfig {
+    // Read NOTION_OAUTH_* variables; per-user connections stay off unless
+    // NOTION_OAUTH_CLIENT_ID is set
+    pub fn from_env() -> NotionResult<Option<Self>> {
+        let Ok(client_id) = env::var("NOTION_OAUTH_CLIENT_ID") else {
+            return Ok(None);
+        };
+        let required = |name: &str| env::var(name)
+            .map_err(|_| NotionMcpError::InvalidParams(format!("NOTION_OAUTH_CLIENT_ID requires {}", name)));
+
+        let client_secret = required("NOTION_OAUTH_CLIENT_SECRET")?;
+        let redirect_uri = required("NOTION_OAUTH_REDIRECT_URI")?;
+        let encryption_key = STANDARD.decode(required("NOTION_OAUTH_ENCRYPTION_KEY")?.trim())
+            .ok()
+            .filter(|key| key.len() == 32)
+            .ok_or_else(|| NotionMcpError::InvalidParams("NOTION_OAUTH_ENCRYPTION_KEY must be 32 bytes encoded in base64".to_string()))?;
+        let token_file = PathBuf::from(env::var("NOTION_OAUTH_TOKEN_FILE").unwrap_or_else(|_| "./notion-tokens.json".to_string()));
+
+        Ok(Some(Self { client_id, client_secret, redirect_uri, token_file, encryption_key }))
+    }
+}
+
+// What Notion returns from the token endpoint
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    bot_id: Option<String>,
+    workspace_id: Option<String>,
+    workspace_name: Option<String>,
+    #[serde(default)]
+    owner: Value,
+}
+
+// Secret part of a connection, stored encrypted
+#[derive(Debug, Serialize, Deserialize)]
+struct Secrets {
+    access_token: String,
+    refresh_token: Option<String>,
+}
+
+// A user's connection as written to the token file. Only `secrets` is
+// sensitive; the rest describes the connection to its owner.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredConnection {
+    secrets: String,
+    workspace_id: Option<String>,
+    workspace_name: Option<String>,
+    bot_id: Option<String>,
+    notion_user_id: Option<String>,
+    // Unix seconds
+    connected_at: Option<u64>,
+}
+
+// Per-user Notion tokens, encrypted with AES-256-GCM. The user name is
+// bound as associated data so entries cannot be swapped between users.
+struct TokenStore {
+    path: PathBuf,
+    cipher: Aes256Gcm,
+    connections: Mutex<HashMap<String, StoredConnection>>,
+}
+
+impl TokenStore {
+    fn open(path: PathBuf, key: &[u8]) -> NotionResult<Self> {
+        let cipher = Aes256Gcm::new_from_slice(key)
+            .map_err(|_| NotionMcpError::InvalidParams("Invalid token encryption key".to_string()))?;
+        let connections = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| NotionMcpError::JsonParse(format!("Invalid token file {}: {}", path.display(), e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, cipher, connections: Mutex::new(connections) })
+    }
+
+    fn encrypt(&self, user: &str, secrets: &Secrets) -> NotionResult<String> {
+        let plaintext = serde_json::to_vec(secrets)?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher.encrypt(&nonce, AeadPayload { msg: &plaintext, aad: user.as_bytes() })
+            .map_err(|_| NotionMcpError::Unknown("Could not encrypt Notion token".to_string()))?;
+        let mut blob = nonce.to_vec();
+        blob.extend(ciphertext);
+        Ok(STANDARD.encode(blob))
+    }
+
+    fn decrypt(&self, user: &str, blob: &str) -> NotionResult<Secrets> {
+        let blob = STANDARD.decode(blob)
+            .ok()
+            .filter(|b| b.len() > NONCE_LEN)
+            .ok_or_else(|| NotionMcpError::Authentication(format!("Stored Notion token of '{}' is corrupted", user)))?;
+        let (nonce, ciphertext) = blob.split_at(NONCE_LEN);
+        let plaintext = self.cipher.decrypt(Nonce::from_slice(nonce), AeadPayload { msg: ciphertext, aad: user.as_bytes() })
+            .map_err(|_| NotionMcpError::Authentication(format!(
+                "Stored Notion token of '{}' cannot be decrypted; was NOTION_OAUTH_ENCRYPTION_KEY changed?", user
+            )))?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+
+    // Rewrite the whole file through a temporary one so a crash never
+    // leaves it half written
+    fn save(&self, connections: &HashMap<String, StoredConnection>) -> NotionResult<()> {
+        let content = serde_json::to_string_pretty(connections)?;
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, content)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&tmp, fs::Permissions::from_mode(0o600))?;
+        }
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    fn access_token(&self, user: &str) -> NotionResult<Option<String>> {
+        let stored = self.connections.lock().unwrap().get(user).cloned();
+        stored.map(|c| self.decrypt(user, &c.secrets).map(|s| s.access_token)).transpose()
+    }
+
+    fn insert(&self, user: &str, connection: StoredConnection) -> NotionResult<()> {
+        let mut connections = self.connections.lock().unwrap();
+        connections.insert(user.to_string(), connection);
+        self.save(&connections)
+    }
+
+    fn remove(&self, user: &str) -> NotionResult<bool> {
+        let mut connections = self.connections.lock().unwrap();
+        let removed = connections.remove(user).is_some();
+        if removed {
+            self.save(&connections)?;
+        }
+        Ok(removed)
+    }
+
+    fn describe(&self, user: &str) -> Option<Value> {
+        self.connections.lock().unwrap().get(user).map(|c| json!({
+            "workspace_id": c.workspace_id,
+            "workspace_name": c.workspace_name,
+            "bot_id": c.bot_id,
+            "notion_user_id": c.notion_user_id,
+            "connected_at": c.connected_at
+        }))
+    }
+}
+
+// Authorization-code flow of a Notion public integration, with one client
+// per connected user
+pub struct NotionOAuth {
+    config: NotionOAuthConfig,
+    store: TokenStore,
+    // Settings copied into every user's client
+    template: NotionClient,
+    http: reqwest::Client,
+    // state -> (user, issued at)
+    pending: Mutex<HashMap<String, (String, Instant)>>,
+    clients: Mutex<HashMap<String, UserClient>>,
+}
+
+impl NotionOAuth {
+    pub fn open(config: NotionOAuthConfig, template: NotionClient) -> NotionResult<Self> {
+        let store = TokenStore::open(config.token_file.clone(), &config.encryption_key)?;
+        Ok(Self {
+            config,
+            store,
+            template,
+            http: reqwest::Client::new(),
+            pending: Mutex::new(HashMap::new()),
+            clients: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // Link the user opens to grant access; the state ties the callback to them
+    pub fn authorize_url(&self, user: &str) -> NotionResult<String> {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let state = URL_SAFE_NO_PAD.encode(bytes);
+
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, (_, issued)| issued.elapsed() < STATE_TTL);
+        pending.insert(state.clone(), (user.to_string(), Instant::now()));
+
+        let url = reqwest::Url::parse_with_params(NOTION_AUTHORIZE_URL, &[
+            ("client_id", self.config.client_id.as_str()),
+            ("response_type", "code"),
+            ("owner", "user"),
+            ("redirect_uri", self.config.redirect_uri.as_str()),
+            ("state", state.as_str()),
+        ])
+        .map_err(|e| NotionMcpError::InvalidParams(format!("Invalid Notion OAuth settings: {}", e)))?;
+        Ok(url.to_string())
+    }
+
+    // Finish the flow started by `authorize_url`: exchange the code and store
+    // the token for the user who asked for the link. Returns that user.
+    pub async fn complete(&self, code: &str, state: &str) -> NotionResult<String> {
+        let user = match self.pending.lock().unwrap().remove(state) {
+            Some((user, issued)) if issued.elapsed() < STATE_TTL => user,
+            _ => return Err(NotionMcpError::Authentication("Unknown or expired authorization link; request a new one".to_string())),
+        };
+
+        let response = self.http.post(NOTION_TOKEN_URL)
+            .basic_auth(&self.config.client_id, Some(&self.config.client_secret))
+            .json(&json!({
+                "grant_type": "authorization_code",
+                "code": code,
+                "redirect_uri": self.config.redirect_uri
+            }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body: Value = response.json().await.unwrap_or_default();
+            return Err(NotionMcpError::Authentication(format!(
+                "Notion refused the authorization code ({}): {}", status,
+                body["error_description"].as_str().or(body["error"].as_str()).unwrap_or("no details")
+            )));
+        }
+        let tokens: TokenResponse = response.json().await
+            .map_err(|e| NotionMcpError::JsonParse(format!("Unexpected token response: {}", e)))?;
+
+        let secrets = Secrets { access_token: tokens.access_token, refresh_token: tokens.refresh_token };
+        let connection = StoredConnection {
+            secrets: self.store.encrypt(&user, &secrets)?,
+            workspace_id: tokens.workspace_id,
+            workspace_name: tokens.workspace_name,
+            bot_id: tokens.bot_id,
+            notion_user_id: tokens.owner["user"]["id"].as_str().map(|s| s.to_string()),
+            connected_at: SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs()),
+        };
+        self.store.insert(&user, connection)?;
+        self.clients.lock().unwrap().remove(&user);
+        info!("Connected Notion account for '{}'", user);
+        Ok(user)
+    }
+
+    pub fn connection(&self, user: &str) -> Option<Value> {
+        self.store.describe(user)
+    }
+
+    pub fn disconnect(&self, user: &str) -> NotionResult<bool> {
+        self.clients.lock().unwrap().remove(user);
+        self.store.remove(user)
+    }
+
+    // Client and search index of a connected user
+    pub fn client_for(&self, user: &str) -> NotionResult<Option<UserClient>> {
+        if let Some(entry) = self.clients.lock().unwrap().get(user) {
+            return Ok(Some(entry.clone()));
+        }
+        let Some(token) = self.store.access_token(user)? else {
+            return Ok(None);
+        };
+        let entry = (Arc::new(self.template.with_token(token)), Arc::new(SearchIndex::new()));
+        self.clients.lock().unwrap().insert(user.to_string(), entry.clone());
+        Ok(Some(entry))
+    }
+}
@@ -0,0 +1,541 @@
+use crate::error::{NotionMcpError, NotionResult};
+use crate::ids::normalize_id;
+use crate::import::MAX_BLOCKS_PER_REQUEST;
+use crate::notion::{NotionClient, PageParent};
+use crate::properties::property_to_plain_json;
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::future::Future;
+use std::pin::Pin;
+
+type CopyFuture<'a, T> = Pin<Box<dyn Future<Output = NotionResult<T>> + Send + 'a>>;
+
+// Block types the API cannot create, or that would not be a faithful copy
+const UNCOPYABLE_BLOCKS: &[(&str, &str)] = &[
+    ("child_database", "databases cannot be copied through the API"),
+    ("link_preview", "link previews cannot be created through the API"),
+    ("template", "template blocks cannot be created through the API"),
+    ("unsupported", "block type not supported by the API"),
+];
+
+// File-like blocks whose Notion-hosted file cannot be re-attached by URL
+const FILE_BLOCKS: &[&str] = &["image", "video", "audio", "file", "pdf"];
+
+// Block types that must be created together with their children
+const INLINE_CHILDREN: &[&str] = &["table", "column_list", "column"];
+
+// Parents followed up from a target before giving up looking for the source
+const MAX_ANCESTORS: usize = 64;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedBlock {
+    pub block_id: String,
+    pub block_type: String,
+    pub reason: String,
+}
+
+// A property whose value the copy does not have
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedProperty {
+    pub page_id: String,
+    pub property: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CopiedPage {
+    pub source_id: String,
+    pub page_id: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CopyReport {
+    pub source_id: String,
+    pub page_id: String,
+    pub url: String,
+    // Every page created, including copies of child pages
+    pub pages: Vec<CopiedPage>,
+    pub copied_blocks: usize,
+    pub skipped: Vec<SkippedBlock>,
+    pub skipped_properties: Vec<SkippedProperty>,
+    // Why the copy stopped part-way; `pages` lists what was created before
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MoveReport {
+    #[serde(flatten)]
+    pub copy: CopyReport,
+    // The original is left in place when any block or property could not
+    // be copied, or the copy stopped part-way
+    pub original_archived: bool,
+}
+
+// Parent of an existing page, if pages can be created there
+fn page_parent(page: &Value) -> Option<PageParent> {
+    let parent = &page["parent"];
+    let id = |key: &str| parent[key].as_str().map(|s| s.to_string());
+    match parent["type"].as_str()? {
+        "database_id" => id("database_id").map(PageParent::Database),
+        "data_source_id" => id("data_source_id").map(PageParent::DataSource),
+        "page_id" => id("page_id").map(PageParent::Page),
+        "workspace" => Some(PageParent::Workspace),
+        _ => None,
+    }
+}
+
+fn clean_rich_text(items: &Value) -> Value {
+    let items = items.as_array().cloned().unwrap_or_default();
+    json!(items.iter().map(|item| {
+        let item_type = item["type"].as_str().unwrap_or("text");
+        let mut content = item[item_type].clone();
+        if item_type == "mention" && content["type"] == "user" {
+            content = json!({ "type": "user", "user": { "id": content["user"]["id"] } });
+        }
+        json!({
+            "type": item_type,
+            item_type: content,
+            "annotations": item["annotations"]
+        })
+    }).collect::<Vec<_>>())
+}
+
+fn names(options: &Value) -> Value {
+    json!(options.as_array().map(|items| {
+        items.iter().map(|o| json!({ "name": o["name"] })).collect::<Vec<_>>()
+    }).unwrap_or_default())
+}
+
+fn ids(items: &Value) -> Value {
+    json!(items.as_array().map(|items| {
+        items.iter().map(|o| json!({ "id": o["id"] })).collect::<Vec<_>>()
+    }).unwrap_or_default())
+}
+
+// Turn a property value as read from a page into one that can be written.
+// Computed properties and Notion-hosted files return None.
+fn writable_property(property: &Value) -> Option<Value> {
+    let property_type = property["type"].as_str()?;
+    let value = &property[property_type];
+    let written = match property_type {
+        "title" | "rich_text" => clean_rich_text(value),
+        "number" | "checkbox" | "url" | "email" | "phone_number" => value.clone(),
+        "select" | "status" => {
+            if value.is_null() { Value::Null } else { json!({ "name": value["name"] }) }
+        }
+        "multi_select" => names(value),
+        "date" => {
+            if value.is_null() {
+                Value::Null
+            } else {
+                json!({ "start": value["start"], "end": value["end"], "time_zone": value["time_zone"] })
+            }
+        }
+        "people" | "relation" => ids(value),
+        "files" => json!(value.as_array().map(|files| {
+            files.iter()
+                .filter(|f| f["type"] == "external")
+                .map(|f| json!({ "name": f["name"], "type": "external", "external": f["external"] }))
+                .collect::<Vec<_>>()
+        }).unwrap_or_default()),
+        _ => return None,
+    };
+    Some(json!({ property_type: written }))
+}
+
+// Whether a property holds nothing that a copy could lose
+fn is_empty(property: &Value) -> bool {
+    match property_to_plain_json(property) {
+        Value::Null | Value::Bool(false) => true,
+        Value::String(s) => s.is_empty(),
+        Value::Array(items) => items.is_empty(),
+        _ => false,
+    }
+}
+
+// Properties for the copy, and the names of those whose value it cannot
+// have, with the reason. A database target keeps the properties its schema
+// shares with the source (same name and type); any other parent only takes
+// the title. Formulas and rollups are computed again by the target.
+fn copy_properties(source: &Value, schema: Option<&Map<String, Value>>, title: Option<&str>) -> (Map<String, Value>, Vec<(String, String)>) {
+    let mut properties = Map::new();
+    let mut dropped = Vec::new();
+    let empty = Map::new();
+
+    for (name, property) in source["properties"].as_object().unwrap_or(&empty) {
+        let is_title = property["type"] == "title";
+        let target_name = match schema {
+            Some(schema) if is_title => schema.iter()
+                .find(|(_, p)| p["type"] == "title")
+                .map(|(n, _)| n.clone()),
+            Some(schema) => schema.get(name)
+                .filter(|p| p["type"] == property["type"])
+                .map(|_| name.clone()),
+            None if is_title => Some("title".to_string()),
+            None => None,
+        };
+
+        let Some(target_name) = target_name else {
+            if !is_empty(property) {
+                let reason = match schema {
+                    Some(_) => "the target database has no property with this name and type",
+                    None => "a page outside a database only keeps its title",
+                };
+                dropped.push((name.clone(), reason.to_string()));
+            }
+            continue;
+        };
+        let value = match (is_title, title) {
+            (true, Some(title)) => Some(json!({ "title": [{ "type": "text", "text": { "content": title } }] })),
+            _ => writable_property(property),
+        };
+        match value {
+            Some(value) => {
+                let hosted_files = property["type"] == "files"
+                    && property["files"].as_array().is_some_and(|files| files.iter().any(|f| f["type"] == "file"));
+                if hosted_files {
+                    dropped.push((name.clone(), "files uploaded to Notion cannot be re-attached by URL".to_string()));
+                }
+                properties.insert(target_name, value);
+            }
+            None if matches!(property["type"].as_str(), Some("formula" | "rollup")) => {}
+            None if !is_empty(property) => {
+                dropped.push((name.clone(), "set by Notion, so the copy gets a new value".to_string()));
+            }
+            None => {}
+        }
+    }
+
+    (properties, dropped)
+}
+
+// Strip the read-only fields of a fetched block so it can be created again.
+// Blocks inside tables and columns are sent in the same request as their
+// container, which cannot nest any deeper.
+fn creatable_block(block: &Value, inline: bool, skipped: &mut Vec<SkippedBlock>) -> Option<Value> {
+    let block_id = block["id"].as_str().unwrap_or("").to_string();
+    let block_type = block["type"].as_str().unwrap_or("").to_string();
+
+    let skip = |reason: &str, skipped: &mut Vec<SkippedBlock>| {
+        skipped.push(SkippedBlock {
+            block_id: block_id.clone(),
+            block_type: block_type.clone(),
+            reason: reason.to_string(),
+        });
+    };
+
+    if let Some((_, reason)) = UNCOPYABLE_BLOCKS.iter().find(|(t, _)| *t == block_type) {
+        skip(reason, skipped);
+        return None;
+    }
+    if FILE_BLOCKS.contains(&block_type.as_str()) && block[&block_type]["type"] == "file" {
+        skip("files uploaded to Notion cannot be re-attached by URL", skipped);
+        return None;
+    }
+
+    let mut content = block[&block_type].clone();
+    if block_type == "synced_block" && content["synced_from"].is_null() {
+        // An original synced block is recreated as a new, independent one
+        content = json!({ "synced_from": null });
+    }
+
+    if INLINE_CHILDREN.contains(&block_type.as_str()) {
+        let children: Vec<Value> = block["children"].as_array()
+            .map(|c| c.iter().filter_map(|child| creatable_block(child, true, skipped)).collect())
+            .unwrap_or_default();
+        content["children"] = json!(children);
+    } else if inline {
+        for child in block["children"].as_array().into_iter().flatten() {
+            skipped.push(SkippedBlock {
+                block_id: child["id"].as_str().unwrap_or("").to_string(),
+                block_type: child["type"].as_str().unwrap_or("").to_string(),
+                reason: "nested too deeply inside a table or column".to_string(),
+            });
+        }
+    }
+
+    Some(json!({
+        "object": "block",
+        "type": block_type,
+        block_type.as_str(): content
+    }))
+}
+
+struct Copier<'a> {
+    client: &'a NotionClient,
+    report: CopyReport,
+}
+
+impl<'a> Copier<'a> {
+    // Append blocks one batch at a time. A failed batch is retried block by
+    // block so that one bad block does not lose the rest.
+    async fn append_batch(&mut self, parent_id: &str, batch: Vec<(Value, Vec<Value>)>) -> Vec<(String, Vec<Value>)> {
+        let blocks: Vec<Value> = batch.iter().map(|(b, _)| b.clone()).collect();
+        let mut created_with_children = Vec::new();
+
+        match self.client.append_block_children(parent_id, blocks).await {
+            Ok(created) => {
+                self.report.copied_blocks += created.len();
+                for (block, (_, children)) in created.iter().zip(batch) {
+                    if let Some(id) = block["id"].as_str() {
+                        created_with_children.push((id.to_string(), children));
+                    }
+                }
+            }
+            Err(batch_error) => {
+                warn!("Batch append to {} failed, retrying block by block: {}", parent_id, batch_error);
+                for (block, children) in batch {
+                    match self.client.append_block_children(parent_id, vec![block.clone()]).await {
+                        Ok(created) => {
+                            self.report.copied_blocks += created.len();
+                            if let Some(id) = created.first().and_then(|b| b["id"].as_str()) {
+                                created_with_children.push((id.to_string(), children));
+                            }
+                        }
+                        Err(e) => self.report.skipped.push(SkippedBlock {
+                            block_id: String::new(),
+                            block_type: block["type"].as_str().unwrap_or("").to_string(),
+                            reason: e.to_string(),
+                        }),
+                    }
+                }
+            }
+        }
+
+        created_with_children
+    }
+
+    // Copy fetched blocks under `parent_id`. Child pages are copied as new
+    // pages of `page_id`, flushing pending blocks first to keep their order.
+    fn copy_blocks<'b>(&'b mut self, parent_id: &'b str, page_id: &'b str, blocks: Vec<Value>) -> CopyFuture<'b, ()> {
+        Box::pin(async move {
+            let mut pending: Vec<(Value, Vec<Value>)> = Vec::new();
+            let mut created = Vec::new();
+
+            for block in blocks {
+                if block["type"] == "child_page" {
+                    // A copy made earlier in this run is not copied again
+                    let block_id = block["id"].as_str().unwrap_or("");
+                    if self.report.pages.iter().any(|p| p.page_id == block_id) {
+                        continue;
+                    }
+                    for batch in std::mem::take(&mut pending).chunks(MAX_BLOCKS_PER_REQUEST) {
+                        created.extend(self.append_batch(parent_id, batch.to_vec()).await);
+                    }
+                    let source_id = block["id"].as_str().unwrap_or("").to_string();
+                    if parent_id != page_id {
+                        warn!("Child page {} inside a nested block is copied to the end of the page", source_id);
+                    }
+                    if let Err(e) = self.copy_page(source_id.clone(), PageParent::Page(page_id.to_string()), None).await {
+                        self.report.skipped.push(SkippedBlock {
+                            block_id: source_id,
+                            block_type: "child_page".to_string(),
+                            reason: e.to_string(),
+                        });
+                    }
+                    continue;
+                }
+
+                let Some(creatable) = creatable_block(&block, false, &mut self.report.skipped) else { continue };
+                let block_type = block["type"].as_str().unwrap_or("");
+                let children = if INLINE_CHILDREN.contains(&block_type) {
+                    Vec::new()
+                } else {
+                    block["children"].as_array().cloned().unwrap_or_default()
+                };
+                pending.push((creatable, children));
+            }
+
+            for batch in pending.chunks(MAX_BLOCKS_PER_REQUEST) {
+                created.extend(self.append_batch(parent_id, batch.to_vec()).await);
+            }
+
+            for (block_id, children) in created {
+                if !children.is_empty() {
+                    self.copy_blocks(&block_id, page_id, children).await?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn copy_page(&mut self, source_id: String, parent: PageParent, title: Option<String>) -> CopyFuture<'_, String> {
+        Box::pin(async move {
+            let source = self.client.get_page_object(&source_id).await?;
+            let blocks = self.client.get_block_tree(&source_id).await?;
+
+            let schema = match &parent {
+                PageParent::Database(id) => self.client.get_database(id).await?["properties"].as_object().cloned(),
+                // Data sources share the schema of the source row
+                PageParent::DataSource(_) => source["properties"].as_object().cloned(),
+                _ => None,
+            };
+            let (properties, dropped) = copy_properties(&source, schema.as_ref(), title.as_deref());
+            for (property, reason) in dropped {
+                self.report.skipped_properties.push(SkippedProperty { page_id: source_id.clone(), property, reason });
+            }
+
+            let created = self.client.create_page(&parent, Value::Object(properties), None).await?;
+            let page_id = created["id"].as_str().unwrap_or("").to_string();
+
+            self.report.pages.push(CopiedPage { source_id: source_id.clone(), page_id: page_id.clone() });
+            if self.report.page_id.is_empty() {
+                self.report.page_id = page_id.clone();
+                self.report.url = created["url"].as_str().unwrap_or("").to_string();
+            }
+
+            self.copy_blocks(&page_id, &page_id, blocks).await?;
+            Ok(page_id)
+        })
+    }
+}
+
+// Refuse a parent inside the page being copied: the copy would end up
+// among the pages it is copying
+async fn check_outside(client: &NotionClient, page_id: &str, parent: &PageParent) -> NotionResult<()> {
+    let source = normalize_id(page_id)?;
+    let (mut kind, mut id) = match parent {
+        PageParent::Page(id) => ("page", id.clone()),
+        PageParent::Database(id) => ("database", id.clone()),
+        PageParent::DataSource(id) => ("data_source", id.clone()),
+        PageParent::Workspace => return Ok(()),
+    };
+
+    for _ in 0..MAX_ANCESTORS {
+        let normalized = normalize_id(&id)?;
+        if normalized == source {
+            return Err(NotionMcpError::InvalidParams(
+                "A page cannot be copied or moved inside itself or one of its descendants".to_string()
+            ));
+        }
+        // Objects the caller cannot see lie outside the page, which it can see
+        let parent = match client.get_parent(kind, &normalized).await {
+            Ok(parent) => parent,
+            Err(e) if e.is_not_found() || matches!(e, NotionMcpError::Authentication(_)) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        kind = match parent["type"].as_str() {
+            Some("page_id") => "page",
+            Some("database_id") => "database",
+            Some("data_source_id") => "data_source",
+            Some("block_id") => "block",
+            _ => return Ok(()),
+        };
+        id = parent[format!("{}_id", kind)].as_str().unwrap_or("").to_string();
+    }
+    Ok(())
+}
+
+// Copy a page, its properties and its full block tree, including child
+// pages. Without a parent the copy goes next to the original. If the copy
+// fails after creating pages, the report lists them together with the error.
+pub async fn duplicate_page(
+    client: &NotionClient,
+    page_id: &str,
+    parent: Option<PageParent>,
+    title: Option<String>,
+) -> NotionResult<CopyReport> {
+    let source = client.get_page_object(page_id).await?;
+    let parent = match parent.or_else(|| page_parent(&source)) {
+        Some(parent) => parent,
+        None => return Err(NotionMcpError::InvalidParams(
+            "The page's parent cannot hold new pages; pass an explicit 'parent'".to_string()
+        )),
+    };
+
+    check_outside(client, page_id, &parent).await?;
+
+    let mut copier = Copier {
+        client,
+        report: CopyReport {
+            source_id: source["id"].as_str().unwrap_or(page_id).to_string(),
+            ..CopyReport::default()
+        },
+    };
+    if let Err(e) = copier.copy_page(copier.report.source_id.clone(), parent, title).await {
+        if copier.report.pages.is_empty() {
+            return Err(e);
+        }
+        warn!("Copy of {} stopped after {} pages: {}", copier.report.source_id, copier.report.pages.len(), e);
+        copier.report.error = Some(e.to_string());
+    }
+
+    info!(
+        "Duplicated {} as {}: {} blocks copied, {} skipped",
+        copier.report.source_id, copier.report.page_id, copier.report.copied_blocks, copier.report.skipped.len()
+    );
+    Ok(copier.report)
+}
+
+// Move a page by copying it under the new parent and archiving the original.
+// The original is kept if any block or property could not be copied, or the
+// copy is incomplete, since archiving it would lose that content.
+pub async fn move_page(client: &NotionClient, page_id: &str, parent: PageParent) -> NotionResult<MoveReport> {
+    let copy = duplicate_page(client, page_id, Some(parent), None).await?;
+
+    let original_archived = if copy.skipped.is_empty() && copy.skipped_properties.is_empty() && copy.error.is_none() {
+        client.set_archived(page_id, true).await?;
+        true
+    } else {
+        warn!(
+            "Not archiving {}: {} blocks and {} properties could not be copied{}",
+            page_id, copy.skipped.len(), copy.skipped_properties.len(),
+            if copy.error.is_some() { " and the copy is incomplete" } else { "" }
+        );
+        false
+    };
+
+    Ok(MoveReport { copy, original_archived })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source() -> Value {
+        json!({
+            "properties": {
+                "Name": { "type": "title", "title": [{ "type": "text", "text": { "content": "Row" }, "plain_text": "Row" }] },
+                "Estimate": { "type": "number", "number": 3 },
+                "Notes": { "type": "rich_text", "rich_text": [] },
+                "Total": { "type": "formula", "formula": { "type": "number", "number": 6 } },
+                "Created": { "type": "created_time", "created_time": "2024-05-01T10:20:00.000Z" },
+                "Files": { "type": "files", "files": [
+                    { "name": "a.pdf", "type": "file", "file": { "url": "https://files.example.com/a.pdf" } },
+                    { "name": "b", "type": "external", "external": { "url": "https://example.com/b" } }
+                ] }
+            }
+        })
+    }
+
+    fn dropped_names(dropped: &[(String, String)]) -> Vec<&str> {
+        let mut names: Vec<&str> = dropped.iter().map(|(name, _)| name.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn reports_properties_a_database_copy_loses() {
+        let schema: Map<String, Value> = serde_json::from_value(json!({
+            "Title": { "type": "title" },
+            "Total": { "type": "formula" },
+            "Created": { "type": "created_time" },
+            "Files": { "type": "files" }
+        })).unwrap();
+        let (properties, dropped) = copy_properties(&source(), Some(&schema), None);
+
+        assert!(properties.contains_key("Title"));
+        assert_eq!(properties["Files"]["files"].as_array().unwrap().len(), 1);
+        // Empty Notes and the recomputed formula lose nothing
+        assert_eq!(dropped_names(&dropped), vec!["Created", "Estimate", "Files"]);
+    }
+
+    #[test]
+    fn reports_every_property_but_the_title_under_a_page() {
+        let (properties, dropped) = copy_properties(&source(), None, Some("Copy"));
+        assert_eq!(properties.keys().collect::<Vec<_>>(), vec!["title"]);
+        assert_eq!(dropped_names(&dropped), vec!["Created", "Estimate", "Files", "Total"]);
+    }
+}
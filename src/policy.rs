@@ -0,0 +1,454 @@
+use crate::auth::{Principal, API_KEY_PREFIX, OAUTH_PREFIX};
+use crate::error::{NotionMcpError, NotionResult};
+use crate::ids::normalize_id;
+use crate::notion::NotionClient;
+use crate::search_index::SearchIndex;
+use log::debug;
+use reqwest::Method;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Where a page sits rarely changes; re-check after this long anyway
+const DECISION_TTL: Duration = Duration::from_secs(5 * 60);
+// Entry applying to keys without a policy of their own
+const DEFAULT_POLICY: &str = "*";
+
+// One entry of the policy file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyRules {
+    // Databases whose rows (and the databases themselves) may be used
+    #[serde(default)]
+    databases: Vec<String>,
+    // Pages that may be used together with everything beneath them
+    #[serde(default)]
+    pages: Vec<String>,
+    // When set, only these properties are visible and writable
+    properties: Option<Vec<String>>,
+    // Properties never shown nor written
+    #[serde(default)]
+    hidden_properties: Vec<String>,
+}
+
+// Restrictions for one API key, enforced on every request to Notion
+#[derive(Debug)]
+pub struct Policy {
+    pub name: String,
+    databases: HashSet<String>,
+    pages: HashSet<String>,
+    properties: Option<HashSet<String>>,
+    hidden_properties: HashSet<String>,
+    // Object ID -> whether it lies inside the allowed scope
+    decisions: Mutex<HashMap<String, (bool, Instant)>>,
+    // Private full-text indexes, so search never returns denied pages
+    indexes: Mutex<HashMap<String, Arc<SearchIndex>>>,
+}
+
+// Lookups go back through the client that called the policy, so their
+// futures are boxed to break the cycle
+type Pending<'a, T> = Pin<Box<dyn Future<Output = NotionResult<T>> + Send + 'a>>;
+
+impl Policy {
+    fn from_rules(name: &str, rules: PolicyRules) -> NotionResult<Self> {
+        let ids = |list: Vec<String>| list.iter().map(|id| normalize_id(id)).collect::<NotionResult<HashSet<String>>>();
+        Ok(Self {
+            name: name.to_string(),
+            databases: ids(rules.databases)?,
+            pages: ids(rules.pages)?,
+            properties: rules.properties.map(|p| p.into_iter().collect()),
+            hidden_properties: rules.hidden_properties.into_iter().collect(),
+            decisions: Mutex::new(HashMap::new()),
+            indexes: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // Without databases or pages the policy only filters properties
+    fn restricts_location(&self) -> bool {
+        !self.databases.is_empty() || !self.pages.is_empty()
+    }
+
+    pub fn property_allowed(&self, name: &str) -> bool {
+        !self.hidden_properties.contains(name) && self.properties.as_ref().is_none_or(|allowed| allowed.contains(name))
+    }
+
+    pub fn search_index(&self, partition: &str) -> Arc<SearchIndex> {
+        self.indexes.lock().unwrap().entry(partition.to_string()).or_default().clone()
+    }
+
+    fn denied(&self, what: String) -> NotionMcpError {
+        NotionMcpError::Authentication(format!("Forbidden by the policy of '{}': {}", self.name, what))
+    }
+
+    fn remember(&self, id: &str, allowed: bool) {
+        self.decisions.lock().unwrap().insert(id.to_string(), (allowed, Instant::now()));
+    }
+
+    fn remembered(&self, id: &str) -> Option<bool> {
+        self.decisions.lock().unwrap().get(id)
+            .filter(|(_, at)| at.elapsed() < DECISION_TTL)
+            .map(|(allowed, _)| *allowed)
+    }
+
+    // Whether a page, block, database or data source lies inside the allowed
+    // databases and page subtrees, walking up its parents until one is known
+    fn allows<'a>(&'a self, raw: &'a NotionClient, kind: &'a str, id: &'a str) -> Pending<'a, bool> {
+        Box::pin(async move {
+            if !self.restricts_location() {
+                return Ok(true);
+            }
+            let id = normalize_id(id)?;
+            if self.pages.contains(&id) || self.databases.contains(&id) {
+                return Ok(true);
+            }
+            if let Some(allowed) = self.remembered(&id) {
+                return Ok(allowed);
+            }
+
+            let parent = raw.get_parent(kind, &id).await?;
+            let allowed = match parent["type"].as_str().unwrap_or("") {
+                "database_id" => self.allows(raw, "database", parent["database_id"].as_str().unwrap_or("")).await?,
+                "data_source_id" => match parent["database_id"].as_str() {
+                    Some(database_id) => self.allows(raw, "database", database_id).await?,
+                    None => self.allows(raw, "data_source", parent["data_source_id"].as_str().unwrap_or("")).await?,
+                },
+                "page_id" => self.allows(raw, "page", parent["page_id"].as_str().unwrap_or("")).await?,
+                "block_id" => self.allows(raw, "block", parent["block_id"].as_str().unwrap_or("")).await?,
+                _ => false,
+            };
+            debug!("Policy '{}': {} {} is {}", self.name, kind, id, if allowed { "allowed" } else { "denied" });
+            self.remember(&id, allowed);
+            Ok(allowed)
+        })
+    }
+
+    // Name of a page property, given the ID used in property item requests
+    fn property_name<'a>(&'a self, raw: &'a NotionClient, page_id: &'a str, property_id: &'a str) -> Pending<'a, String> {
+        Box::pin(async move {
+            let page = raw.get_page_object(page_id).await?;
+            Ok(page["properties"].as_object()
+                .and_then(|props| props.iter().find(|(_, p)| p["id"].as_str() == Some(property_id)))
+                .map(|(name, _)| name.clone())
+                .unwrap_or_default())
+        })
+    }
+
+    async fn require(&self, raw: &NotionClient, kind: &str, id: &str) -> NotionResult<()> {
+        if self.allows(raw, kind, id).await? {
+            Ok(())
+        } else {
+            Err(self.denied(format!("{} {} is outside the allowed pages and databases", kind, id)))
+        }
+    }
+
+    fn require_properties(&self, body: Option<&Value>) -> NotionResult<()> {
+        let Some(properties) = body.and_then(|b| b["properties"].as_object()) else {
+            return Ok(());
+        };
+        match properties.keys().find(|name| !self.property_allowed(name)) {
+            Some(name) => Err(self.denied(format!("property '{}' cannot be written", name))),
+            None => Ok(()),
+        }
+    }
+
+    async fn require_parent(&self, raw: &NotionClient, parent: &Value) -> NotionResult<()> {
+        for kind in ["database", "data_source", "page", "block"] {
+            if let Some(id) = parent[format!("{}_id", kind)].as_str() {
+                return self.require(raw, kind, id).await;
+            }
+        }
+        if self.restricts_location() {
+            return Err(self.denied("pages can only be created inside the allowed pages and databases".to_string()));
+        }
+        Ok(())
+    }
+
+    // Check a request before it is sent. `route` holds the path segments
+    // after the API base URL, e.g. ["pages", "<id>"].
+    pub async fn authorize(&self, raw: &NotionClient, method: &Method, route: &[&str], query: Option<&str>, body: Option<&Value>) -> NotionResult<()> {
+        match route {
+            ["search"] | ["users", ..] | ["file_uploads", ..] => Ok(()),
+            ["pages"] => {
+                self.require_parent(raw, &body.map(|b| b["parent"].clone()).unwrap_or_default()).await?;
+                self.require_properties(body)
+            }
+            ["pages", id] => {
+                self.require(raw, "page", id).await?;
+                self.require_properties(body)
+            }
+            ["pages", id, "properties", property_id] => {
+                self.require(raw, "page", id).await?;
+                let name = self.property_name(raw, id, property_id).await?;
+                if self.property_allowed(&name) {
+                    Ok(())
+                } else {
+                    Err(self.denied(format!("property '{}' cannot be read", name)))
+                }
+            }
+            ["databases", id, ..] => self.require(raw, "database", id).await,
+            ["data_sources", id, ..] => self.require(raw, "data_source", id).await,
+            ["blocks", id, ..] => self.require(raw, "block", id).await,
+            ["comments"] if method == Method::GET => {
+                let block_id = query.unwrap_or("").split('&')
+                    .find_map(|pair| pair.strip_prefix("block_id="))
+                    .unwrap_or("");
+                self.require(raw, "block", block_id).await
+            }
+            ["comments"] => {
+                let parent = body.map(|b| b["parent"].clone()).unwrap_or_default();
+                match parent["page_id"].as_str() {
+                    Some(page_id) => self.require(raw, "page", page_id).await,
+                    // A discussion cannot be traced back to its page
+                    None if self.restricts_location() => Err(self.denied("replies to discussions are not allowed".to_string())),
+                    None => Ok(()),
+                }
+            }
+            _ if self.restricts_location() => Err(self.denied(format!("{} /{} is not allowed", method, route.join("/")))),
+            _ => Ok(()),
+        }
+    }
+
+    fn strip_properties(&self, object: &mut Value) {
+        if let Some(properties) = object.get_mut("properties").and_then(|p| p.as_object_mut()) {
+            properties.retain(|name, _| self.property_allowed(name));
+        }
+    }
+
+    // Remove what the policy hides from a response: denied properties, and
+    // search results outside the allowed scope. Children and query results
+    // of an allowed parent are remembered as allowed.
+    pub async fn filter_response(&self, raw: &NotionClient, route: &[&str], response: &mut Value) -> NotionResult<()> {
+        if response["object"] == "list" {
+            let results = response["results"].as_array_mut().map(std::mem::take).unwrap_or_default();
+            let mut kept = Vec::with_capacity(results.len());
+            for mut item in results {
+                let id = item["id"].as_str().unwrap_or("").to_string();
+                match route {
+                    ["search"] => {
+                        let kind = if item["object"] == "database" { "database" } else { "page" };
+                        if !self.allows(raw, kind, &id).await.unwrap_or(false) {
+                            continue;
+                        }
+                    }
+                    ["blocks", _, "children"] | ["databases", _, "query"] | ["data_sources", _, "query"] if !id.is_empty() => {
+                        self.remember(&id, true);
+                    }
+                    _ => {}
+                }
+                self.strip_properties(&mut item);
+                kept.push(item);
+            }
+            response["results"] = Value::Array(kept);
+        } else {
+            self.strip_properties(response);
+        }
+        Ok(())
+    }
+}
+
+// Policies by principal name, loaded from MCP_POLICY_FILE
+#[derive(Debug, Default)]
+pub struct PolicySet {
+    policies: HashMap<String, Arc<Policy>>,
+}
+
+impl PolicySet {
+    // YAML or JSON mapping principal names (or `*` for every other caller)
+    // to rules. A bare name is an API key, the same as `key:<name>`.
+    pub fn load(path: &Path) -> NotionResult<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let rules: HashMap<String, PolicyRules> = serde_yaml::from_str(&content)
+            .map_err(|e| NotionMcpError::InvalidParams(format!("Invalid policy file {}: {}", path.display(), e)))?;
+        let mut policies = HashMap::new();
+        for (name, rules) in rules {
+            let name = if name == DEFAULT_POLICY || name.starts_with(API_KEY_PREFIX) || name.starts_with(OAUTH_PREFIX) {
+                name
+            } else {
+                format!("{}{}", API_KEY_PREFIX, name)
+            };
+            let policy = Policy::from_rules(&name, rules)?;
+            policies.insert(name, Arc::new(policy));
+        }
+        Ok(Self { policies })
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.policies.keys().map(|k| k.as_str()).collect()
+    }
+
+    pub fn for_principal(&self, principal: Option<&Principal>) -> Option<Arc<Policy>> {
+        principal.and_then(|p| self.policies.get(&p.name))
+            .or_else(|| self.policies.get(DEFAULT_POLICY))
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Scope;
+    use crate::fake_notion::FakeNotion;
+    use serde_json::json;
+
+    const PROJECTS_DB: &str = "1a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d";
+    const PROJECT: &str = "2a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d";
+    const TOGGLE: &str = "3a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d";
+    const PRIVATE: &str = "4a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d";
+    const NOTE: &str = "5a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d";
+
+    // PROJECT is a row of PROJECTS_DB and TOGGLE a block inside it;
+    // PRIVATE sits at the top of the workspace and NOTE is a block in it
+    fn workspace() -> FakeNotion {
+        FakeNotion::start(|method, path, _| {
+            let parent = match (method, path.split_once('/')?) {
+                ("GET", ("pages", id)) if id == PROJECT => json!({ "type": "database_id", "database_id": PROJECTS_DB }),
+                ("GET", ("pages", id)) if id == PRIVATE => json!({ "type": "workspace", "workspace": true }),
+                ("GET", ("blocks", id)) if id == TOGGLE => json!({ "type": "page_id", "page_id": PROJECT }),
+                ("GET", ("blocks", id)) if id == NOTE => json!({ "type": "page_id", "page_id": PRIVATE }),
+                _ => return None,
+            };
+            Some(json!({ "object": "page", "parent": parent, "properties": {
+                "Name": { "id": "title", "type": "title" },
+                "Salary": { "id": "s%3Bl", "type": "number" }
+            } }))
+        })
+    }
+
+    fn contractor(rules: PolicyRules) -> Policy {
+        Policy::from_rules("key:contractor", rules).unwrap()
+    }
+
+    fn projects_only() -> Policy {
+        contractor(PolicyRules {
+            databases: vec![PROJECTS_DB.to_string()],
+            hidden_properties: vec!["Salary".to_string()],
+            ..Default::default()
+        })
+    }
+
+    fn is_denied(result: NotionResult<()>) -> bool {
+        matches!(result, Err(NotionMcpError::Authentication(message)) if message.contains("key:contractor"))
+    }
+
+    #[actix_web::test]
+    async fn walks_up_parents_to_an_allowed_database() {
+        let notion = workspace();
+        let raw = notion.client();
+        let policy = projects_only();
+
+        assert!(policy.authorize(&raw, &Method::GET, &["blocks", TOGGLE, "children"], None, None).await.is_ok());
+        assert!(is_denied(policy.authorize(&raw, &Method::GET, &["blocks", NOTE, "children"], None, None).await));
+        let lookups = notion.requests().len();
+        assert_eq!(lookups, 4);
+
+        // Decisions are remembered for the whole chain
+        assert!(policy.authorize(&raw, &Method::GET, &["pages", PROJECT], None, None).await.is_ok());
+        assert!(is_denied(policy.authorize(&raw, &Method::GET, &["pages", PRIVATE], None, None).await));
+        assert_eq!(notion.requests().len(), lookups);
+        notion.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn restricted_locations_deny_routes_outside_them() {
+        let notion = workspace();
+        let raw = notion.client();
+        let policy = projects_only();
+
+        assert!(policy.authorize(&raw, &Method::POST, &["search"], None, None).await.is_ok());
+        let at_top = json!({ "parent": { "type": "workspace", "workspace": true } });
+        assert!(is_denied(policy.authorize(&raw, &Method::POST, &["pages"], None, Some(&at_top)).await));
+        let reply = json!({ "discussion_id": "d1", "rich_text": [] });
+        assert!(is_denied(policy.authorize(&raw, &Method::POST, &["comments"], None, Some(&reply)).await));
+        let query = format!("block_id={}", NOTE);
+        assert!(is_denied(policy.authorize(&raw, &Method::GET, &["comments"], Some(&query), None).await));
+        assert!(is_denied(policy.authorize(&raw, &Method::GET, &["views", "v1"], None, None).await));
+
+        // Filtering properties alone does not confine where pages live
+        let anywhere = contractor(PolicyRules { hidden_properties: vec!["Salary".to_string()], ..Default::default() });
+        assert!(anywhere.authorize(&raw, &Method::POST, &["pages"], None, Some(&at_top)).await.is_ok());
+        assert!(anywhere.authorize(&raw, &Method::GET, &["views", "v1"], None, None).await.is_ok());
+        notion.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn hides_properties_and_pages_from_search_and_query_results() {
+        let notion = workspace();
+        let raw = notion.client();
+        let policy = projects_only();
+        let row = |id: &str| json!({ "object": "page", "id": id, "properties": { "Name": {}, "Salary": { "number": 90000 } } });
+
+        let mut found = json!({ "object": "list", "results": [row(PROJECT), row(PRIVATE)] });
+        policy.filter_response(&raw, &["search"], &mut found).await.unwrap();
+        assert_eq!(found["results"], json!([{ "object": "page", "id": PROJECT, "properties": { "Name": {} } }]));
+
+        // Rows returned by an allowed query need no lookup afterwards
+        let other_row = "6a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d";
+        let mut rows = json!({ "object": "list", "results": [row(other_row)] });
+        policy.filter_response(&raw, &["databases", PROJECTS_DB, "query"], &mut rows).await.unwrap();
+        assert_eq!(rows["results"][0]["properties"], json!({ "Name": {} }));
+        let lookups = notion.requests().len();
+        assert!(policy.authorize(&raw, &Method::GET, &["pages", other_row], None, None).await.is_ok());
+        assert_eq!(notion.requests().len(), lookups);
+
+        let mut page = row(PROJECT);
+        policy.filter_response(&raw, &["pages", PROJECT], &mut page).await.unwrap();
+        assert_eq!(page["properties"], json!({ "Name": {} }));
+        notion.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn rejects_writing_or_reading_hidden_properties() {
+        let notion = workspace();
+        let raw = notion.client();
+        let policy = projects_only();
+
+        let raise = json!({ "properties": { "Name": {}, "Salary": { "number": 1 } } });
+        assert!(is_denied(policy.authorize(&raw, &Method::PATCH, &["pages", PROJECT], None, Some(&raise)).await));
+        let create = json!({ "parent": { "database_id": PROJECTS_DB }, "properties": { "Salary": { "number": 1 } } });
+        assert!(is_denied(policy.authorize(&raw, &Method::POST, &["pages"], None, Some(&create)).await));
+        let rename = json!({ "properties": { "Name": {} } });
+        assert!(policy.authorize(&raw, &Method::PATCH, &["pages", PROJECT], None, Some(&rename)).await.is_ok());
+
+        assert!(is_denied(policy.authorize(&raw, &Method::GET, &["pages", PROJECT, "properties", "s%3Bl"], None, None).await));
+        assert!(policy.authorize(&raw, &Method::GET, &["pages", PROJECT, "properties", "title"], None, None).await.is_ok());
+
+        // An allow list hides everything it does not name
+        let named = contractor(PolicyRules { properties: Some(vec!["Name".to_string()]), ..Default::default() });
+        assert!(is_denied(named.authorize(&raw, &Method::PATCH, &["pages", PROJECT], None, Some(&raise)).await));
+        notion.stop().await;
+    }
+
+    #[test]
+    fn resolves_principal_names_when_loading() {
+        let path = std::env::temp_dir().join(format!("notion-mcp-policy-{}.yaml", std::process::id()));
+        std::fs::write(&path, format!(
+            "'*':\n  hidden_properties: [Salary]\ncontractor:\n  databases: ['{}']\n'key:intern':\n  properties: [Name]\n'oauth:https://idp.example.com:alice':\n  pages: []\n",
+            PROJECTS_DB
+        )).unwrap();
+        let policies = PolicySet::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut names = policies.names();
+        names.sort();
+        assert_eq!(names, vec!["*", "key:contractor", "key:intern", "oauth:https://idp.example.com:alice"]);
+        let principal = |name: &str| Principal { name: name.to_string(), scope: Scope::Read };
+        assert_eq!(policies.for_principal(Some(&principal("key:contractor"))).unwrap().name, "key:contractor");
+        assert_eq!(policies.for_principal(Some(&principal("key:other"))).unwrap().name, "*");
+        assert_eq!(policies.for_principal(None).unwrap().name, "*");
+    }
+
+    #[test]
+    fn rejects_unknown_rules_and_bad_ids() {
+        let path = std::env::temp_dir().join(format!("notion-mcp-policy-invalid-{}.yaml", std::process::id()));
+        for content in ["contractor:\n  tables: []\n", "contractor:\n  pages: [not-an-id]\n"] {
+            std::fs::write(&path, content).unwrap();
+            assert!(PolicySet::load(&path).is_err(), "{}", content);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
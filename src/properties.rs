@@ -0,0 +1,255 @@
+use serde_json::{json, Map, Value};
+
+// Concatenate the plain text of a rich text array
+pub fn rich_text_to_plain(rich_text: &Value) -> String {
+    rich_text.as_array()
+        .map(|parts| parts.iter()
+            .filter_map(|rt| rt["plain_text"].as_str().or_else(|| rt["text"]["content"].as_str()))
+            .collect::<Vec<_>>()
+            .join(""))
+        .unwrap_or_default()
+}
+
+// Title of a page or database, whatever its title property is called
+pub fn page_title(page: &Value) -> String {
+    if let Some(title) = page.get("title").filter(|t| t.is_array()) {
+        return rich_text_to_plain(title);
+    }
+
+    page["properties"].as_object()
+        .and_then(|props| props.values().find(|p| p["type"] == "title"))
+        .map(|p| rich_text_to_plain(&p["title"]))
+        .unwrap_or_default()
+}
+
+// Human-readable values of a property. Multi-valued properties (multi_select,
+// people, relation, files) yield one entry per value.
+pub fn property_values(property: &Value) -> Vec<String> {
+    let property_type = property["type"].as_str().unwrap_or("");
+    let value = &property[property_type];
+
+    let values = match property_type {
+        "title" | "rich_text" => vec![rich_text_to_plain(value)],
+        "number" => value.as_f64().map(format_number).into_iter().collect(),
+        "select" | "status" => value["name"].as_str().map(|s| s.to_string()).into_iter().collect(),
+        "multi_select" => value.as_array()
+            .map(|options| options.iter()
+                .filter_map(|o| o["name"].as_str().map(|s| s.to_string()))
+                .collect())
+            .unwrap_or_default(),
+        "date" => date_to_text(value).into_iter().collect(),
+        "checkbox" => vec![value.as_bool().unwrap_or(false).to_string()],
+        "url" | "email" | "phone_number" | "created_time" | "last_edited_time" => {
+            value.as_str().map(|s| s.to_string()).into_iter().collect()
+        }
+        "people" => value.as_array()
+            .map(|people| people.iter().map(user_to_text).collect())
+            .unwrap_or_default(),
+        "created_by" | "last_edited_by" => vec![user_to_text(value)],
+        "relation" => value.as_array()
+            .map(|pages| pages.iter()
+                .filter_map(|p| p["id"].as_str().map(|s| s.to_string()))
+                .collect())
+            .unwrap_or_default(),
+        "files" => value.as_array()
+            .map(|files| files.iter()
+                .filter_map(|f| f["name"].as_str().map(|s| s.to_string()))
+                .collect())
+            .unwrap_or_default(),
+        "formula" => {
+            let formula_type = value["type"].as_str().unwrap_or("");
+            match formula_type {
+                "date" => date_to_text(&value["date"]).into_iter().collect(),
+                _ => scalar_to_text(&value[formula_type]).into_iter().collect(),
+            }
+        }
+        "rollup" => {
+            let rollup_type = value["type"].as_str().unwrap_or("");
+            match rollup_type {
+                "array" => value["array"].as_array()
+                    .map(|items| items.iter().flat_map(property_values).collect())
+                    .unwrap_or_default(),
+                "date" => date_to_text(&value["date"]).into_iter().collect(),
+                _ => scalar_to_text(&value[rollup_type]).into_iter().collect(),
+            }
+        }
+        "unique_id" => value["number"].as_i64()
+            .map(|n| match value["prefix"].as_str() {
+                Some(prefix) => format!("{}-{}", prefix, n),
+                None => n.to_string(),
+            })
+            .into_iter()
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    values.into_iter().filter(|v| !v.is_empty()).collect()
+}
+
+// Single-string rendering of a property, joining multiple values with ", "
+pub fn property_to_text(property: &Value) -> String {
+    property_values(property).join(", ")
+}
+
+// Plain JSON rendering of a property: lists for multi-valued types, native
+// booleans and numbers, strings for everything else
+pub fn property_to_plain_json(property: &Value) -> Value {
+    let property_type = property["type"].as_str().unwrap_or("");
+    match property_type {
+        "multi_select" | "people" | "relation" | "files" => json!(property_values(property)),
+        "checkbox" => property["checkbox"].clone(),
+        "number" => property["number"].clone(),
+        _ => json!(property_to_text(property)),
+    }
+}
+
+fn user_to_text(user: &Value) -> String {
+    user["name"].as_str()
+        .or_else(|| user["person"]["email"].as_str())
+        .or_else(|| user["id"].as_str())
+        .unwrap_or("")
+        .to_string()
+}
+
+fn date_to_text(date: &Value) -> Option<String> {
+    let start = date["start"].as_str()?;
+    match date["end"].as_str() {
+        Some(end) => Some(format!("{} → {}", start, end)),
+        None => Some(start.to_string()),
+    }
+}
+
+fn scalar_to_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => n.as_f64().map(format_number),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn format_number(n: f64) -> String {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        format!("{}", n as i64)
+    } else {
+        n.to_string()
+    }
+}
+
+// Strings held by a plain value: array elements, or a comma-separated string
+fn value_to_strings(value: &Value) -> Vec<String> {
+    match value {
+        Value::Array(items) => items.iter().flat_map(value_to_strings).collect(),
+        Value::String(s) => s.split(',')
+            .map(|part| part.trim().to_string())
+            .filter(|part| !part.is_empty())
+            .collect(),
+        Value::Null => Vec::new(),
+        other => vec![other.to_string()],
+    }
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        Value::Array(items) => items.iter().map(value_to_string).collect::<Vec<_>>().join(", "),
+        other => other.to_string(),
+    }
+}
+
+// Build the API payload of a property from a plain value (string, number,
+// boolean or list), following the property's type in the database schema.
+// Computed types such as formulas or rollups cannot be written and yield None.
+pub fn build_property_value(schema: &Value, value: &Value) -> Option<Value> {
+    let property_type = schema["type"].as_str()?;
+    let text = value_to_string(value);
+
+    let built = match property_type {
+        "title" | "rich_text" => json!({ property_type: crate::markdown::text_to_rich_text(&text) }),
+        "number" => {
+            let number = match value {
+                Value::Number(n) => Some(n.clone()),
+                _ => text.trim().parse::<f64>().ok().and_then(serde_json::Number::from_f64),
+            };
+            json!({ "number": number })
+        }
+        "select" | "status" => {
+            if text.trim().is_empty() {
+                json!({ property_type: null })
+            } else {
+                json!({ property_type: { "name": text.trim() } })
+            }
+        }
+        "multi_select" => json!({
+            "multi_select": value_to_strings(value).iter().map(|name| json!({ "name": name })).collect::<Vec<_>>()
+        }),
+        "date" => {
+            if text.trim().is_empty() {
+                json!({ "date": null })
+            } else {
+                match text.split_once('→') {
+                    Some((start, end)) => json!({ "date": { "start": start.trim(), "end": end.trim() } }),
+                    None => json!({ "date": { "start": text.trim() } }),
+                }
+            }
+        }
+        "checkbox" => {
+            let checked = match value {
+                Value::Bool(b) => *b,
+                _ => matches!(text.trim().to_lowercase().as_str(), "true" | "yes" | "1" | "x" | "sí" | "si"),
+            };
+            json!({ "checkbox": checked })
+        }
+        "url" | "email" | "phone_number" => {
+            let text = text.trim();
+            json!({ property_type: if text.is_empty() { Value::Null } else { json!(text) } })
+        }
+        "relation" => json!({
+            "relation": value_to_strings(value).iter().map(|id| json!({ "id": id })).collect::<Vec<_>>()
+        }),
+        // People given by name or email are resolved to IDs by the client
+        "people" => json!({
+            "people": value_to_strings(value).iter().map(|person| {
+                if crate::ids::normalize_id(person).is_ok() {
+                    json!({ "id": person })
+                } else if person.contains('@') {
+                    json!({ "email": person })
+                } else {
+                    json!({ "name": person })
+                }
+            }).collect::<Vec<_>>()
+        }),
+        "files" => json!({
+            "files": value_to_strings(value).iter()
+                .filter(|url| url.starts_with("http://") || url.starts_with("https://"))
+                .map(|url| json!({
+                    "name": url.rsplit('/').next().unwrap_or(url),
+                    "type": "external",
+                    "external": { "url": url }
+                }))
+                .collect::<Vec<_>>()
+        }),
+        _ => return None,
+    };
+
+    Some(built)
+}
+
+// Build a properties payload from plain values keyed by property name.
+// Returns the payload and the names that are not writable properties of the schema.
+pub fn build_properties(schema: &Map<String, Value>, values: &Map<String, Value>) -> (Map<String, Value>, Vec<String>) {
+    let mut properties = Map::new();
+    let mut skipped = Vec::new();
+
+    for (name, value) in values {
+        match schema.get(name).and_then(|s| build_property_value(s, value)) {
+            Some(built) => {
+                properties.insert(name.clone(), built);
+            }
+            None => skipped.push(name.clone()),
+        }
+    }
+
+    (properties, skipped)
+}
@@ -0,0 +1,41 @@
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+// Notion allows an average of three requests per second per integration
+pub const DEFAULT_REQUESTS_PER_SECOND: f64 = 3.0;
+
+// Spaces out requests so that a single client never exceeds a fixed rate
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next_slot: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: f64) -> Self {
+        let requests_per_second = if requests_per_second > 0.0 {
+            requests_per_second
+        } else {
+            DEFAULT_REQUESTS_PER_SECOND
+        };
+
+        Self {
+            interval: Duration::from_secs_f64(1.0 / requests_per_second),
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+
+    // Wait until the next request slot is available
+    pub async fn acquire(&self) {
+        let wait_until = {
+            let mut next_slot = self.next_slot.lock().await;
+            let now = Instant::now();
+            let slot = if *next_slot > now { *next_slot } else { now };
+            *next_slot = slot + self.interval;
+            slot
+        };
+
+        tokio::time::sleep_until(wait_until).await;
+    }
+}
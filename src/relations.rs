@@ -0,0 +1,143 @@
+use crate::error::{NotionMcpError, NotionResult};
+use crate::notion::NotionClient;
+use crate::properties::page_title;
+use log::debug;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+
+// Deeper expansion multiplies requests quickly
+pub const MAX_EXPAND_DEPTH: usize = 3;
+
+// What to follow: how many levels of relations, and optionally only the
+// named relation properties of the top-level page
+#[derive(Debug, Clone, Default)]
+pub struct ExpandOptions {
+    pub depth: usize,
+    pub properties: Option<Vec<String>>,
+}
+
+impl ExpandOptions {
+    // Accepts `true`, a depth (1 to 3) or a list of property names
+    pub fn from_param(value: &Value) -> NotionResult<Option<Self>> {
+        match value {
+            Value::Null | Value::Bool(false) => Ok(None),
+            Value::Bool(true) => Ok(Some(Self { depth: 1, properties: None })),
+            Value::Number(n) => match n.as_u64() {
+                Some(0) => Ok(None),
+                Some(depth) if depth as usize <= MAX_EXPAND_DEPTH => Ok(Some(Self { depth: depth as usize, properties: None })),
+                _ => Err(NotionMcpError::InvalidParams(format!("'expand' depth must be between 0 and {}", MAX_EXPAND_DEPTH))),
+            },
+            Value::Array(names) => Ok(Some(Self {
+                depth: 1,
+                properties: Some(names.iter().filter_map(|n| n.as_str().map(|s| s.to_string())).collect()),
+            })),
+            _ => Err(NotionMcpError::InvalidParams("'expand' must be a boolean, a depth or a list of property names".to_string())),
+        }
+    }
+}
+
+// Follows relations, reusing pages already fetched in the same call
+pub struct Expander<'a> {
+    client: &'a NotionClient,
+    pages: HashMap<String, Value>,
+}
+
+impl<'a> Expander<'a> {
+    pub fn new(client: &'a NotionClient) -> Self {
+        Self { client, pages: HashMap::new() }
+    }
+
+    async fn related_page(&mut self, id: &str) -> Option<Value> {
+        if let Some(page) = self.pages.get(id) {
+            return Some(page.clone());
+        }
+        match self.client.get_page_object(id).await {
+            Ok(page) => {
+                self.pages.insert(id.to_string(), page.clone());
+                Some(page)
+            }
+            Err(e) => {
+                debug!("Could not expand related page {}: {}", id, e);
+                None
+            }
+        }
+    }
+
+    // Replace truncated relations and rollups with their full values, and
+    // inline each related page as {id, title, url}. Below the last level the
+    // related page's own properties are included, expanded one level less.
+    pub fn expand_page<'b>(&'b mut self, page: &'b mut Value, options: &'b ExpandOptions) -> Pin<Box<dyn Future<Output = ()> + Send + 'b>> {
+        Box::pin(async move {
+            let page_id = page["id"].as_str().unwrap_or("").to_string();
+            let names: Vec<String> = page["properties"].as_object()
+                .map(|props| props.keys().cloned().collect())
+                .unwrap_or_default();
+
+            for name in names {
+                if options.properties.as_ref().is_some_and(|only| !only.contains(&name)) {
+                    continue;
+                }
+                let property = &page["properties"][&name];
+                let property_type = property["type"].as_str().unwrap_or("").to_string();
+                let property_id = property["id"].as_str().unwrap_or("").to_string();
+
+                match property_type.as_str() {
+                    "relation" => {
+                        let mut ids: Vec<String> = property["relation"].as_array()
+                            .map(|r| r.iter().filter_map(|p| p["id"].as_str().map(|s| s.to_string())).collect())
+                            .unwrap_or_default();
+                        if property["has_more"].as_bool().unwrap_or(false) {
+                            match self.client.get_property_item(&page_id, &property_id).await {
+                                Ok(full) => {
+                                    ids = full["results"].as_array()
+                                        .map(|items| items.iter()
+                                            .filter_map(|item| item["relation"]["id"].as_str().map(|s| s.to_string()))
+                                            .collect())
+                                        .unwrap_or_default();
+                                }
+                                Err(e) => debug!("Could not read the full relation {} of {}: {}", name, page_id, e),
+                            }
+                        }
+
+                        let mut related = Vec::new();
+                        for id in ids {
+                            let Some(mut target) = self.related_page(&id).await else {
+                                related.push(json!({ "id": id }));
+                                continue;
+                            };
+                            let mut entry = json!({
+                                "id": target["id"],
+                                "title": page_title(&target),
+                                "url": target["url"]
+                            });
+                            if options.depth > 1 {
+                                let nested = ExpandOptions { depth: options.depth - 1, properties: None };
+                                self.expand_page(&mut target, &nested).await;
+                                entry["properties"] = target["properties"].take();
+                            }
+                            related.push(entry);
+                        }
+
+                        page["properties"][&name]["relation"] = json!(related);
+                        page["properties"][&name]["has_more"] = json!(false);
+                    }
+                    "rollup" => match self.client.get_property_item(&page_id, &property_id).await {
+                        Ok(full) => {
+                            let mut rollup = full["property_item"]["rollup"].clone();
+                            if rollup["type"] == "array" {
+                                rollup["array"] = full["results"].clone();
+                            }
+                            if !rollup.is_null() {
+                                page["properties"][&name]["rollup"] = rollup;
+                            }
+                        }
+                        Err(e) => debug!("Could not resolve rollup {} of {}: {}", name, page_id, e),
+                    },
+                    _ => {}
+                }
+            }
+        })
+    }
+}
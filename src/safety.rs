@@ -0,0 +1,172 @@
+use crate::auth::Principal;
+use crate::workspaces::RequestParams;
+use actix_web::HttpMessage;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// How long the caller has to confirm a destructive operation
+const CONFIRMATION_TTL: Duration = Duration::from_secs(5 * 60);
+// Argument that carries the confirmation back with the repeated call
+pub const CONFIRMATION_PARAM: &str = "confirmation_token";
+
+// Tools that change content in Notion. Exports and indexing only write to
+// the server's disk or memory, so they stay available when read-only.
+const MUTATING_ROUTES: &[&str] = &[
+    "/api/create_page",
+    "/api/update_page",
+    "/api/archive_page",
+    "/api/duplicate_page",
+    "/api/move_page",
+    "/api/add_comment",
+    "/api/reply_to_comment",
+    "/api/import_markdown",
+    "/api/bulk_import",
+    "/api/upload_file",
+    "/api/undo",
+];
+
+// Tools whose `dry_run: true` only reads from Notion
+const DRY_RUN_ROUTES: &[&str] = &[
+    "/api/create_page",
+    "/api/update_page",
+    "/api/bulk_import",
+];
+
+pub fn is_mutating(path: &str) -> bool {
+    MUTATING_ROUTES.contains(&path)
+}
+
+pub fn supports_dry_run(path: &str) -> bool {
+    DRY_RUN_ROUTES.contains(&path)
+}
+
+// A destructive call waiting for the user's go-ahead
+#[derive(Debug)]
+struct Pending {
+    caller: Option<String>,
+    tool: String,
+    arguments: Value,
+    issued: Instant,
+}
+
+// Protection against unwanted writes: a read-only switch, and a
+// confirmation round-trip before destructive operations
+#[derive(Debug, Default)]
+pub struct WriteGuard {
+    read_only: bool,
+    confirm_destructive: bool,
+    pending: Mutex<HashMap<String, Pending>>,
+}
+
+impl WriteGuard {
+    pub fn new(read_only: bool, confirm_destructive: bool) -> Self {
+        Self { read_only, confirm_destructive, pending: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn confirms_destructive(&self) -> bool {
+        self.confirm_destructive
+    }
+
+    // Whether a destructive call may run now. The first call answers with a
+    // question for the user and a token; repeating the same call with that
+    // token as `confirmation_token` runs it. Tokens are single use and bound
+    // to the caller and the exact arguments.
+    pub fn confirm(&self, principal: Option<&Principal>, tool: &str, params: &Value, question: String) -> Result<(), Value> {
+        if !self.confirm_destructive {
+            return Ok(());
+        }
+        let caller = principal.map(|p| p.name.clone());
+        let mut arguments = params.clone();
+        let token = arguments.as_object_mut()
+            .and_then(|args| args.remove(CONFIRMATION_PARAM))
+            .and_then(|token| token.as_str().map(|t| t.to_string()));
+
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, p| p.issued.elapsed() < CONFIRMATION_TTL);
+
+        if let Some(token) = token {
+            return match pending.remove(&token) {
+                Some(p) if p.caller == caller && p.tool == tool && p.arguments == arguments => Ok(()),
+                _ => Err(json!({
+                    "error": "Invalid or expired confirmation token; call again without it to get a new one"
+                })),
+            };
+        }
+
+        let mut bytes = [0u8; 24];
+        OsRng.fill_bytes(&mut bytes);
+        let token = URL_SAFE_NO_PAD.encode(bytes);
+        pending.insert(token.clone(), Pending { caller, tool: tool.to_string(), arguments, issued: Instant::now() });
+        Err(json!({
+            "confirmation_required": true,
+            "message": question,
+            CONFIRMATION_PARAM: token,
+            "expires_in": CONFIRMATION_TTL.as_secs(),
+            "instructions": format!(
+                "Ask the user to confirm, then repeat the same call with '{}' set to this token", CONFIRMATION_PARAM
+            )
+        }))
+    }
+}
+
+// Refuse tools that change Notion while the server is read-only. Dry runs
+// only read, so they are let through.
+pub async fn reject_writes(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let read_only = req.app_data::<web::Data<Arc<WriteGuard>>>().is_some_and(|guard| guard.is_read_only());
+    let dry_run = supports_dry_run(req.path())
+        && req.extensions().get::<RequestParams>().is_some_and(|params| params.0["dry_run"] == true);
+    if read_only && is_mutating(req.path()) && !dry_run {
+        let response = HttpResponse::Forbidden().json(json!({
+            "error": format!("The server is read-only; {} is disabled", req.path())
+        }));
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    next.call(req).await.map(|res| res.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::dev::Service;
+    use actix_web::{middleware::from_fn, test, App};
+
+    async fn call(params: Value) -> u16 {
+        let guard = web::Data::new(Arc::new(WriteGuard::new(true, false)));
+        let app = test::init_service(
+            App::new()
+                .app_data(guard)
+                .wrap(from_fn(reject_writes))
+                .wrap_fn(move |req, srv| {
+                    req.extensions_mut().insert(RequestParams(params.clone()));
+                    srv.call(req)
+                })
+                .route("/api/create_page", web::post().to(HttpResponse::Ok)),
+        ).await;
+        let req = test::TestRequest::post().uri("/api/create_page").to_request();
+        test::call_service(&app, req).await.status().as_u16()
+    }
+
+    #[actix_web::test]
+    async fn read_only_blocks_writes_but_not_dry_runs() {
+        assert_eq!(call(json!({"title": "x"})).await, 403);
+        assert_eq!(call(json!({"title": "x", "dry_run": false})).await, 403);
+        assert_eq!(call(json!({"title": "x", "dry_run": true})).await, 200);
+    }
+}
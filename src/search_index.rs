@@ -0,0 +1,520 @@
+use crate::error::NotionResult;
+use crate::ids::normalize_id;
+use crate::mirror::Mirror;
+use crate::notion::NotionClient;
+use crate::properties::{page_title, property_values};
+use log::{debug, info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::RwLock;
+
+// BM25 parameters
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+// Title terms count this many times towards a page's term frequency
+const TITLE_WEIGHT: f64 = 2.0;
+const SNIPPET_CONTEXT_CHARS: usize = 80;
+
+#[derive(Debug)]
+struct IndexedPage {
+    page_id: String,
+    url: String,
+    title: String,
+    properties: BTreeMap<String, Vec<String>>,
+    // (block ID, plain text) in document order
+    blocks: Vec<(String, String)>,
+    terms: HashMap<String, f64>,
+    length: f64,
+}
+
+#[derive(Debug, Default)]
+struct IndexData {
+    pages: Vec<Option<IndexedPage>>,
+    slots: HashMap<String, usize>,
+    postings: HashMap<String, HashSet<usize>>,
+    total_length: f64,
+    count: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct SearchQuery {
+    pub terms: Vec<String>,
+    pub phrases: Vec<Vec<String>>,
+    // Property name -> required value (case-insensitive)
+    pub filters: BTreeMap<String, String>,
+    pub limit: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchHit {
+    pub page_id: String,
+    pub title: String,
+    pub url: String,
+    pub score: f64,
+    pub block_id: Option<String>,
+    pub block_url: Option<String>,
+    pub snippet: String,
+}
+
+// Local inverted index over page titles, properties and block text
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    data: RwLock<IndexData>,
+}
+
+// Lowercase word tokens with their byte spans in the original text
+fn tokenize_with_spans(text: &str) -> Vec<(usize, usize, String)> {
+    let mut tokens = Vec::new();
+    let mut start: Option<usize> = None;
+
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                tokens.push((s, i, text[s..i].to_lowercase()));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        tokens.push((s, text.len(), text[s..].to_lowercase()));
+    }
+
+    tokens
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    tokenize_with_spans(text).into_iter().map(|(_, _, t)| t).collect()
+}
+
+fn contains_phrase(tokens: &[String], phrase: &[String]) -> bool {
+    !phrase.is_empty() && tokens.windows(phrase.len()).any(|w| w == phrase)
+}
+
+// Flatten a block tree into (block ID, text) pairs in reading order
+fn flatten_blocks(blocks: &[Value], out: &mut Vec<(String, String)>) {
+    for block in blocks {
+        let text = NotionClient::block_text(block);
+        if let (Some(id), false) = (block["id"].as_str(), text.trim().is_empty()) {
+            out.push((id.to_string(), text));
+        }
+        if let Some(children) = block["children"].as_array() {
+            flatten_blocks(children, out);
+        }
+    }
+}
+
+impl SearchQuery {
+    // Parse a query string: quoted segments are phrases, the rest are terms
+    pub fn parse(query: &str) -> Self {
+        let mut parsed = SearchQuery::default();
+
+        for (i, segment) in query.split('"').enumerate() {
+            let tokens = tokenize(segment);
+            if i % 2 == 1 && !tokens.is_empty() {
+                parsed.phrases.push(tokens.clone());
+            }
+            parsed.terms.extend(tokens);
+        }
+
+        parsed.terms.sort();
+        parsed.terms.dedup();
+        parsed
+    }
+}
+
+impl IndexedPage {
+    fn new(page: &Value, blocks: &[Value]) -> Option<Self> {
+        let page_id = page["id"].as_str()?.to_string();
+        let title = page_title(page);
+
+        let properties = page["properties"].as_object()
+            .map(|props| props.iter()
+                .filter(|(_, p)| p["type"] != "title")
+                .map(|(name, p)| (name.clone(), property_values(p)))
+                .collect())
+            .unwrap_or_default();
+
+        let mut flattened = Vec::new();
+        flatten_blocks(blocks, &mut flattened);
+
+        let mut indexed = Self {
+            page_id,
+            url: page["url"].as_str().unwrap_or("").to_string(),
+            title,
+            properties,
+            blocks: flattened,
+            terms: HashMap::new(),
+            length: 0.0,
+        };
+        indexed.count_terms();
+        Some(indexed)
+    }
+
+    fn count_terms(&mut self) {
+        let add = |text: &str, weight: f64, terms: &mut HashMap<String, f64>, length: &mut f64| {
+            for token in tokenize(text) {
+                *terms.entry(token).or_insert(0.0) += weight;
+                *length += weight;
+            }
+        };
+
+        let mut terms = HashMap::new();
+        let mut length = 0.0;
+        add(&self.title, TITLE_WEIGHT, &mut terms, &mut length);
+        for values in self.properties.values() {
+            for value in values {
+                add(value, 1.0, &mut terms, &mut length);
+            }
+        }
+        for (_, text) in &self.blocks {
+            add(text, 1.0, &mut terms, &mut length);
+        }
+
+        self.terms = terms;
+        self.length = length;
+    }
+
+    fn matches_filters(&self, filters: &BTreeMap<String, String>) -> bool {
+        filters.iter().all(|(name, expected)| {
+            let expected = expected.to_lowercase();
+            if name.eq_ignore_ascii_case("title") {
+                return self.title.to_lowercase() == expected;
+            }
+            self.properties.iter()
+                .find(|(prop, _)| prop.eq_ignore_ascii_case(name))
+                .map(|(_, values)| values.iter().any(|v| v.to_lowercase() == expected))
+                .unwrap_or(false)
+        })
+    }
+
+    fn matches_phrases(&self, phrases: &[Vec<String>]) -> bool {
+        phrases.iter().all(|phrase| {
+            contains_phrase(&tokenize(&self.title), phrase)
+                || self.blocks.iter().any(|(_, text)| contains_phrase(&tokenize(text), phrase))
+                || self.properties.values().flatten().any(|v| contains_phrase(&tokenize(v), phrase))
+        })
+    }
+
+    // Pick the block that best matches the query and highlight it
+    fn best_snippet(&self, query: &SearchQuery) -> (Option<String>, String) {
+        let terms: HashSet<&str> = query.terms.iter().map(|t| t.as_str()).collect();
+
+        let score = |text: &str| -> usize {
+            let tokens = tokenize(text);
+            let distinct: HashSet<&String> = tokens.iter().filter(|t| terms.contains(t.as_str())).collect();
+            let phrase_hits = query.phrases.iter().filter(|p| contains_phrase(&tokens, p)).count();
+            phrase_hits * 100 + distinct.len()
+        };
+
+        let best_block = self.blocks.iter()
+            .map(|(id, text)| (score(text), id, text))
+            .filter(|(s, _, _)| *s > 0)
+            .max_by_key(|(s, _, _)| *s);
+
+        match best_block {
+            Some((_, id, text)) if score(&self.title) <= score(text) || query.terms.is_empty() => {
+                (Some(id.clone()), highlight(text, &terms))
+            }
+            _ => (None, highlight(&self.title, &terms)),
+        }
+    }
+}
+
+// Wrap matching terms in ** and trim the text to a window around the first match
+fn highlight(text: &str, terms: &HashSet<&str>) -> String {
+    let tokens = tokenize_with_spans(text);
+    let first_match = tokens.iter().find(|(_, _, t)| terms.contains(t.as_str())).map(|(s, _, _)| *s).unwrap_or(0);
+
+    let mut window_start = first_match.saturating_sub(SNIPPET_CONTEXT_CHARS);
+    while !text.is_char_boundary(window_start) {
+        window_start -= 1;
+    }
+    let mut window_end = (first_match + SNIPPET_CONTEXT_CHARS * 2).min(text.len());
+    while !text.is_char_boundary(window_end) {
+        window_end += 1;
+    }
+
+    let mut snippet = String::new();
+    if window_start > 0 {
+        snippet.push('…');
+    }
+    let mut cursor = window_start;
+    for (start, end, token) in &tokens {
+        if *start < window_start || *end > window_end || !terms.contains(token.as_str()) {
+            continue;
+        }
+        snippet.push_str(&text[cursor..*start]);
+        snippet.push_str("**");
+        snippet.push_str(&text[*start..*end]);
+        snippet.push_str("**");
+        cursor = *end;
+    }
+    snippet.push_str(&text[cursor..window_end]);
+    if window_end < text.len() {
+        snippet.push('…');
+    }
+
+    snippet
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Add or replace a page in the index
+    pub fn index_page(&self, page: &Value, blocks: &[Value]) {
+        let Some(indexed) = IndexedPage::new(page, blocks) else {
+            return;
+        };
+        let Ok(key) = normalize_id(&indexed.page_id) else {
+            return;
+        };
+
+        // A page indexed again takes over its previous slot
+        let mut data = self.data.write().unwrap();
+        let slot = match data.slots.get(&key).copied() {
+            Some(slot) => {
+                data.remove_slot(slot);
+                slot
+            }
+            None => {
+                data.pages.push(None);
+                data.pages.len() - 1
+            }
+        };
+
+        for term in indexed.terms.keys() {
+            data.postings.entry(term.clone()).or_default().insert(slot);
+        }
+        data.total_length += indexed.length;
+        data.count += 1;
+        data.pages[slot] = Some(indexed);
+        data.slots.insert(key, slot);
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.read().unwrap().count
+    }
+
+    // Replace the index contents with everything in the mirror
+    pub fn rebuild_from_mirror(&self, mirror: &Mirror) {
+        let fresh = SearchIndex::new();
+        for page_id in mirror.page_ids() {
+            if let Some(page) = mirror.get_page(&page_id) {
+                let blocks = mirror.get_blocks(&page_id).unwrap_or_default();
+                fresh.index_page(&page, &blocks);
+            }
+        }
+
+        let fresh = fresh.data.into_inner().unwrap();
+        *self.data.write().unwrap() = fresh;
+        info!("Search index rebuilt from mirror, {} pages", self.len());
+    }
+
+    // Fetch pages from Notion with their full block trees and index them.
+    // Without explicit IDs, every page shared with the integration is indexed.
+    pub async fn index_from_notion(&self, client: &NotionClient, page_ids: &[String]) -> NotionResult<usize> {
+        let mut pages = Vec::new();
+
+        if page_ids.is_empty() {
+            let mut cursor: Option<String> = None;
+            loop {
+                let response = client.search_with_cursor("", cursor.as_deref(), 100).await?;
+                pages.extend(response.results.into_iter().filter(|r| r["object"] == "page"));
+                match response.next_cursor {
+                    Some(next) if response.has_more => cursor = Some(next),
+                    _ => break,
+                }
+            }
+        } else {
+            for page_id in page_ids {
+                pages.push(client.get_page_object(page_id).await?);
+            }
+        }
+
+        let mut indexed = 0;
+        for page in pages {
+            let page_id = page["id"].as_str().unwrap_or("").to_string();
+            match client.get_block_tree(&page_id).await {
+                Ok(blocks) => {
+                    self.index_page(&page, &blocks);
+                    indexed += 1;
+                }
+                Err(e) => warn!("Could not index page {}: {}", page_id, e),
+            }
+        }
+
+        debug!("Indexed {} pages from Notion", indexed);
+        Ok(indexed)
+    }
+
+    // Rank pages with BM25, keeping only those that satisfy phrases and filters
+    pub fn search(&self, query: &SearchQuery) -> Vec<SearchHit> {
+        let data = self.data.read().unwrap();
+        let average_length = if data.count > 0 { data.total_length / data.count as f64 } else { 0.0 };
+        let n = data.count as f64;
+
+        let mut scores: HashMap<usize, f64> = HashMap::new();
+        if query.terms.is_empty() {
+            for (slot, page) in data.pages.iter().enumerate() {
+                if page.is_some() {
+                    scores.insert(slot, 0.0);
+                }
+            }
+        }
+        for term in &query.terms {
+            let Some(slots) = data.postings.get(term) else {
+                continue;
+            };
+            let df = slots.len() as f64;
+            let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+
+            for slot in slots {
+                let Some(page) = &data.pages[*slot] else {
+                    continue;
+                };
+                let tf = page.terms.get(term).copied().unwrap_or(0.0);
+                let norm = if average_length > 0.0 { page.length / average_length } else { 1.0 };
+                let term_score = idf * (tf * (K1 + 1.0)) / (tf + K1 * (1.0 - B + B * norm));
+                *scores.entry(*slot).or_insert(0.0) += term_score;
+            }
+        }
+
+        let mut hits: Vec<SearchHit> = scores.into_iter()
+            .filter_map(|(slot, score)| {
+                let page = data.pages[slot].as_ref()?;
+                if !page.matches_filters(&query.filters) || !page.matches_phrases(&query.phrases) {
+                    return None;
+                }
+                let (block_id, snippet) = page.best_snippet(query);
+                let block_url = block_id.as_ref().map(|id| format!("{}#{}", page.url, id.replace('-', "")));
+                Some(SearchHit {
+                    page_id: page.page_id.clone(),
+                    title: page.title.clone(),
+                    url: page.url.clone(),
+                    score,
+                    block_id,
+                    block_url,
+                    snippet,
+                })
+            })
+            .collect();
+
+        hits.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+        hits.truncate(query.limit.max(1));
+        hits
+    }
+}
+
+impl IndexData {
+    fn remove_slot(&mut self, slot: usize) {
+        if let Some(page) = self.pages[slot].take() {
+            for term in page.terms.keys() {
+                if let Some(slots) = self.postings.get_mut(term) {
+                    slots.remove(&slot);
+                    if slots.is_empty() {
+                        self.postings.remove(term);
+                    }
+                }
+            }
+            self.total_length -= page.length;
+            self.count -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn page(id: &str, title: &str, text: &str) -> (Value, Vec<Value>) {
+        let page = json!({
+            "id": id,
+            "url": format!("https://www.notion.so/{}", id.replace('-', "")),
+            "properties": {
+                "Name": { "type": "title", "title": [{ "plain_text": title }] }
+            }
+        });
+        let blocks = vec![json!({
+            "id": format!("{}-b", id),
+            "type": "paragraph",
+            "paragraph": { "rich_text": [{ "plain_text": text }] }
+        })];
+        (page, blocks)
+    }
+
+    fn index(pages: &[(&str, &str, &str)]) -> SearchIndex {
+        let index = SearchIndex::new();
+        for (id, title, text) in pages {
+            let (page, blocks) = page(id, title, text);
+            index.index_page(&page, &blocks);
+        }
+        index
+    }
+
+    fn search(index: &SearchIndex, query: &str) -> Vec<String> {
+        let mut query = SearchQuery::parse(query);
+        query.limit = 10;
+        index.search(&query).into_iter().map(|hit| hit.page_id).collect()
+    }
+
+    const A: &str = "aaaaaaaa-aaaa-aaaa-aaaa-aaaaaaaaaaaa";
+    const B: &str = "bbbbbbbb-bbbb-bbbb-bbbb-bbbbbbbbbbbb";
+    const C: &str = "cccccccc-cccc-cccc-cccc-cccccccccccc";
+
+    #[test]
+    fn ranks_frequent_terms_higher() {
+        let index = index(&[
+            (A, "Notes", "rust once among many other unrelated words here"),
+            (B, "Notes", "rust rust rust"),
+            (C, "Notes", "nothing relevant"),
+        ]);
+        assert_eq!(search(&index, "rust"), vec![B, A]);
+    }
+
+    #[test]
+    fn title_matches_outweigh_body_matches() {
+        let index = index(&[
+            (A, "Other", "deploy checklist"),
+            (B, "Deploy", "checklist"),
+        ]);
+        assert_eq!(search(&index, "deploy")[0], B);
+    }
+
+    #[test]
+    fn rare_terms_weigh_more() {
+        let index = index(&[
+            (A, "Notes", "common common"),
+            (B, "Notes", "common rare"),
+            (C, "Notes", "common"),
+        ]);
+        assert_eq!(search(&index, "common rare")[0], B);
+    }
+
+    #[test]
+    fn phrases_need_adjacent_words_in_order() {
+        let index = index(&[
+            (A, "Notes", "the quick brown fox"),
+            (B, "Notes", "brown and quick"),
+        ]);
+        assert_eq!(search(&index, "\"quick brown\""), vec![A]);
+        assert_eq!(search(&index, "\"brown quick\""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn reindexing_replaces_the_page_in_place() {
+        let index = index(&[(A, "Notes", "before")]);
+        let (page, blocks) = page(&A.replace('-', "").to_uppercase(), "Notes", "after");
+        index.index_page(&page, &blocks);
+
+        assert_eq!(index.len(), 1);
+        assert_eq!(index.data.read().unwrap().pages.len(), 1);
+        assert!(search(&index, "before").is_empty());
+        assert_eq!(search(&index, "after").len(), 1);
+    }
+}
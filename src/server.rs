@@ -1,13 +1,43 @@
-use crate::notion::NotionClient;
-use crate::error::NotionMcpError;
-use actix_web::{web, App, HttpServer, Responder};
+use crate::audit::{self, AuditLog, AuditQuery};
+use crate::auth::{self, AuthConfig, Principal};
+use crate::oauth;
+use crate::bulk_import::{self, BulkImportOptions};
+use crate::chunks::{self, DEFAULT_MAX_TOKENS, MIN_MAX_TOKENS};
+use crate::concurrency::{self, Precondition};
+use crate::comments;
+use crate::database_export::{self, TableFormat};
+use crate::dry_run;
+use crate::error::{NotionMcpError, NotionResult};
+use crate::file_proxy::FileProxy;
+use crate::file_roots::FileRoots;
+use crate::export;
+use crate::ids::normalize_id;
+use crate::import;
+use crate::journal::Journal;
+use crate::markdown::markdown_to_rich_text;
+use crate::mirror::{self, Mirror};
+use crate::notion::{NotionClient, NotionPageResponse, PageParent};
+use crate::notion_oauth::{self, NotionOAuth};
+use crate::page_ops;
+use crate::policy::PolicySet;
+use crate::properties::page_title;
+use crate::relations::{ExpandOptions, Expander};
+use crate::safety::{self, WriteGuard};
+use crate::search_index::SearchQuery;
+use crate::uploads::{self, UploadFile};
+use crate::users;
+use crate::workspaces::{self, Accounts, NotionSession};
+use actix_web::http::{header, StatusCode};
+use actix_web::{middleware, web, App, HttpResponse, HttpServer, Responder};
+use base64::Engine;
 use actix_cors::Cors;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::sync::Arc;
 
 async fn handle_search(
-    notion_client: web::Data<Arc<NotionClient>>,
+    notion_client: NotionSession,
     params: web::Json<Value>,
 ) -> impl Responder {
     let query = match params.get("query").and_then(|v| v.as_str()) {
@@ -29,35 +59,90 @@ async fn handle_search(
     }
 }
 
+// Show people in page properties by name when the user list is readable
+async fn resolve_people(notion_client: &NotionClient, properties: &mut Value) {
+    let has_people = properties.as_object().is_some_and(|props| props.values().any(|p| {
+        matches!(p["type"].as_str(), Some("people" | "created_by" | "last_edited_by"))
+    }));
+    if has_people {
+        if let Ok(directory) = notion_client.directory().await {
+            directory.resolve_properties(properties);
+        }
+    }
+}
+
 async fn handle_get_page(
-    notion_client: web::Data<Arc<NotionClient>>,
+    notion_client: NotionSession,
     params: web::Json<Value>,
 ) -> impl Responder {
-    let page_id = match params.get("page_id").and_then(|v| v.as_str()) {
-        Some(id) => id,
+    let page_id = match params.get("page_id").and_then(|v| v.as_str()).map(normalize_id) {
+        Some(Ok(id)) => id,
+        Some(Err(e)) => return web::Json(json!({
+            "error": e.to_string()
+        })),
         None => return web::Json(json!({
             "error": "Falta parámetro 'page_id'"
         }))
     };
+    let page_id = page_id.as_str();
     
-    match notion_client.get_page(page_id).await {
-        Ok(page) => web::Json(json!(page)),
-        Err(e) => web::Json(json!({
+    let expand = match ExpandOptions::from_param(params.get("expand").unwrap_or(&Value::Null)) {
+        Ok(expand) => expand,
+        Err(e) => return web::Json(json!({
             "error": e.to_string()
         }))
+    };
+    
+    let mirrored = notion_client.mirror()
+        .and_then(|m| m.get_page(page_id))
+        .and_then(|page| serde_json::from_value::<NotionPageResponse>(page).ok());
+    
+    let mut page = match mirrored {
+        Some(page) => {
+            debug!("Serving page {} from mirror", page_id);
+            page
+        }
+        None => match notion_client.get_page(page_id).await {
+            Ok(page) => page,
+            Err(e) => return web::Json(json!({
+                "error": e.to_string()
+            }))
+        }
+    };
+    
+    // Relations are followed live, even when the page comes from the mirror
+    if let Some(expand) = expand {
+        let mut object = json!({ "id": page.id, "properties": page.properties });
+        Expander::new(&notion_client).expand_page(&mut object, &expand).await;
+        page.properties = object["properties"].take();
     }
+    
+    resolve_people(&notion_client, &mut page.properties).await;
+    web::Json(json!(page))
 }
 
 async fn handle_get_page_content(
-    notion_client: web::Data<Arc<NotionClient>>,
+    notion_client: NotionSession,
     params: web::Json<Value>,
 ) -> impl Responder {
-    let page_id = match params.get("page_id").and_then(|v| v.as_str()) {
-        Some(id) => id,
+    let page_id = match params.get("page_id").and_then(|v| v.as_str()).map(normalize_id) {
+        Some(Ok(id)) => id,
+        Some(Err(e)) => return web::Json(json!({
+            "error": e.to_string()
+        })),
         None => return web::Json(json!({
             "error": "Falta parámetro 'page_id'"
         }))
     };
+    let page_id = page_id.as_str();
+    
+    if let Some(content) = notion_client.mirror().and_then(|m| m.get_blocks(page_id)) {
+        debug!("Serving content of page {} from mirror", page_id);
+        return web::Json(json!({
+            "content": content,
+            "text": NotionClient::extract_text_from_blocks(&content)
+        }));
+    }
     
     match notion_client.get_page_content(page_id).await {
         Ok(content) => web::Json(json!({
@@ -70,16 +155,96 @@ async fn handle_get_page_content(
     }
 }
 
+async fn handle_get_page_chunks(
+    notion_client: NotionSession,
+    params: web::Json<Value>,
+) -> impl Responder {
+    // Canonical form keeps chunk IDs stable whichever way the page was given
+    let page_id = match params.get("page_id").and_then(|v| v.as_str()).map(normalize_id) {
+        Some(Ok(id)) => id,
+        Some(Err(e)) => return web::Json(json!({
+            "error": e.to_string()
+        })),
+        None => return web::Json(json!({
+            "error": "Missing 'page_id' parameter"
+        }))
+    };
+    let page_id = page_id.as_str();
+    
+    let max_tokens = params.get("max_tokens")
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize)
+        .unwrap_or(DEFAULT_MAX_TOKENS)
+        .max(MIN_MAX_TOKENS);
+    
+    let blocks = match notion_client.mirror().and_then(|m| m.get_blocks(page_id)) {
+        Some(blocks) => blocks,
+        None => match notion_client.get_block_tree(page_id).await {
+            Ok(blocks) => blocks,
+            Err(e) => return web::Json(json!({
+                "error": e.to_string()
+            }))
+        }
+    };
+    
+    let all_chunks = chunks::chunk_page(page_id, &blocks, max_tokens);
+    let total = all_chunks.len();
+    
+    // Select chunk N, a [start, end] range, or list every chunk without text
+    let (start, end, include_text) = match params.get("chunk").and_then(|v| v.as_u64()) {
+        Some(n) => (n as usize, n as usize, true),
+        None => match (params.get("start").and_then(|v| v.as_u64()), params.get("end").and_then(|v| v.as_u64())) {
+            (None, None) => (0, total.saturating_sub(1), params.get("include_text").and_then(|v| v.as_bool()).unwrap_or(false)),
+            (start, end) => (
+                start.unwrap_or(0) as usize,
+                end.map(|e| e as usize).unwrap_or(total.saturating_sub(1)),
+                true,
+            ),
+        }
+    };
+    
+    if total > 0 && start >= total {
+        return web::Json(json!({
+            "error": format!("Chunk {} out of range, page has {} chunks", start, total)
+        }));
+    }
+    
+    let selected: Vec<Value> = all_chunks.into_iter()
+        .skip(start)
+        .take(end.saturating_sub(start) + 1)
+        .map(|chunk| {
+            let mut value = json!(chunk);
+            if !include_text {
+                if let Some(obj) = value.as_object_mut() {
+                    obj.remove("text");
+                }
+            }
+            value
+        })
+        .collect();
+    
+    web::Json(json!({
+        "page_id": page_id,
+        "max_tokens": max_tokens,
+        "total_chunks": total,
+        "chunks": selected
+    }))
+}
+
 async fn handle_query_database(
-    notion_client: web::Data<Arc<NotionClient>>,
+    notion_client: NotionSession,
     params: web::Json<Value>,
 ) -> impl Responder {
-    let database_id = match params.get("database_id").and_then(|v| v.as_str()) {
-        Some(id) => id,
+    let database_id = match params.get("database_id").and_then(|v| v.as_str()).map(normalize_id) {
+        Some(Ok(id)) => id,
+        Some(Err(e)) => return web::Json(json!({
+            "error": e.to_string()
+        })),
         None => return web::Json(json!({
             "error": "Missing 'database_id' parameter"
         }))
     };
+    let database_id = database_id.as_str();
     
     let mut filter_obj = json!({});
     
@@ -112,6 +277,22 @@ async fn handle_query_database(
     
     debug!("Query params - database_id: {}, filter: {:?}, limit: {:?}", database_id, filter, limit);
     
+    // Expanded results keep every property instead of the brand projection
+    match ExpandOptions::from_param(params.get("expand").unwrap_or(&Value::Null)) {
+        Ok(Some(expand)) => return query_expanded(&notion_client, database_id, filter, limit, &expand).await,
+        Ok(None) => {}
+        Err(e) => return web::Json(json!({
+            "error": e.to_string()
+        }))
+    }
+    
+    if let Some(results) = notion_client.mirror().and_then(|m| query_mirror(&notion_client, m, database_id, filter.as_ref(), limit)) {
+        debug!("Serving query from mirror, {} results", results.len());
+        return web::Json(json!({
+            "results": results
+        }));
+    }
+    
     match notion_client.query_database(database_id, filter, limit).await {
         Ok(results) => {
             debug!("Query successful, {} results", results.len());
@@ -128,14 +309,139 @@ async fn handle_query_database(
     }
 }
 
+// Answer a database query from the mirror, if the database and filter allow it
+async fn query_expanded(
+    notion_client: &NotionClient,
+    database_id: &str,
+    filter: Option<Value>,
+    limit: Option<u32>,
+    expand: &ExpandOptions,
+) -> web::Json<Value> {
+    let pages = match notion_client.query_database_pages(database_id, filter).await {
+        Ok(pages) => pages,
+        Err(e) => return web::Json(json!({
+            "error": e.to_string()
+        }))
+    };
+    
+    let mut expander = Expander::new(notion_client);
+    let mut results = Vec::new();
+    for mut page in pages.into_iter().take(limit.unwrap_or(100) as usize) {
+        expander.expand_page(&mut page, expand).await;
+        resolve_people(notion_client, &mut page["properties"]).await;
+        results.push(json!({
+            "id": page["id"],
+            "url": page["url"],
+            "title": page_title(&page),
+            "properties": page["properties"]
+        }));
+    }
+    
+    web::Json(json!({
+        "results": results
+    }))
+}
+
+fn query_mirror(notion_client: &NotionClient, mirror: &Mirror, database_id: &str, filter: Option<&Value>, limit: Option<u32>) -> Option<Vec<Value>> {
+    let rows = mirror.database_rows(database_id)?;
+    
+    let mut matched = Vec::new();
+    for row in rows {
+        let keep = match filter {
+            Some(f) => mirror::matches_filter(&row, f)?,
+            None => true,
+        };
+        if keep {
+            matched.push(row);
+        }
+    }
+    
+    // Notion returns rows most recently created first by default
+    matched.sort_by(|a, b| b["created_time"].as_str().cmp(&a["created_time"].as_str()));
+    
+    Some(matched.iter()
+        .take(limit.unwrap_or(100) as usize)
+        .filter_map(|page| notion_client.extract_page_info(page))
+        .collect())
+}
+
+async fn handle_full_text_search(
+    notion_client: NotionSession,
+    params: web::Json<Value>,
+) -> impl Responder {
+    let search_index = notion_client.search_index();
+    let query = match params.get("query").and_then(|v| v.as_str()) {
+        Some(q) => q,
+        None => return web::Json(json!({
+            "error": "Missing 'query' parameter"
+        }))
+    };
+    
+    let mut search_query = SearchQuery::parse(query);
+    search_query.limit = params.get("limit")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(10) as usize;
+    
+    if let Some(filters) = params.get("filters").and_then(|v| v.as_object()) {
+        for (name, value) in filters {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            search_query.filters.insert(name.clone(), value);
+        }
+    }
+    
+    let results = search_index.search(&search_query);
+    debug!("Full-text search '{}' matched {} pages", query, results.len());
+    
+    web::Json(json!({
+        "indexed_pages": search_index.len(),
+        "results": results
+    }))
+}
+
+async fn handle_index_pages(
+    notion_client: NotionSession,
+    params: web::Json<Value>,
+) -> impl Responder {
+    let search_index = notion_client.search_index();
+    let page_ids = params.get("page_ids")
+        .and_then(|v| v.as_array())
+        .map(|ids| ids.iter().filter_map(|id| id.as_str().map(|s| s.to_string())).collect::<Vec<_>>())
+        .unwrap_or_default();
+    
+    match search_index.index_from_notion(&notion_client, &page_ids).await {
+        Ok(indexed) => web::Json(json!({
+            "indexed": indexed,
+            "indexed_pages": search_index.len()
+        })),
+        Err(e) => web::Json(json!({
+            "error": e.to_string()
+        }))
+    }
+}
+
+// Explicit parent object, or a bare parent_id whose type is detected
+async fn parent_param(notion_client: &NotionClient, params: &Value) -> Option<NotionResult<PageParent>> {
+    match (params.get("parent"), params.get("parent_id").and_then(|v| v.as_str())) {
+        (Some(parent), _) => Some(PageParent::from_params(parent)),
+        (None, Some(id)) => Some(notion_client.detect_parent(id).await),
+        (None, None) => None,
+    }
+}
+
 async fn handle_create_page(
-    notion_client: web::Data<Arc<NotionClient>>,
+    notion_client: NotionSession,
     params: web::Json<Value>,
 ) -> impl Responder {
-    let parent_id = match params.get("parent_id").and_then(|v| v.as_str()) {
-        Some(id) => id,
+    let parent = match parent_param(&notion_client, &params).await {
+        Some(Ok(parent)) => parent,
+        Some(Err(e)) => return web::Json(json!({
+            "error": e.to_string()
+        })),
         None => return web::Json(json!({
-            "error": "Missing 'parent_id' parameter"
+            "error": "Missing 'parent' or 'parent_id' parameter"
         }))
     };
     
@@ -150,7 +456,16 @@ async fn handle_create_page(
         .and_then(|v| v.as_array())
         .map(|arr| arr.to_vec());
     
-    match notion_client.create_page(parent_id, properties, content).await {
+    if params.get("dry_run").and_then(|v| v.as_bool()).unwrap_or(false) {
+        return match dry_run::preview_create(&notion_client, &parent, properties, content).await {
+            Ok(preview) => web::Json(json!(preview)),
+            Err(e) => web::Json(json!({
+                "error": e.to_string()
+            }))
+        };
+    }
+    
+    match notion_client.create_page(&parent, properties, content).await {
         Ok(page) => web::Json(json!(page)),
         Err(e) => web::Json(json!({
             "error": e.to_string()
@@ -159,7 +474,7 @@ async fn handle_create_page(
 }
 
 async fn handle_update_page(
-    notion_client: web::Data<Arc<NotionClient>>,
+    notion_client: NotionSession,
     params: web::Json<Value>,
 ) -> impl Responder {
     let page_id = match params.get("page_id").and_then(|v| v.as_str()) {
@@ -176,33 +491,1049 @@ async fn handle_update_page(
         }))
     };
     
+    let precondition = match Precondition::from_params(&params) {
+        Ok(precondition) => precondition,
+        Err(e) => return web::Json(json!({
+            "error": e.to_string()
+        }))
+    };
+    // Merge mode writes only what the caller changed from `base_properties`,
+    // the properties as it read them
+    let merge = params.get("merge").and_then(|v| v.as_bool()).unwrap_or(false);
+    let base = params.get("base_properties").and_then(|v| v.as_object());
+    if merge && base.is_none() {
+        return web::Json(json!({
+            "error": "Merge mode needs 'base_properties', the page properties as last read"
+        }));
+    }
+    
+    let mut properties = properties;
+    let mut unchanged = None;
+    if merge || precondition.is_some() {
+        let page = match notion_client.get_page_object(page_id).await {
+            Ok(page) => page,
+            Err(e) => return web::Json(json!({
+                "error": e.to_string()
+            }))
+        };
+        let conflict = |message: String, conflicts: Vec<concurrency::PropertyConflict>| web::Json(json!({
+            "error": message,
+            "conflict": true,
+            "page_id": page["id"],
+            "last_edited_time": page["last_edited_time"],
+            "last_edited_by": page["last_edited_by"]["id"],
+            "conflicts": conflicts
+        }));
+        
+        if let Some(precondition) = &precondition {
+            if !precondition.holds(&page) {
+                return conflict(format!(
+                    "Page {} was edited at {}; read it again before updating",
+                    page_id, page["last_edited_time"].as_str().unwrap_or("an unknown time")
+                ), Vec::new());
+            }
+        }
+        if let (true, Some(base)) = (merge, base) {
+            // People named by email or name are compared by their IDs
+            if let Err(e) = notion_client.resolve_people_payload(&mut properties).await {
+                return web::Json(json!({
+                    "error": e.to_string()
+                }));
+            }
+            let current = page["properties"].as_object().cloned().unwrap_or_default();
+            let requested = properties.as_object().cloned().unwrap_or_default();
+            let merged = concurrency::merge(base, &current, &requested);
+            if !merged.conflicts.is_empty() {
+                let names: Vec<&str> = merged.conflicts.iter().map(|c| c.property.as_str()).collect();
+                return conflict(format!("Properties changed by someone else since they were read: {}", names.join(", ")), merged.conflicts);
+            }
+            properties = Value::Object(merged.properties);
+            unchanged = Some(merged.unchanged);
+        }
+        
+        // Nothing left to write once the caller's unchanged values are dropped
+        if merge && properties.as_object().is_some_and(|p| p.is_empty()) {
+            let mut page = page;
+            page["merge"] = json!({ "applied": [], "unchanged": unchanged });
+            return web::Json(page);
+        }
+    }
+    
+    if params.get("dry_run").and_then(|v| v.as_bool()).unwrap_or(false) {
+        return match dry_run::preview_update(&notion_client, page_id, properties).await {
+            Ok(preview) => web::Json(json!(preview)),
+            Err(e) => web::Json(json!({
+                "error": e.to_string()
+            }))
+        };
+    }
+    
+    let applied: Vec<String> = properties.as_object().map(|p| p.keys().cloned().collect()).unwrap_or_default();
     match notion_client.update_page(page_id, properties).await {
-        Ok(page) => web::Json(json!(page)),
+        Ok(mut page) => {
+            if let Some(unchanged) = unchanged {
+                page["merge"] = json!({ "applied": applied, "unchanged": unchanged });
+            }
+            web::Json(page)
+        }
         Err(e) => web::Json(json!({
             "error": e.to_string()
         }))
     }
 }
 
-pub async fn run_notion_mcp_server(notion_client: Arc<NotionClient>, port: u16) -> std::io::Result<()> {
-    info!("Starting HTTP server on port {}", port);
+async fn handle_archive_page(
+    notion_client: NotionSession,
+    guard: web::Data<Arc<WriteGuard>>,
+    principal: Option<web::ReqData<Principal>>,
+    params: web::Json<Value>,
+) -> impl Responder {
+    let page_id = match params.get("page_id").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => return web::Json(json!({
+            "error": "Missing 'page_id' parameter"
+        }))
+    };
     
-    let notion_client_data = web::Data::new(notion_client);
+    // "archived": false restores the page from the trash
+    let archived = params.get("archived").and_then(|v| v.as_bool()).unwrap_or(true);
     
-    HttpServer::new(move || {
-        let cors = Cors::permissive();
+    if archived && guard.confirms_destructive() {
+        let question = format!("Move the page {} and everything inside it to the trash?", describe_page(&notion_client, page_id).await);
+        if let Err(response) = guard.confirm(principal.as_deref(), "archive_page", &params, question) {
+            return web::Json(response);
+        }
+    }
+    
+    match notion_client.set_archived(page_id, archived).await {
+        Ok(page) => web::Json(json!({
+            "page_id": page["id"],
+            "archived": page["archived"],
+            "url": page["url"]
+        })),
+        Err(e) => web::Json(json!({
+            "error": e.to_string()
+        }))
+    }
+}
+
+// Title and ID of a page, for confirmation questions
+async fn describe_page(notion_client: &NotionClient, page_id: &str) -> String {
+    match notion_client.get_page_object(page_id).await {
+        Ok(page) => format!("'{}' ({})", page_title(&page), page_id),
+        Err(_) => page_id.to_string(),
+    }
+}
+
+async fn handle_duplicate_page(
+    notion_client: NotionSession,
+    params: web::Json<Value>,
+) -> impl Responder {
+    let page_id = match params.get("page_id").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => return web::Json(json!({
+            "error": "Missing 'page_id' parameter"
+        }))
+    };
+    
+    // Without a parent the copy is created next to the original
+    let parent = match parent_param(&notion_client, &params).await.transpose() {
+        Ok(parent) => parent,
+        Err(e) => return web::Json(json!({
+            "error": e.to_string()
+        }))
+    };
+    let title = params.get("title").and_then(|v| v.as_str()).map(|s| s.to_string());
+    
+    match page_ops::duplicate_page(&notion_client, page_id, parent, title).await {
+        Ok(report) => web::Json(json!(report)),
+        Err(e) => web::Json(json!({
+            "error": e.to_string()
+        }))
+    }
+}
+
+async fn handle_move_page(
+    notion_client: NotionSession,
+    guard: web::Data<Arc<WriteGuard>>,
+    principal: Option<web::ReqData<Principal>>,
+    params: web::Json<Value>,
+) -> impl Responder {
+    let page_id = match params.get("page_id").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => return web::Json(json!({
+            "error": "Missing 'page_id' parameter"
+        }))
+    };
+    
+    let parent = match parent_param(&notion_client, &params).await {
+        Some(Ok(parent)) => parent,
+        Some(Err(e)) => return web::Json(json!({
+            "error": e.to_string()
+        })),
+        None => return web::Json(json!({
+            "error": "Missing 'parent' or 'parent_id' parameter"
+        }))
+    };
+    
+    if guard.confirms_destructive() {
+        let question = format!("Move the page {} to a new parent?", describe_page(&notion_client, page_id).await);
+        if let Err(response) = guard.confirm(principal.as_deref(), "move_page", &params, question) {
+            return web::Json(response);
+        }
+    }
+    
+    match page_ops::move_page(&notion_client, page_id, parent).await {
+        Ok(report) => web::Json(json!(report)),
+        Err(e) => web::Json(json!({
+            "error": e.to_string()
+        }))
+    }
+}
+
+async fn handle_list_comments(
+    notion_client: NotionSession,
+    params: web::Json<Value>,
+) -> impl Responder {
+    let block_id = match params.get("block_id").or_else(|| params.get("page_id")).and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => return web::Json(json!({
+            "error": "Missing 'page_id' or 'block_id' parameter"
+        }))
+    };
+    
+    match notion_client.list_comments(block_id).await {
+        Ok(comments) => web::Json(json!({
+            "total": comments.len(),
+            "discussions": comments::group_discussions(&comments)
+        })),
+        Err(e) => web::Json(json!({
+            "error": e.to_string()
+        }))
+    }
+}
+
+async fn handle_add_comment(
+    notion_client: NotionSession,
+    params: web::Json<Value>,
+) -> impl Responder {
+    let page_id = match params.get("page_id").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => return web::Json(json!({
+            "error": "Missing 'page_id' parameter"
+        }))
+    };
+    
+    let text = match params.get("text").and_then(|v| v.as_str()) {
+        Some(text) => text,
+        None => return web::Json(json!({
+            "error": "Missing 'text' parameter"
+        }))
+    };
+    
+    match notion_client.add_comment(page_id, markdown_to_rich_text(text)).await {
+        Ok(comment) => web::Json(json!(comments::render_comment(&comment))),
+        Err(e) => web::Json(json!({
+            "error": e.to_string()
+        }))
+    }
+}
+
+async fn handle_reply_to_comment(
+    notion_client: NotionSession,
+    params: web::Json<Value>,
+) -> impl Responder {
+    let discussion_id = match params.get("discussion_id").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => return web::Json(json!({
+            "error": "Missing 'discussion_id' parameter"
+        }))
+    };
+    
+    let text = match params.get("text").and_then(|v| v.as_str()) {
+        Some(text) => text,
+        None => return web::Json(json!({
+            "error": "Missing 'text' parameter"
+        }))
+    };
+    
+    match notion_client.reply_to_discussion(discussion_id, markdown_to_rich_text(text)).await {
+        Ok(comment) => web::Json(json!(comments::render_comment(&comment))),
+        Err(e) => web::Json(json!({
+            "error": e.to_string()
+        }))
+    }
+}
+
+async fn handle_list_users(
+    notion_client: NotionSession,
+) -> impl Responder {
+    // Always fetched fresh; this also refreshes the cached directory
+    match notion_client.list_users().await {
+        Ok(users) => web::Json(json!({
+            "total": users.len(),
+            "users": users.iter().map(users::user_summary).collect::<Vec<_>>()
+        })),
+        Err(e) => web::Json(json!({
+            "error": e.to_string()
+        }))
+    }
+}
+
+async fn handle_get_user(
+    notion_client: NotionSession,
+    params: web::Json<Value>,
+) -> impl Responder {
+    let user_id = match params.get("user_id").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => return web::Json(json!({
+            "error": "Missing 'user_id' parameter"
+        }))
+    };
+    
+    match notion_client.get_user(user_id).await {
+        Ok(user) => web::Json(users::user_summary(&user)),
+        Err(e) => web::Json(json!({
+            "error": e.to_string()
+        }))
+    }
+}
+
+async fn handle_get_bot_user(
+    notion_client: NotionSession,
+) -> impl Responder {
+    match notion_client.get_bot_user().await {
+        Ok(bot) => web::Json(json!({
+            "id": bot["id"],
+            "name": bot["name"],
+            "workspace_name": bot["bot"]["workspace_name"],
+            "owner": bot["bot"]["owner"]
+        })),
+        Err(e) => web::Json(json!({
+            "error": e.to_string()
+        }))
+    }
+}
+
+async fn handle_upload_file(
+    notion_client: NotionSession,
+    roots: web::Data<Arc<FileRoots>>,
+    params: web::Json<Value>,
+) -> impl Responder {
+    let content_type = params.get("content_type").and_then(|v| v.as_str());
+    
+    // A path under MCP_UPLOAD_DIR, or base64 content sent by the client with a filename
+    let file = match (params.get("path").and_then(|v| v.as_str()), params.get("content_base64").and_then(|v| v.as_str())) {
+        (Some(path), _) => roots.uploads.resolve(path).and_then(|path| UploadFile::from_path(&path, content_type)),
+        (None, Some(content)) => {
+            let filename = match params.get("filename").and_then(|v| v.as_str()) {
+                Some(name) => name.to_string(),
+                None => return web::Json(json!({
+                    "error": "Missing 'filename' parameter"
+                }))
+            };
+            match base64::engine::general_purpose::STANDARD.decode(content.trim()) {
+                Ok(data) => UploadFile::from_bytes(filename, data, content_type),
+                Err(e) => return web::Json(json!({
+                    "error": format!("Invalid 'content_base64': {}", e)
+                }))
+            }
+        }
+        (None, None) => return web::Json(json!({
+            "error": "Missing 'path' or 'content_base64' parameter"
+        }))
+    };
+    let file = match file {
+        Ok(file) => file,
+        Err(e) => return web::Json(json!({
+            "error": e.to_string()
+        }))
+    };
+    
+    let upload = match uploads::upload_file(&notion_client, &file).await {
+        Ok(upload) => upload,
+        Err(e) => return web::Json(json!({
+            "error": e.to_string()
+        }))
+    };
+    
+    let page_id = params.get("page_id").and_then(|v| v.as_str());
+    let property = params.get("property").and_then(|v| v.as_str());
+    let block_parent = params.get("block_parent_id").and_then(|v| v.as_str());
+    
+    // Optionally attach the upload; it expires if not attached within an hour
+    let attached = match (page_id, property, block_parent) {
+        (Some(page_id), Some(property), _) => {
+            uploads::attach_to_property(&notion_client, page_id, property, &upload).await
+                .map(|_| json!({ "page_id": page_id, "property": property }))
+        }
+        (_, _, Some(parent_id)) => {
+            let block_type = params.get("block_type").and_then(|v| v.as_str()).unwrap_or(file.block_type());
+            let caption = params.get("caption").and_then(|v| v.as_str());
+            notion_client.append_block_children(parent_id, vec![upload.block(block_type, caption)]).await
+                .map(|created| json!({ "block_id": created.first().map(|b| b["id"].clone()), "block_type": block_type }))
+        }
+        _ => Ok(Value::Null),
+    };
+    
+    match attached {
+        Ok(attached) => web::Json(json!({
+            "upload": upload,
+            "attached": attached
+        })),
+        Err(e) => web::Json(json!({
+            "upload": upload,
+            "error": format!("Uploaded but could not attach: {}", e)
+        }))
+    }
+}
+
+// GET /files/{page_id}/{property}/{index}: serve a file of a files property
+// from the local cache, or redirect to a freshly signed URL
+async fn handle_file(
+    notion_client: NotionSession,
+    file_proxy: web::Data<Arc<FileProxy>>,
+    path: web::Path<(String, String, usize)>,
+) -> HttpResponse {
+    let (page_id, property, index) = path.into_inner();
+    // Also keeps the cache path inside the cache directory
+    let page_id = match normalize_id(&page_id) {
+        Ok(id) => id,
+        Err(e) => return HttpResponse::BadRequest().json(json!({
+            "error": e.to_string()
+        })),
+    };
+    
+    if let Some((cached, filename)) = file_proxy.cached(notion_client.partition(), &page_id, &property, index) {
+        match std::fs::read(&cached) {
+            Ok(data) => return file_response(&filename, data),
+            Err(e) => warn!("Could not read cached file {}: {}", cached.display(), e),
+        }
+    }
+    
+    let file = match file_proxy.resolve(&notion_client, notion_client.partition(), &page_id, &property, index).await {
+        Ok(file) => file,
+        Err(NotionMcpError::ResourceNotFound(message)) => return HttpResponse::NotFound().json(json!({
+            "error": message
+        })),
+        Err(e) => return HttpResponse::BadGateway().json(json!({
+            "error": e.to_string()
+        })),
+    };
+    
+    if file.hosted && file_proxy.caches_bytes() {
+        match notion_client.download(&file.url).await {
+            Ok(data) => {
+                file_proxy.store(notion_client.partition(), &page_id, &property, index, &file.filename, &data);
+                return file_response(&file.filename, data);
+            }
+            Err(e) => warn!("Could not download {} for caching: {}", file.filename, e),
+        }
+    }
+    
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, file.url))
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .finish()
+}
+
+// Files come from user content, so they are downloaded rather than rendered
+// (an HTML or SVG file would otherwise run script on this origin) and are
+// never kept by shared caches
+fn file_response(filename: &str, data: Vec<u8>) -> HttpResponse {
+    let content_type = mime_guess::from_path(filename).first_or_octet_stream();
+    HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, content_type.essence_str().to_string()))
+        .insert_header(header::ContentDisposition {
+            disposition: header::DispositionType::Attachment,
+            parameters: vec![header::DispositionParam::Filename(filename.to_string())],
+        })
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .insert_header((header::CACHE_CONTROL, "private, no-store"))
+        .body(data)
+}
+
+async fn handle_export(
+    notion_client: NotionSession,
+    roots: web::Data<Arc<FileRoots>>,
+    params: web::Json<Value>,
+) -> impl Responder {
+    let output_dir = match params.get("output_dir").and_then(|v| v.as_str()) {
+        Some(dir) => dir,
+        None => return web::Json(json!({
+            "error": "Missing 'output_dir' parameter"
+        }))
+    };
+    // Callers only name a directory under MCP_EXPORT_DIR
+    let output_dir = match roots.export.resolve(output_dir) {
+        Ok(dir) => dir,
+        Err(e) => return web::Json(json!({
+            "error": e.to_string()
+        }))
+    };
+    
+    // Without a page_id the whole workspace is exported
+    let page_id = params.get("page_id").and_then(|v| v.as_str());
+    
+    match export::export(&notion_client, page_id, &output_dir).await {
+        Ok(report) => web::Json(json!(report)),
+        Err(e) => web::Json(json!({
+            "error": e.to_string()
+        }))
+    }
+}
+
+async fn handle_import_markdown(
+    notion_client: NotionSession,
+    roots: web::Data<Arc<FileRoots>>,
+    params: web::Json<Value>,
+) -> impl Responder {
+    let source_dir = match params.get("source_dir").and_then(|v| v.as_str()) {
+        Some(dir) => dir,
+        None => return web::Json(json!({
+            "error": "Missing 'source_dir' parameter"
+        }))
+    };
+    // Callers only name a directory under MCP_IMPORT_DIR
+    let source_dir = match roots.import.resolve(source_dir) {
+        Ok(dir) => dir,
+        Err(e) => return web::Json(json!({
+            "error": e.to_string()
+        }))
+    };
+    
+    let parent_id = match params.get("parent_id").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => return web::Json(json!({
+            "error": "Missing 'parent_id' parameter"
+        }))
+    };
+    
+    match import::import_markdown(&notion_client, &source_dir, parent_id).await {
+        Ok(report) => web::Json(json!(report)),
+        Err(e) => web::Json(json!({
+            "error": e.to_string()
+        }))
+    }
+}
+
+async fn handle_bulk_import(
+    notion_client: NotionSession,
+    guard: web::Data<Arc<WriteGuard>>,
+    roots: web::Data<Arc<FileRoots>>,
+    principal: Option<web::ReqData<Principal>>,
+    params: web::Json<Value>,
+) -> impl Responder {
+    let database_id = match params.get("database_id").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => return web::Json(json!({
+            "error": "Missing 'database_id' parameter"
+        }))
+    };
+    
+    // Rows come inline or from a CSV / JSON Lines file under MCP_IMPORT_DIR
+    let rows: Vec<Value> = match (params.get("rows").and_then(|v| v.as_array()), params.get("path").and_then(|v| v.as_str())) {
+        (Some(rows), _) => rows.clone(),
+        (None, Some(path)) => match roots.import.resolve(path).and_then(|path| bulk_import::read_rows(&path)) {
+            Ok(rows) => rows.into_iter().map(Value::Object).collect(),
+            Err(e) => return web::Json(json!({
+                "error": e.to_string()
+            }))
+        },
+        (None, None) => return web::Json(json!({
+            "error": "Missing 'rows' or 'path' parameter"
+        }))
+    };
+    
+    let options = BulkImportOptions {
+        key_property: params.get("key_property").and_then(|v| v.as_str()).map(|s| s.to_string()),
+        mapping: params.get("mapping")
+            .and_then(|v| v.as_object())
+            .map(|m| m.iter()
+                .filter_map(|(column, property)| property.as_str().map(|p| (column.clone(), p.to_string())))
+                .collect())
+            .unwrap_or_default(),
+        concurrency: params.get("concurrency")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as usize)
+            .unwrap_or(bulk_import::DEFAULT_CONCURRENCY),
+        dry_run: params.get("dry_run").and_then(|v| v.as_bool()).unwrap_or(false),
+    };
+    
+    // Only an upsert key overwrites existing rows; plain imports just add pages
+    if let Some(key) = options.key_property.as_deref().filter(|_| guard.confirms_destructive() && !options.dry_run) {
+        let question = format!(
+            "Import {} rows into database {}, overwriting the existing rows with the same '{}'?",
+            rows.len(), database_id, key
+        );
+        if let Err(response) = guard.confirm(principal.as_deref(), "bulk_import", &params, question) {
+            return web::Json(response);
+        }
+    }
+    
+    match bulk_import::bulk_import(&notion_client, database_id, rows, options).await {
+        Ok(report) => web::Json(json!(report)),
+        Err(e) => web::Json(json!({
+            "error": e.to_string()
+        }))
+    }
+}
+
+async fn handle_export_database(
+    notion_client: NotionSession,
+    roots: web::Data<Arc<FileRoots>>,
+    params: web::Json<Value>,
+) -> impl Responder {
+    let database_id = match params.get("database_id").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => return web::Json(json!({
+            "error": "Missing 'database_id' parameter"
+        }))
+    };
+    
+    let columns = params.get("columns")
+        .and_then(|v| v.as_array())
+        .map(|cols| cols.iter().filter_map(|c| c.as_str().map(|s| s.to_string())).collect());
+    let filter = params.get("filter").cloned();
+    // A file is only written under MCP_EXPORT_DIR
+    let output_path = match params.get("output_path").and_then(|v| v.as_str()).map(|path| roots.export.resolve(path)) {
+        Some(Ok(path)) => Some(path),
+        Some(Err(e)) => return web::Json(json!({
+            "error": e.to_string()
+        })),
+        None => None,
+    };
+    let output_path = output_path.as_deref();
+    
+    let format = match params.get("format").and_then(|v| v.as_str()) {
+        Some(name) => match TableFormat::parse(name) {
+            Some(format) => format,
+            None => return web::Json(json!({
+                "error": format!("Unsupported format '{}', expected csv or jsonl", name)
+            }))
+        },
+        None => output_path.and_then(TableFormat::from_path).unwrap_or(TableFormat::Csv),
+    };
+    
+    let table = match database_export::read_database_table(&notion_client, database_id, columns, filter).await {
+        Ok(table) => table,
+        Err(e) => return web::Json(json!({
+            "error": e.to_string()
+        }))
+    };
+    
+    // Without an output path the rows are returned inline
+    match output_path {
+        Some(path) => match database_export::write_table(&table, path, format) {
+            Ok(_) => web::Json(json!({
+                "output_path": path.display().to_string(),
+                "columns": table.columns,
+                "rows": table.rows.len()
+            })),
+            Err(e) => web::Json(json!({
+                "error": e.to_string()
+            }))
+        },
+        None => web::Json(json!(table)),
+    }
+}
+
+// Tools this caller may use. While the server is read-only the tools that
+// change Notion are left out, as are tools above the caller's scope.
+async fn handle_list_tools(
+    guard: web::Data<Arc<WriteGuard>>,
+    principal: Option<web::ReqData<Principal>>,
+) -> impl Responder {
+    let read_only = guard.is_read_only();
+    let tools: Vec<Value> = auth::routes()
+        .filter(|(route, _)| !(read_only && safety::is_mutating(route)))
+        .filter(|(_, scope)| principal.as_ref().is_none_or(|p| p.scope >= *scope))
+        .map(|(route, scope)| json!({
+            "name": route.trim_start_matches("/api/"),
+            "scope": scope.as_str(),
+            "mutating": safety::is_mutating(route),
+            "dry_run": safety::supports_dry_run(route)
+        }))
+        .collect();
+    web::Json(json!({
+        "read_only": read_only,
+        "tools": tools
+    }))
+}
+
+// Recorded calls to tools that modify Notion, newest first
+async fn handle_query_audit_log(
+    audit_log: web::Data<Option<Arc<AuditLog>>>,
+    params: web::Json<Value>,
+) -> impl Responder {
+    let Some(audit_log) = audit_log.as_ref() else {
+        return web::Json(json!({
+            "error": "The audit log is not enabled; set MCP_AUDIT_LOG"
+        }));
+    };
+    
+    let text = |name: &str| params.get(name).and_then(|v| v.as_str()).map(|s| s.to_string());
+    let query = AuditQuery {
+        since: params.get("since").and_then(|v| v.as_u64()),
+        until: params.get("until").and_then(|v| v.as_u64()),
+        caller: text("caller"),
+        tool: text("tool"),
+        target: text("target"),
+        outcome: text("outcome"),
+        limit: params.get("limit")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as usize)
+            .unwrap_or(audit::DEFAULT_QUERY_LIMIT),
+    };
+    
+    match audit_log.query(&query) {
+        Ok(entries) => web::Json(json!({
+            "count": entries.len(),
+            "entries": entries
+        })),
+        Err(e) => web::Json(json!({
+            "error": e.to_string()
+        }))
+    }
+}
+
+// Callers only see and undo their own operations, except admins
+fn may_undo(principal: &Option<web::ReqData<Principal>>, caller: Option<&str>) -> bool {
+    match principal {
+        Some(principal) => principal.scope >= auth::Scope::Admin || caller == Some(principal.name.as_str()),
+        None => true,
+    }
+}
+
+// Journaled operations that can be undone, newest first
+async fn handle_list_operations(
+    journal: web::Data<Option<Arc<Journal>>>,
+    principal: Option<web::ReqData<Principal>>,
+    params: web::Json<Value>,
+) -> impl Responder {
+    let Some(journal) = journal.as_ref() else {
+        return web::Json(json!({
+            "error": "The undo journal is not enabled; set MCP_UNDO_JOURNAL"
+        }));
+    };
+    
+    let session = params.get("session").and_then(|v| v.as_str());
+    let limit = params.get("limit").and_then(|v| v.as_u64()).unwrap_or(50) as usize;
+    let operations: Vec<Value> = journal.list(session).into_iter()
+        .filter(|o| may_undo(&principal, o.caller.as_deref()))
+        .take(limit)
+        .map(|o| json!({
+            "operation_id": o.id,
+            "timestamp": o.timestamp,
+            "caller": o.caller,
+            "session": o.session,
+            "tool": o.tool,
+            "change": o.change,
+            "undone": o.undone
+        }))
+        .collect();
+    web::Json(json!({
+        "operations": operations
+    }))
+}
+
+// Restore the state captured before an operation, or before every
+// operation of a session (newest first)
+async fn handle_undo(
+    notion_client: NotionSession,
+    journal: web::Data<Option<Arc<Journal>>>,
+    principal: Option<web::ReqData<Principal>>,
+    params: web::Json<Value>,
+) -> impl Responder {
+    let Some(journal) = journal.as_ref() else {
+        return web::Json(json!({
+            "error": "The undo journal is not enabled; set MCP_UNDO_JOURNAL"
+        }));
+    };
+    
+    let operation_id = params.get("operation_id").and_then(|v| v.as_str());
+    let session = params.get("session").and_then(|v| v.as_str());
+    // Undo even when the page was edited after the operation
+    let force = params.get("force").and_then(|v| v.as_bool()).unwrap_or(false);
+    
+    let operations = match journal.select(operation_id, session) {
+        Ok(operations) => operations,
+        Err(e) => return web::Json(json!({
+            "error": e.to_string()
+        }))
+    };
+    
+    let mut results = Vec::new();
+    for operation in &operations {
+        if !may_undo(&principal, operation.caller.as_deref()) {
+            results.push(json!({
+                "operation_id": operation.id,
+                "tool": operation.tool,
+                "status": "failed",
+                "message": "The operation belongs to another caller"
+            }));
+            continue;
+        }
+        // The operation must be reversed with the same workspace and account
+        if operation.partition != notion_client.partition() {
+            results.push(json!({
+                "operation_id": operation.id,
+                "tool": operation.tool,
+                "status": "failed",
+                "message": format!("The operation was made through '{}'; undo it from there", operation.partition)
+            }));
+            continue;
+        }
+        results.push(json!(journal.undo(&notion_client, operation, force).await));
+    }
+    
+    let count = |status: &str| results.iter().filter(|r| r["status"] == status).count();
+    web::Json(json!({
+        "restored": count("restored"),
+        "conflicts": count("conflict"),
+        "failed": count("failed"),
+        "results": results
+    }))
+}
+
+// Named Notion connections the caller can pass as `workspace`
+async fn handle_list_workspaces(
+    accounts: web::Data<Arc<Accounts>>,
+    principal: Option<web::ReqData<Principal>>,
+) -> impl Responder {
+    web::Json(accounts.describe(principal.as_deref()))
+}
+
+// Per-user Notion connections and the caller's name, or the status and
+// message to answer with
+fn notion_oauth_for<'a>(accounts: &'a Accounts, principal: &Option<web::ReqData<Principal>>) -> Result<(&'a NotionOAuth, String), (StatusCode, &'static str)> {
+    let Some(oauth) = accounts.oauth() else {
+        return Err((StatusCode::OK, "This server uses a shared Notion integration; per-user connections are not enabled"));
+    };
+    match principal {
+        Some(principal) => Ok((oauth, principal.name.clone())),
+        None => Err((StatusCode::UNAUTHORIZED, "Connecting a Notion account requires an authenticated caller")),
+    }
+}
+
+// Start connecting the caller's Notion account: returns the link to open
+async fn handle_connect_notion(
+    accounts: web::Data<Arc<Accounts>>,
+    principal: Option<web::ReqData<Principal>>,
+) -> HttpResponse {
+    let (oauth, user) = match notion_oauth_for(&accounts, &principal) {
+        Ok(found) => found,
+        Err((status, message)) => return HttpResponse::build(status).json(json!({
+            "error": message
+        })),
+    };
+    match oauth.authorize_url(&user) {
+        Ok(url) => HttpResponse::Ok().json(json!({
+            "authorize_url": url,
+            "expires_in": 600,
+            "connected": oauth.connection(&user)
+        })),
+        Err(e) => HttpResponse::Ok().json(json!({
+            "error": e.to_string()
+        })),
+    }
+}
+
+async fn handle_notion_connection(
+    accounts: web::Data<Arc<Accounts>>,
+    principal: Option<web::ReqData<Principal>>,
+) -> HttpResponse {
+    let (oauth, user) = match notion_oauth_for(&accounts, &principal) {
+        Ok(found) => found,
+        Err((status, message)) => return HttpResponse::build(status).json(json!({
+            "error": message
+        })),
+    };
+    HttpResponse::Ok().json(json!({
+        "user": user,
+        "connected": oauth.connection(&user)
+    }))
+}
+
+async fn handle_disconnect_notion(
+    accounts: web::Data<Arc<Accounts>>,
+    principal: Option<web::ReqData<Principal>>,
+) -> HttpResponse {
+    let (oauth, user) = match notion_oauth_for(&accounts, &principal) {
+        Ok(found) => found,
+        Err((status, message)) => return HttpResponse::build(status).json(json!({
+            "error": message
+        })),
+    };
+    match oauth.disconnect(&user) {
+        Ok(removed) => HttpResponse::Ok().json(json!({
+            "user": user,
+            "disconnected": removed
+        })),
+        Err(e) => HttpResponse::Ok().json(json!({
+            "error": e.to_string()
+        })),
+    }
+}
+
+// GET /oauth/notion/callback: Notion sends the browser here after the user
+// approves (or refuses) access
+async fn handle_notion_callback(
+    accounts: web::Data<Arc<Accounts>>,
+    query: web::Query<HashMap<String, String>>,
+) -> HttpResponse {
+    let page = |status: StatusCode, message: &str| HttpResponse::build(status)
+        .content_type("text/plain; charset=utf-8")
+        .body(message.to_string());
+    
+    let Some(oauth) = accounts.oauth() else {
+        return page(StatusCode::NOT_FOUND, "Notion OAuth is not enabled on this server.");
+    };
+    if let Some(error) = query.get("error") {
+        return page(StatusCode::BAD_REQUEST, &format!("Notion did not grant access: {}", error));
+    }
+    let (Some(code), Some(state)) = (query.get("code"), query.get("state")) else {
+        return page(StatusCode::BAD_REQUEST, "Missing 'code' or 'state' parameter.");
+    };
+    
+    match oauth.complete(code, state).await {
+        Ok(user) => page(StatusCode::OK, &format!("Notion account connected for '{}'. You can close this window.", user)),
+        Err(e) => {
+            warn!("Notion OAuth callback failed: {}", e);
+            page(StatusCode::BAD_REQUEST, &e.to_string())
+        }
+    }
+}
+
+// GET /.well-known/oauth-protected-resource: metadata telling OAuth clients
+// which authorization servers issue tokens for this server
+async fn handle_resource_metadata(auth: web::Data<Arc<AuthConfig>>) -> HttpResponse {
+    match auth.oauth() {
+        Some(oauth) => HttpResponse::Ok().json(oauth.metadata()),
+        None => HttpResponse::NotFound().json(json!({ "error": "OAuth is not configured" })),
+    }
+}
+
+// HTTP-level settings of the server
+pub struct ServerConfig {
+    pub port: u16,
+    pub auth: Arc<AuthConfig>,
+    // Browser origins allowed to call the API; none by default
+    pub cors_origins: Vec<String>,
+    pub policies: Arc<PolicySet>,
+    pub write_guard: Arc<WriteGuard>,
+    pub audit_log: Option<Arc<AuditLog>>,
+    pub journal: Option<Arc<Journal>>,
+    // Server directories the export, import and upload tools may use
+    pub file_roots: Arc<FileRoots>,
+}
+
+pub async fn run_notion_mcp_server(
+    accounts: Arc<Accounts>,
+    file_proxy: Arc<FileProxy>,
+    config: ServerConfig,
+) -> std::io::Result<()> {
+    info!("Starting HTTP server on port {}", config.port);
+    if let Some(oauth) = config.auth.oauth() {
+        info!("OAuth enabled for resource {} (metadata at {})", oauth.resource, oauth.metadata_url());
+    }
+    if !config.auth.key_names().is_empty() {
+        info!("API key authentication enabled for: {}", config.auth.key_names().join(", "));
+    }
+    if !config.auth.is_enabled() {
+        warn!("No MCP_API_KEYS configured: every route is open to local clients");
+    }
+    
+    if accounts.oauth().is_some() {
+        info!("Notion OAuth enabled: each caller uses their own Notion connection");
+    }
+    let accounts_data = web::Data::new(accounts);
+    let file_proxy_data = web::Data::new(file_proxy);
+    if !config.policies.names().is_empty() {
+        info!("Access policies loaded for: {}", config.policies.names().join(", "));
+    }
+    let auth_data = web::Data::new(config.auth);
+    let policies_data = web::Data::new(config.policies);
+    if config.write_guard.is_read_only() {
+        info!("Read-only mode: tools that modify Notion are disabled");
+    } else if config.write_guard.confirms_destructive() {
+        info!("Destructive operations require confirmation");
+    }
+    let write_guard_data = web::Data::new(config.write_guard);
+    if config.audit_log.is_some() {
+        info!("Audit log enabled for tools that modify Notion");
+    }
+    let audit_log_data = web::Data::new(config.audit_log);
+    if config.journal.is_some() {
+        info!("Undo journal enabled");
+    }
+    let journal_data = web::Data::new(config.journal);
+    let file_roots_data = web::Data::new(config.file_roots);
+    let cors_origins = config.cors_origins;
+    
+    HttpServer::new(move || {
+        let mut cors = Cors::default()
+            .allowed_methods(vec!["GET", "POST"])
+            .allowed_headers(vec![header::AUTHORIZATION, header::CONTENT_TYPE, header::HeaderName::from_static("x-api-key")])
+            .expose_headers(vec![header::WWW_AUTHENTICATE]);
+        for origin in &cors_origins {
+            cors = cors.allowed_origin(origin);
+        }
         
+        // CORS is outermost so preflight requests are answered without a key
         App::new()
+            .wrap(middleware::from_fn(safety::reject_writes))
+            .wrap(middleware::from_fn(audit::record))
+            .wrap(middleware::from_fn(workspaces::select_workspace))
+            .wrap(middleware::from_fn(auth::require_api_key))
             .wrap(cors)
-            .app_data(notion_client_data.clone())
+            .app_data(auth_data.clone())
+            .app_data(policies_data.clone())
+            .app_data(write_guard_data.clone())
+            .app_data(audit_log_data.clone())
+            .app_data(journal_data.clone())
+            .app_data(file_roots_data.clone())
+            .app_data(accounts_data.clone())
+            .app_data(file_proxy_data.clone())
+            .route(oauth::METADATA_PATH, web::get().to(handle_resource_metadata))
+            .route(&format!("{}/{{path:.*}}", oauth::METADATA_PATH), web::get().to(handle_resource_metadata))
+            .route(notion_oauth::CALLBACK_PATH, web::get().to(handle_notion_callback))
+            .route("/files/{page_id}/{property}/{index}", web::get().to(handle_file))
             .route("/api/search", web::post().to(handle_search))
             .route("/api/get_page", web::post().to(handle_get_page))
             .route("/api/get_page_content", web::post().to(handle_get_page_content))
+            .route("/api/get_page_chunks", web::post().to(handle_get_page_chunks))
             .route("/api/query_database", web::post().to(handle_query_database))
+            .route("/api/full_text_search", web::post().to(handle_full_text_search))
+            .route("/api/index_pages", web::post().to(handle_index_pages))
+            .route("/api/export", web::post().to(handle_export))
+            .route("/api/import_markdown", web::post().to(handle_import_markdown))
+            .route("/api/bulk_import", web::post().to(handle_bulk_import))
+            .route("/api/export_database", web::post().to(handle_export_database))
             .route("/api/create_page", web::post().to(handle_create_page))
             .route("/api/update_page", web::post().to(handle_update_page))
+            .route("/api/archive_page", web::post().to(handle_archive_page))
+            .route("/api/duplicate_page", web::post().to(handle_duplicate_page))
+            .route("/api/move_page", web::post().to(handle_move_page))
+            .route("/api/list_comments", web::post().to(handle_list_comments))
+            .route("/api/add_comment", web::post().to(handle_add_comment))
+            .route("/api/reply_to_comment", web::post().to(handle_reply_to_comment))
+            .route("/api/list_users", web::post().to(handle_list_users))
+            .route("/api/get_user", web::post().to(handle_get_user))
+            .route("/api/get_bot_user", web::post().to(handle_get_bot_user))
+            .route("/api/list_workspaces", web::post().to(handle_list_workspaces))
+            .route("/api/list_tools", web::post().to(handle_list_tools))
+            .route("/api/connect_notion", web::post().to(handle_connect_notion))
+            .route("/api/notion_connection", web::post().to(handle_notion_connection))
+            .route("/api/disconnect_notion", web::post().to(handle_disconnect_notion))
+            .route("/api/query_audit_log", web::post().to(handle_query_audit_log))
+            .route("/api/list_operations", web::post().to(handle_list_operations))
+            .route("/api/undo", web::post().to(handle_undo))
+            .service(web::resource("/api/upload_file")
+                .app_data(web::JsonConfig::default().limit(uploads::MAX_INLINE_REQUEST_BYTES))
+                .route(web::post().to(handle_upload_file)))
     })
-    .bind(("127.0.0.1", port))?
+    .bind(("127.0.0.1", config.port))?
     .run()
     .await
 }
@@ -0,0 +1,416 @@
+use crate::error::{NotionMcpError, NotionResult};
+use crate::notion::NotionClient;
+use log::{debug, info};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::borrow::Cow;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+// Files up to this size are sent in a single request
+pub const SINGLE_PART_LIMIT: usize = 20 * 1024 * 1024;
+// Largest JSON body accepted by the upload tool, enough for a single-part
+// file sent as base64; larger files must be given by path
+pub const MAX_INLINE_REQUEST_BYTES: usize = SINGLE_PART_LIMIT * 4 / 3 + 64 * 1024;
+// Size of each part of a multi-part upload (Notion accepts 5 to 20 MB)
+const PART_SIZE: usize = 10 * 1024 * 1024;
+// Largest file Notion accepts on any plan; workspaces may allow less
+const MAX_FILE_SIZE: usize = 5 * 1024 * 1024 * 1024;
+
+// MIME types accepted by the Notion file upload API
+const SUPPORTED_TYPES: &[&str] = &[
+    // Audio
+    "audio/aac", "audio/midi", "audio/mpeg", "audio/mp4", "audio/ogg", "audio/wav", "audio/webm",
+    "audio/x-ms-wma", "audio/x-m4a",
+    // Documents
+    "application/json", "application/pdf", "text/plain", "text/csv", "text/markdown",
+    "application/msword",
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    "application/vnd.ms-excel",
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    "application/vnd.ms-powerpoint",
+    "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    // Images
+    "image/gif", "image/heic", "image/vnd.microsoft.icon", "image/x-icon", "image/jpeg", "image/png",
+    "image/svg+xml", "image/tiff", "image/webp",
+    // Video
+    "video/x-msvideo", "video/x-flv", "video/mp4", "video/quicktime", "video/webm", "video/x-ms-wmv",
+    "video/mpeg",
+];
+
+// Where the bytes of an upload come from
+#[derive(Debug)]
+enum Contents {
+    Bytes(Vec<u8>),
+    // Read one part at a time, so large files never sit in memory
+    File(PathBuf),
+}
+
+// A file ready to be uploaded
+#[derive(Debug)]
+pub struct UploadFile {
+    pub filename: String,
+    pub content_type: String,
+    contents: Contents,
+    size: usize,
+}
+
+impl UploadFile {
+    // A local file, guessing its MIME type from the extension
+    pub fn from_path(path: &Path, content_type: Option<&str>) -> NotionResult<Self> {
+        let filename = path.file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .ok_or_else(|| NotionMcpError::InvalidParams(format!("{} is not a file", path.display())))?;
+        let metadata = std::fs::metadata(path)?;
+        if !metadata.is_file() {
+            return Err(NotionMcpError::InvalidParams(format!("{} is not a file", path.display())));
+        }
+        // Refuse anything Notion could never accept
+        if metadata.len() > MAX_FILE_SIZE as u64 {
+            return Err(NotionMcpError::InvalidParams(format!("{} is larger than Notion's 5 GB limit", filename)));
+        }
+        Self::new(filename, Contents::File(path.to_path_buf()), metadata.len() as usize, content_type)
+    }
+
+    // Bytes sent by a client, named so the type can be detected
+    pub fn from_bytes(filename: String, data: Vec<u8>, content_type: Option<&str>) -> NotionResult<Self> {
+        let size = data.len();
+        Self::new(filename, Contents::Bytes(data), size, content_type)
+    }
+
+    fn new(filename: String, contents: Contents, size: usize, content_type: Option<&str>) -> NotionResult<Self> {
+        let guessed: Vec<String> = mime_guess::from_path(&filename).iter().map(|m| m.essence_str().to_string()).collect();
+
+        let content_type = match content_type {
+            Some(given) => {
+                let given = given.trim().to_lowercase();
+                // Notion rejects uploads whose extension does not match the type
+                if !guessed.is_empty() && !guessed.contains(&given) {
+                    return Err(NotionMcpError::InvalidParams(format!(
+                        "Content type {} does not match the extension of {} ({})", given, filename, guessed.join(", ")
+                    )));
+                }
+                given
+            }
+            None => guessed.iter()
+                .find(|m| SUPPORTED_TYPES.contains(&m.as_str()))
+                .or(guessed.first())
+                .cloned()
+                .ok_or_else(|| NotionMcpError::InvalidParams(format!(
+                    "Cannot detect the type of {}; pass 'content_type'", filename
+                )))?,
+        };
+
+        if !SUPPORTED_TYPES.contains(&content_type.as_str()) {
+            return Err(NotionMcpError::InvalidParams(format!("Notion does not accept files of type {}", content_type)));
+        }
+
+        Ok(Self { filename, content_type, contents, size })
+    }
+
+    // `len` bytes starting at `offset`
+    async fn read(&self, offset: usize, len: usize) -> NotionResult<Cow<'_, [u8]>> {
+        match &self.contents {
+            Contents::Bytes(data) => Ok(Cow::Borrowed(&data[offset..offset + len])),
+            Contents::File(path) => {
+                let mut file = tokio::fs::File::open(path).await?;
+                file.seek(SeekFrom::Start(offset as u64)).await?;
+                let mut data = vec![0; len];
+                file.read_exact(&mut data).await?;
+                Ok(Cow::Owned(data))
+            }
+        }
+    }
+
+    // Block type that displays this file
+    pub fn block_type(&self) -> &'static str {
+        if self.content_type == "application/pdf" {
+            return "pdf";
+        }
+        match self.content_type.split('/').next().unwrap_or("") {
+            "image" => "image",
+            "video" => "video",
+            "audio" => "audio",
+            _ => "file",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadReport {
+    pub file_upload_id: String,
+    pub filename: String,
+    pub content_type: String,
+    pub size: usize,
+    pub parts: usize,
+    pub status: String,
+}
+
+impl UploadReport {
+    // Reference to the upload in a files property value
+    pub fn file_property_item(&self) -> Value {
+        json!({
+            "name": self.filename,
+            "type": "file_upload",
+            "file_upload": { "id": self.file_upload_id }
+        })
+    }
+
+    // A block showing the uploaded file
+    pub fn block(&self, block_type: &str, caption: Option<&str>) -> Value {
+        let mut content = json!({
+            "type": "file_upload",
+            "file_upload": { "id": self.file_upload_id }
+        });
+        if let Some(caption) = caption {
+            content["caption"] = crate::markdown::text_to_rich_text(caption);
+        }
+        json!({
+            "object": "block",
+            "type": block_type,
+            block_type: content
+        })
+    }
+}
+
+// A file already in a files property, in a form Notion accepts on a write.
+// Notion-hosted files cannot be written back by their signed URL, so they
+// are downloaded and uploaded again.
+async fn writable_file(client: &NotionClient, file: &Value) -> NotionResult<Value> {
+    match file["type"].as_str() {
+        Some("file") => {
+            let url = crate::markdown::file_url(file)
+                .ok_or_else(|| NotionMcpError::JsonParse("Notion-hosted file has no URL".to_string()))?;
+            let from_url = url.split('?').next().unwrap_or("").rsplit('/').next().unwrap_or("");
+            let name = file["name"].as_str().filter(|n| !n.is_empty()).unwrap_or(from_url).to_string();
+            let data = client.download(url).await?;
+            let copy = UploadFile::from_bytes(name.clone(), data.clone(), None)
+                .or_else(|_| UploadFile::from_bytes(from_url.to_string(), data, None))?;
+            let mut item = upload_file(client, &copy).await?.file_property_item();
+            item["name"] = json!(name);
+            Ok(item)
+        }
+        // External links and pending uploads are written back as they are
+        _ => {
+            let mut item = file.clone();
+            if let Some(item) = item.as_object_mut() {
+                item.retain(|key, _| matches!(key.as_str(), "name" | "type" | "external" | "file_upload"));
+            }
+            Ok(item)
+        }
+    }
+}
+
+// Add an upload to a files property, keeping the files already there, since
+// a write replaces the whole list
+pub async fn attach_to_property(client: &NotionClient, page_id: &str, property: &str, upload: &UploadReport) -> NotionResult<Value> {
+    let page = client.get_page_object(page_id).await?;
+    let current = &page["properties"][property];
+    let mut files = Vec::new();
+    if !current.is_null() {
+        let existing = current["files"].as_array().ok_or_else(|| NotionMcpError::InvalidParams(format!(
+            "'{}' is not a files property", property
+        )))?;
+        for file in existing {
+            files.push(writable_file(client, file).await?);
+        }
+    }
+    files.push(upload.file_property_item());
+    client.update_page(page_id, json!({ property: { "files": files } })).await
+}
+
+// Largest upload the workspace accepts, as reported for the bot user
+async fn workspace_limit(client: &NotionClient) -> usize {
+    match client.get_bot_user().await {
+        Ok(bot) => bot["bot"]["workspace_limits"]["max_file_upload_size_in_bytes"].as_u64()
+            .map(|limit| limit as usize)
+            .unwrap_or(MAX_FILE_SIZE),
+        Err(e) => {
+            debug!("Could not read the workspace upload limit: {}", e);
+            MAX_FILE_SIZE
+        }
+    }
+}
+
+// Upload a file to Notion, in one request or in parts for large files. The
+// returned ID can be attached to files properties and file blocks.
+pub async fn upload_file(client: &NotionClient, file: &UploadFile) -> NotionResult<UploadReport> {
+    let size = file.size;
+    if size == 0 {
+        return Err(NotionMcpError::InvalidParams(format!("{} is empty", file.filename)));
+    }
+    let limit = workspace_limit(client).await;
+    if size > limit {
+        return Err(NotionMcpError::InvalidParams(format!(
+            "{} is {} bytes; this workspace accepts uploads up to {} bytes", file.filename, size, limit
+        )));
+    }
+
+    let parts = if size > SINGLE_PART_LIMIT { size.div_ceil(PART_SIZE) } else { 1 };
+    let mut payload = json!({
+        "filename": file.filename,
+        "content_type": file.content_type
+    });
+    if parts > 1 {
+        payload["mode"] = json!("multi_part");
+        payload["number_of_parts"] = json!(parts);
+    } else {
+        payload["mode"] = json!("single_part");
+    }
+
+    let upload = client.create_file_upload(payload).await?;
+    let upload_id = upload["id"].as_str()
+        .ok_or_else(|| NotionMcpError::JsonParse("File upload has no ID".to_string()))?
+        .to_string();
+
+    let result = if parts > 1 {
+        for index in 0..parts {
+            debug!("Sending part {}/{} of {}", index + 1, parts, file.filename);
+            let offset = index * PART_SIZE;
+            let chunk = file.read(offset, PART_SIZE.min(size - offset)).await?;
+            client.send_file_upload(&upload_id, &chunk, &file.filename, &file.content_type, Some(index + 1)).await?;
+        }
+        client.complete_file_upload(&upload_id).await?
+    } else {
+        let data = file.read(0, size).await?;
+        client.send_file_upload(&upload_id, &data, &file.filename, &file.content_type, None).await?
+    };
+
+    info!("Uploaded {} ({} bytes, {} parts)", file.filename, size, parts);
+    Ok(UploadReport {
+        file_upload_id: upload_id,
+        filename: file.filename.clone(),
+        content_type: file.content_type.clone(),
+        size,
+        parts,
+        status: result["status"].as_str().unwrap_or("").to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_notion::FakeNotion;
+    use std::sync::{Arc, OnceLock};
+
+    const PAGE: &str = "1a2b3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d";
+    const UPLOAD: &str = "ffffffff-0000-4000-8000-000000000001";
+
+    fn report(filename: &str) -> UploadReport {
+        UploadReport {
+            file_upload_id: UPLOAD.to_string(),
+            filename: filename.to_string(),
+            content_type: "image/png".to_string(),
+            size: 3,
+            parts: 1,
+            status: "uploaded".to_string(),
+        }
+    }
+
+    // A workspace accepting uploads of up to 10 bytes, with one page whose
+    // "Files" property holds an external link and a Notion-hosted file
+    fn workspace() -> FakeNotion {
+        let base: Arc<OnceLock<String>> = Arc::default();
+        let url = base.clone();
+        let notion = FakeNotion::start(move |method, path, _| {
+            let page = json!({
+                "object": "page",
+                "id": PAGE,
+                "properties": {
+                    "Files": { "type": "files", "files": [
+                        { "name": "site", "type": "external", "external": { "url": "https://example.com/a" } },
+                        { "name": "notes.txt", "type": "file", "file": {
+                            "url": format!("{}/s3/notes.txt?X-Amz-Signature=abc", url.get().unwrap()),
+                            "expiry_time": "2030-01-01T00:00:00.000Z"
+                        } }
+                    ] },
+                    "Name": { "type": "title", "title": [] }
+                }
+            });
+            match (method, path) {
+                ("GET", "users/me") => Some(json!({ "bot": { "workspace_limits": { "max_file_upload_size_in_bytes": 10 } } })),
+                ("GET", "s3/notes.txt") => Some(json!("hi")),
+                ("POST", "file_uploads") => Some(json!({ "id": "eeeeeeee-0000-4000-8000-000000000002" })),
+                ("POST", _) if path.ends_with("/send") => Some(json!({ "status": "uploaded" })),
+                (_, p) if p == format!("pages/{}", PAGE) => Some(page),
+                _ => None,
+            }
+        });
+        base.set(notion.url.clone()).unwrap();
+        notion
+    }
+
+    #[test]
+    fn detects_the_type_from_the_extension() {
+        let file = UploadFile::from_bytes("photo.PNG".to_string(), vec![1], None).unwrap();
+        assert_eq!(file.content_type, "image/png");
+        assert_eq!(file.block_type(), "image");
+        let file = UploadFile::from_bytes("report.pdf".to_string(), vec![1], Some(" Application/PDF ")).unwrap();
+        assert_eq!(file.block_type(), "pdf");
+    }
+
+    #[test]
+    fn rejects_types_notion_does_not_accept() {
+        assert!(UploadFile::from_bytes("photo.png".to_string(), vec![1], Some("image/jpeg")).is_err());
+        assert!(UploadFile::from_bytes("setup.exe".to_string(), vec![1], None).is_err());
+        assert!(UploadFile::from_bytes("README".to_string(), vec![1], None).is_err());
+    }
+
+    #[actix_web::test]
+    async fn reads_server_files_in_parts() {
+        let dir = std::env::temp_dir().join(format!("notion-mcp-upload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.csv");
+        std::fs::write(&path, "a,b\n1,2\n").unwrap();
+
+        let file = UploadFile::from_path(&path, None).unwrap();
+        assert_eq!(file.content_type, "text/csv");
+        assert_eq!(file.size, 8);
+        assert_eq!(&*file.read(4, 3).await.unwrap(), b"1,2");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn refuses_empty_files_and_files_over_the_workspace_limit() {
+        let notion = workspace();
+        let client = notion.client();
+
+        let empty = UploadFile::from_bytes("a.txt".to_string(), Vec::new(), None).unwrap();
+        assert!(upload_file(&client, &empty).await.is_err());
+        let large = UploadFile::from_bytes("a.txt".to_string(), vec![b'a'; 11], None).unwrap();
+        let error = upload_file(&client, &large).await.unwrap_err().to_string();
+        assert!(error.contains("up to 10 bytes"), "{}", error);
+        assert!(notion.bodies("POST", "file_uploads").is_empty());
+
+        let small = UploadFile::from_bytes("a.txt".to_string(), vec![b'a'; 10], None).unwrap();
+        let report = upload_file(&client, &small).await.unwrap();
+        assert_eq!((report.parts, report.status.as_str()), (1, "uploaded"));
+        notion.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn attaching_keeps_the_files_already_in_the_property() {
+        let notion = workspace();
+        attach_to_property(&notion.client(), PAGE, "Files", &report("photo.png")).await.unwrap();
+
+        let writes = notion.bodies("PATCH", &format!("pages/{}", PAGE));
+        assert_eq!(writes.len(), 1);
+        assert_eq!(writes[0]["properties"]["Files"]["files"], json!([
+            { "name": "site", "type": "external", "external": { "url": "https://example.com/a" } },
+            { "name": "notes.txt", "type": "file_upload", "file_upload": { "id": "eeeeeeee-0000-4000-8000-000000000002" } },
+            { "name": "photo.png", "type": "file_upload", "file_upload": { "id": UPLOAD } }
+        ]));
+        // The hosted file was uploaded again under its own name
+        assert_eq!(notion.bodies("POST", "file_uploads")[0]["filename"], "notes.txt");
+        notion.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn attaching_to_another_kind_of_property_fails() {
+        let notion = workspace();
+        assert!(attach_to_property(&notion.client(), PAGE, "Name", &report("photo.png")).await.is_err());
+        assert!(notion.bodies("PATCH", &format!("pages/{}", PAGE)).is_empty());
+        notion.stop().await;
+    }
+}
@@ -0,0 +1,197 @@
+use crate::error::{NotionMcpError, NotionResult};
+use crate::ids::normalize_id;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// How long the user list is reused before it is fetched again
+const DIRECTORY_TTL: Duration = Duration::from_secs(600);
+
+// Users of the workspace, indexed by ID
+#[derive(Debug, Default)]
+pub struct Directory {
+    users: HashMap<String, Value>,
+}
+
+impl Directory {
+    pub fn from_users(users: Vec<Value>) -> Self {
+        let users = users.into_iter()
+            .filter_map(|user| Some((normalize_id(user["id"].as_str()?).ok()?, user)))
+            .collect();
+        Self { users }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Value> {
+        self.users.get(&normalize_id(id).ok()?)
+    }
+
+    // Find a user by ID, email or display name (case-insensitive). A name
+    // shared by several users is an error rather than a guess.
+    pub fn find(&self, query: &str) -> Result<&Value, String> {
+        let query = query.trim();
+        if let Some(user) = self.get(query) {
+            return Ok(user);
+        }
+        let lower = query.to_lowercase();
+        let email = |u: &Value| u["person"]["email"].as_str().map(|e| e.to_lowercase());
+        let name = |u: &Value| u["name"].as_str().map(|n| n.to_lowercase());
+
+        for field in [&email as &dyn Fn(&Value) -> Option<String>, &name] {
+            let matches: Vec<&Value> = self.users.values()
+                .filter(|u| field(u).is_some_and(|v| v == lower))
+                .collect();
+            match matches[..] {
+                [] => continue,
+                [user] => return Ok(user),
+                _ => {
+                    let mut ids: Vec<&str> = matches.iter().filter_map(|u| u["id"].as_str()).collect();
+                    ids.sort();
+                    return Err(format!(
+                        "'{}' matches {} users ({}); give an email or ID instead", query, matches.len(), ids.join(", ")
+                    ));
+                }
+            }
+        }
+        Err(format!("No user matches '{}'", query))
+    }
+
+    // Replace a partial user object ({"object": "user", "id": ...}) with the
+    // directory entry, so renderers can show names instead of IDs
+    fn fill_user(&self, user: &mut Value) {
+        if user.get("name").is_some() {
+            return;
+        }
+        if let Some(entry) = user["id"].as_str().and_then(|id| self.get(id)) {
+            *user = entry.clone();
+        }
+    }
+
+    // Fill in the names of every person mentioned by a page's properties and
+    // its created_by / last_edited_by fields
+    pub fn resolve_page(&self, page: &mut Value) {
+        for key in ["created_by", "last_edited_by"] {
+            if let Some(user) = page.get_mut(key) {
+                self.fill_user(user);
+            }
+        }
+        if let Some(properties) = page.get_mut("properties") {
+            self.resolve_properties(properties);
+        }
+    }
+
+    pub fn resolve_properties(&self, properties: &mut Value) {
+        let Some(properties) = properties.as_object_mut() else { return };
+        for property in properties.values_mut() {
+            match property["type"].as_str().unwrap_or("") {
+                "people" => {
+                    if let Some(people) = property["people"].as_array_mut() {
+                        people.iter_mut().for_each(|user| self.fill_user(user));
+                    }
+                }
+                "created_by" | "last_edited_by" => {
+                    let key = property["type"].as_str().unwrap_or("").to_string();
+                    self.fill_user(&mut property[&key]);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn user_id(&self, query: &str) -> Result<String, String> {
+        self.find(query)?["id"].as_str()
+            .map(|id| id.to_string())
+            .ok_or_else(|| format!("No user matches '{}'", query))
+    }
+
+    // Resolve people given by name or email in an API properties payload:
+    // {"Owner": {"people": ["Ana", {"email": "bo@example.com"}, {"id": "..."}]}}
+    pub fn resolve_people_payload(&self, properties: &mut Value) -> NotionResult<()> {
+        let Some(properties) = properties.as_object_mut() else { return Ok(()) };
+        for property in properties.values_mut() {
+            let Some(people) = property.get_mut("people").and_then(|p| p.as_array_mut()) else { continue };
+            for person in people.iter_mut() {
+                let query = match person {
+                    Value::String(query) => query.clone(),
+                    Value::Object(user) if !user.contains_key("id") => {
+                        match user.get("email").or_else(|| user.get("name")).and_then(|v| v.as_str()) {
+                            Some(query) => query.to_string(),
+                            None => continue,
+                        }
+                    }
+                    _ => continue,
+                };
+                let id = self.user_id(&query).map_err(NotionMcpError::InvalidParams)?;
+                *person = json!({ "id": id });
+            }
+        }
+        Ok(())
+    }
+}
+
+// Whether a properties payload names people by anything other than an ID
+pub fn payload_needs_directory(properties: &Value) -> bool {
+    properties.as_object().is_some_and(|props| props.values().any(|p| {
+        p["people"].as_array().is_some_and(|people| people.iter().any(|person| {
+            person.is_string() || (person.is_object() && person.get("id").is_none())
+        }))
+    }))
+}
+
+// Compact view of a user for tool responses
+pub fn user_summary(user: &Value) -> Value {
+    json!({
+        "id": user["id"],
+        "type": user["type"],
+        "name": user["name"],
+        "email": user["person"]["email"],
+        "avatar_url": user["avatar_url"]
+    })
+}
+
+// Shared, time-limited cache of the workspace directory
+#[derive(Debug, Default)]
+pub struct DirectoryCache {
+    cached: Mutex<Option<(Instant, Arc<Directory>)>>,
+}
+
+impl DirectoryCache {
+    pub fn get(&self) -> Option<Arc<Directory>> {
+        let cached = self.cached.lock().unwrap();
+        cached.as_ref()
+            .filter(|(loaded_at, _)| loaded_at.elapsed() < DIRECTORY_TTL)
+            .map(|(_, directory)| directory.clone())
+    }
+
+    pub fn store(&self, directory: Directory) {
+        *self.cached.lock().unwrap() = Some((Instant::now(), Arc::new(directory)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directory() -> Directory {
+        Directory::from_users(vec![
+            json!({ "id": "11111111-1111-1111-1111-111111111111", "name": "Ana", "person": { "email": "ana@example.com" } }),
+            json!({ "id": "22222222-2222-2222-2222-222222222222", "name": "Ana", "person": { "email": "ana.b@example.com" } }),
+            json!({ "id": "33333333-3333-3333-3333-333333333333", "name": "Bo", "person": { "email": "bo@example.com" } }),
+        ])
+    }
+
+    #[test]
+    fn finds_users_by_id_email_or_unique_name() {
+        let directory = directory();
+        assert_eq!(directory.find("33333333333333333333333333333333").unwrap()["name"], "Bo");
+        assert_eq!(directory.find("ANA.B@example.com").unwrap()["id"], "22222222-2222-2222-2222-222222222222");
+        assert_eq!(directory.find(" bo ").unwrap()["id"], "33333333-3333-3333-3333-333333333333");
+        assert!(directory.find("Cy").is_err());
+    }
+
+    #[test]
+    fn shared_names_are_ambiguous() {
+        let error = directory().find("ana").unwrap_err();
+        assert!(error.contains("matches 2 users"), "{}", error);
+    }
+}
@@ -0,0 +1,406 @@
+use crate::audit::RequestIds;
+use crate::auth::Principal;
+use crate::error::{NotionMcpError, NotionResult};
+use crate::file_proxy::UrlSigner;
+use crate::journal::{Journal, JournalContext};
+use crate::mirror::Mirror;
+use crate::notion::NotionClient;
+use crate::notion_oauth::NotionOAuth;
+use crate::policy::{Policy, PolicySet};
+use crate::rate_limit::DEFAULT_REQUESTS_PER_SECOND;
+use crate::safety;
+use crate::search_index::SearchIndex;
+use crate::uploads::MAX_INLINE_REQUEST_BYTES;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::PayloadError;
+use actix_web::http::{header, Method};
+use actix_web::middleware::Next;
+use actix_web::web::{Bytes, BytesMut};
+use actix_web::{web, Error, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use futures_util::{stream, Stream, StreamExt};
+use serde_json::{json, Value};
+use std::env;
+use std::future::{ready, Ready};
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::Arc;
+
+// Name of the workspace reached with NOTION_API_KEY
+pub const DEFAULT_WORKSPACE: &str = "default";
+const URI_SCHEME: &str = "notion://";
+
+// A named Notion connection with its own token, endpoint and rate limit
+pub struct Workspace {
+    pub name: String,
+    client: Arc<NotionClient>,
+    search_index: Arc<SearchIndex>,
+    // Only the NOTION_API_KEY workspace is mirrored
+    mirror: Option<Arc<Mirror>>,
+}
+
+// Every workspace configured for the shared integration tokens
+pub struct Workspaces {
+    default: String,
+    entries: Vec<Workspace>,
+}
+
+// Workspace names double as URI hosts and environment variable suffixes
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+impl Workspaces {
+    // The NOTION_API_KEY connection as `default`, plus each workspace named
+    // in NOTION_WORKSPACES, configured with NOTION_WORKSPACE_<NAME>_API_KEY,
+    // _BASE_URL and _RATE_LIMIT
+    pub fn from_env(
+        default_client: Option<Arc<NotionClient>>,
+        search_index: Arc<SearchIndex>,
+        mirror: Option<Arc<Mirror>>,
+        file_proxy_base: Option<&str>,
+        url_signer: Option<Arc<UrlSigner>>,
+    ) -> NotionResult<Self> {
+        let mut entries = Vec::new();
+        if let Some(client) = default_client {
+            entries.push(Workspace { name: DEFAULT_WORKSPACE.to_string(), client, search_index, mirror });
+        }
+
+        let names = env::var("NOTION_WORKSPACES").unwrap_or_default();
+        for name in names.split(',').map(|n| n.trim()).filter(|n| !n.is_empty()) {
+            if !valid_name(name) {
+                return Err(NotionMcpError::InvalidParams(format!(
+                    "Workspace name '{}' may only contain lowercase letters, digits, '-' and '_'", name
+                )));
+            }
+            if entries.iter().any(|w| w.name == name) {
+                return Err(NotionMcpError::InvalidParams(format!("Workspace '{}' is configured twice", name)));
+            }
+            let prefix = format!("NOTION_WORKSPACE_{}", name.to_uppercase().replace('-', "_"));
+            let api_key = env::var(format!("{}_API_KEY", prefix))
+                .map_err(|_| NotionMcpError::InvalidParams(format!("Workspace '{}' needs {}_API_KEY", name, prefix)))?;
+            let rate = match env::var(format!("{}_RATE_LIMIT", prefix)).or_else(|_| env::var("NOTION_RATE_LIMIT")) {
+                Ok(rate) => rate.parse::<f64>()
+                    .map_err(|_| NotionMcpError::InvalidParams(format!("Invalid rate limit for workspace '{}'", name)))?,
+                Err(_) => DEFAULT_REQUESTS_PER_SECOND,
+            };
+
+            // Each token gets its own limiter: Notion counts per integration
+            let mut client = NotionClient::new(api_key)
+                .with_rate_limit(rate)
+                .with_workspace(name.to_string());
+            if let Ok(base_url) = env::var(format!("{}_BASE_URL", prefix)) {
+                client = client.with_base_url(base_url);
+            }
+            if let Some(base) = file_proxy_base {
+                client = client.with_file_proxy(base.to_string()).with_url_signer(url_signer.clone());
+            }
+            entries.push(Workspace {
+                name: name.to_string(),
+                client: Arc::new(client),
+                search_index: Arc::new(SearchIndex::new()),
+                mirror: None,
+            });
+        }
+
+        let default = match env::var("NOTION_DEFAULT_WORKSPACE") {
+            Ok(name) if entries.iter().any(|w| w.name == name) => name,
+            Ok(name) => return Err(NotionMcpError::InvalidParams(format!("NOTION_DEFAULT_WORKSPACE '{}' is not configured", name))),
+            Err(_) => entries.first()
+                .map(|w| w.name.clone())
+                .ok_or_else(|| NotionMcpError::InvalidParams("No Notion workspace is configured".to_string()))?,
+        };
+        Ok(Self { default, entries })
+    }
+
+    pub fn is_multiple(&self) -> bool {
+        self.entries.len() > 1
+    }
+
+    pub fn get(&self, name: Option<&str>) -> NotionResult<&Workspace> {
+        let name = name.unwrap_or(&self.default);
+        self.entries.iter().find(|w| w.name == name).ok_or_else(|| NotionMcpError::InvalidParams(format!(
+            "Unknown workspace '{}'; configured: {}",
+            name,
+            self.entries.iter().map(|w| w.name.as_str()).collect::<Vec<_>>().join(", ")
+        )))
+    }
+
+    pub fn describe(&self) -> Vec<Value> {
+        self.entries.iter().map(|w| json!({
+            "name": w.name,
+            "default": w.name == self.default,
+            "base_url": w.client.base_url(),
+            "uri_prefix": format!("{}{}/", URI_SCHEME, w.name),
+            "mirrored": w.mirror.is_some()
+        })).collect()
+    }
+}
+
+// Where each request's Notion client comes from: the shared integration
+// tokens, or the caller's own connection when Notion OAuth is configured
+pub enum Accounts {
+    Shared(Workspaces),
+    PerUser(Box<NotionOAuth>),
+}
+
+impl Accounts {
+    pub fn oauth(&self) -> Option<&NotionOAuth> {
+        match self {
+            Accounts::PerUser(oauth) => Some(oauth),
+            Accounts::Shared(_) => None,
+        }
+    }
+
+    // Workspaces the caller can choose from
+    pub fn describe(&self, principal: Option<&Principal>) -> Value {
+        match self {
+            Accounts::Shared(workspaces) => json!({ "workspaces": workspaces.describe() }),
+            Accounts::PerUser(oauth) => json!({
+                "workspaces": [],
+                "connection": principal.and_then(|p| oauth.connection(&p.name))
+            }),
+        }
+    }
+
+    fn session(&self, principal: Option<&Principal>, workspace: Option<&str>) -> NotionResult<NotionSession> {
+        match self {
+            Accounts::Shared(workspaces) => {
+                let workspace = workspaces.get(workspace)?;
+                Ok(NotionSession {
+                    client: workspace.client.clone(),
+                    search_index: workspace.search_index.clone(),
+                    mirror: workspace.mirror.clone(),
+                    partition: workspace.name.clone(),
+                })
+            }
+            Accounts::PerUser(oauth) => {
+                if workspace.is_some() {
+                    return Err(NotionMcpError::InvalidParams(
+                        "The 'workspace' argument is not available with per-user Notion connections".to_string()
+                    ));
+                }
+                let user = principal
+                    .ok_or_else(|| NotionMcpError::Authentication("Notion OAuth requires an authenticated caller".to_string()))?;
+                match oauth.client_for(&user.name)? {
+                    Some((client, search_index)) => Ok(NotionSession {
+                        client,
+                        search_index,
+                        mirror: None,
+                        partition: format!("user:{}", user.name),
+                    }),
+                    None => Err(NotionMcpError::Authentication(format!(
+                        "'{}' has not connected a Notion account; call connect_notion first", user.name
+                    ))),
+                }
+            }
+        }
+    }
+}
+
+// The Notion client serving the current request, with the search index and
+// mirror of the same workspace
+pub struct NotionSession {
+    client: Arc<NotionClient>,
+    search_index: Arc<SearchIndex>,
+    mirror: Option<Arc<Mirror>>,
+    partition: String,
+}
+
+impl NotionSession {
+    // Key separating cached data of different workspaces and accounts
+    pub fn partition(&self) -> &str {
+        &self.partition
+    }
+
+    pub fn search_index(&self) -> &Arc<SearchIndex> {
+        &self.search_index
+    }
+
+    pub fn mirror(&self) -> Option<&Arc<Mirror>> {
+        self.mirror.as_ref()
+    }
+
+    // Apply a key's policy. The mirror and the shared index are bypassed,
+    // since they hold everything the token can see.
+    fn restricted(self, policy: Arc<Policy>) -> Self {
+        let partition = format!("{}/policy:{}", self.partition, policy.name);
+        Self {
+            client: Arc::new(self.client.as_ref().clone().with_policy(policy.clone())),
+            search_index: policy.search_index(&partition),
+            mirror: None,
+            partition,
+        }
+    }
+}
+
+impl NotionSession {
+    // Collect Notion's request IDs for the audit log
+    fn recorded(self, request_ids: RequestIds) -> Self {
+        Self {
+            client: Arc::new(self.client.as_ref().clone().with_request_ids(request_ids)),
+            ..self
+        }
+    }
+
+    // Issue signed file links to the caller
+    fn linked_to(self, caller: &str) -> Self {
+        Self {
+            client: Arc::new(self.client.as_ref().clone().with_link_caller(caller.to_string())),
+            ..self
+        }
+    }
+
+    // Capture prior states in the undo journal
+    fn journaled(self, journal: JournalContext) -> Self {
+        Self {
+            client: Arc::new(self.client.as_ref().clone().with_journal(journal)),
+            ..self
+        }
+    }
+}
+
+// Session grouping a caller's operations for undo: the `session` argument
+// or the MCP session header
+fn undo_session(req: &HttpRequest) -> Option<String> {
+    req.extensions().get::<RequestParams>()
+        .and_then(|p| p.0.get("session").and_then(|s| s.as_str()).map(|s| s.to_string()))
+        .or_else(|| req.headers().get("Mcp-Session-Id").and_then(|v| v.to_str().ok()).map(|s| s.to_string()))
+}
+
+impl Deref for NotionSession {
+    type Target = NotionClient;
+
+    fn deref(&self) -> &NotionClient {
+        &self.client
+    }
+}
+
+impl FromRequest for NotionSession {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let Some(accounts) = req.app_data::<web::Data<Arc<Accounts>>>() else {
+            return ready(Err(actix_web::error::ErrorInternalServerError("Notion accounts are not configured")));
+        };
+        let principal = req.extensions().get::<Principal>().cloned();
+        let workspace = req.extensions().get::<RequestedWorkspace>().map(|w| w.0.clone());
+        let policy = req.app_data::<web::Data<Arc<PolicySet>>>()
+            .and_then(|policies| policies.for_principal(principal.as_ref()));
+        let request_ids = req.extensions().get::<RequestIds>().cloned();
+        let session = accounts.session(principal.as_ref(), workspace.as_deref())
+            .map(|session| match policy {
+                Some(policy) => session.restricted(policy),
+                None => session,
+            })
+            .map(|session| match request_ids {
+                Some(request_ids) => session.recorded(request_ids),
+                None => session,
+            })
+            .map(|session| match &principal {
+                Some(principal) if session.client.signs_links() => session.linked_to(&principal.name),
+                _ => session,
+            })
+            .map(|session| {
+                // Undoing is not journaled itself
+                let journal = req.app_data::<web::Data<Option<Arc<Journal>>>>()
+                    .and_then(|journal| journal.as_ref().clone())
+                    .filter(|_| safety::is_mutating(req.path()) && req.path() != "/api/undo");
+                match journal {
+                    Some(journal) => {
+                        let context = JournalContext::new(
+                            journal,
+                            principal.as_ref().map(|p| p.name.clone()),
+                            undo_session(req),
+                            session.partition().to_string(),
+                            req.path().trim_start_matches("/api/").to_string(),
+                        );
+                        session.journaled(context)
+                    }
+                    None => session,
+                }
+            });
+        ready(session.map_err(|e| {
+            let response = match e {
+                NotionMcpError::InvalidParams(_) => HttpResponse::BadRequest(),
+                _ => HttpResponse::Forbidden(),
+            }
+            .json(json!({ "error": e.to_string() }));
+            actix_web::error::InternalError::from_response(e, response).into()
+        }))
+    }
+}
+
+// Workspace named by a request, stored in the request extensions
+#[derive(Debug, Clone)]
+pub struct RequestedWorkspace(pub String);
+
+// Parsed JSON arguments of a tool call, stored in the request extensions
+#[derive(Debug, Clone)]
+pub struct RequestParams(pub Value);
+
+// Workspace host of a `notion://<workspace>/...` URI
+fn uri_workspace(value: &str) -> Option<String> {
+    let rest = value.strip_prefix(URI_SCHEME)?;
+    let name = rest.split('/').next()?;
+    valid_name(name).then(|| name.to_string())
+}
+
+// The `workspace` argument, or the workspace of the first resource URI
+// among the top-level arguments
+fn workspace_param(params: &Value) -> Option<String> {
+    if let Some(name) = params.get("workspace").and_then(|v| v.as_str()) {
+        return Some(name.to_string());
+    }
+    params.as_object()?.values()
+        .flat_map(|v| match v {
+            Value::Array(items) => items.iter().collect(),
+            other => vec![other],
+        })
+        .filter_map(|v| v.as_str())
+        .find_map(uri_workspace)
+}
+
+fn query_workspace(query: &str) -> Option<String> {
+    query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == "workspace")
+        .map(|(_, value)| value.to_string())
+}
+
+// Read the workspace a tool call targets before its handler runs. JSON
+// bodies are buffered, kept as `RequestParams` and handed back unchanged
+// to the handler.
+pub async fn select_workspace(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let mut requested = query_workspace(req.query_string());
+
+    let is_json = req.headers().get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("application/json"));
+    if req.method() == Method::POST && is_json {
+        let mut payload = req.take_payload();
+        let mut body = BytesMut::new();
+        while let Some(chunk) = payload.next().await {
+            body.extend_from_slice(&chunk?);
+            if body.len() > MAX_INLINE_REQUEST_BYTES {
+                return Err(actix_web::error::ErrorPayloadTooLarge("Request body is too large"));
+            }
+        }
+        let body = body.freeze();
+        if let Ok(params) = serde_json::from_slice::<Value>(&body) {
+            requested = requested.or_else(|| workspace_param(&params));
+            req.extensions_mut().insert(RequestParams(params));
+        }
+        let replay: Pin<Box<dyn Stream<Item = Result<Bytes, PayloadError>>>> = Box::pin(stream::once(async move { Ok(body) }));
+        req.set_payload(Payload::from(replay));
+    }
+
+    if let Some(name) = requested {
+        req.extensions_mut().insert(RequestedWorkspace(name));
+    }
+    next.call(req).await
+}